@@ -8,10 +8,10 @@ use rustbee::{api, device::DigiMeshDevice};
 use std::error;
 
 #[cfg(target_os = "linux")]
-static PORT: &'static str = "/dev/ttyUSB0";
+static PORT: &str = "/dev/ttyUSB0";
 
 #[cfg(target_os = "windows")]
-static PORT: &'static str = "COM1";
+static PORT: &str = "COM1";
 
 fn main() -> Result<(), Box<dyn error::Error>> {
     // first create instance of device
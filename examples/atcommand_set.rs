@@ -8,22 +8,22 @@ use rustbee::{api, device::DigiMeshDevice};
 use std::error;
 
 #[cfg(target_os = "linux")]
-static PORT: &'static str = "/dev/ttyUSB0";
+static PORT: &str = "/dev/ttyUSB0";
 
 #[cfg(target_os = "windows")]
-static PORT: &'static str = "COM1";
+static PORT: &str = "COM1";
 
-static NODE_ID: &'static str = "MY_NODE";
+static NODE_ID: &str = "MY_NODE";
 
 fn main() -> Result<(), Box<dyn error::Error>> {
     // first create instance of device
     let mut device = DigiMeshDevice::new(PORT, 9600)?;
 
-    // Construct At command and set node_id of device by supplying valid [u8] but None
-    let _ = api::AtCommandFrame("NI", Some(NODE_ID.as_bytes()));
+    // Set node_id of device; the catalog rejects values NI would not accept
+    device.send_frame(api::AtCmd::Ni.set(NODE_ID.as_bytes())?)?;
 
     // Now query new node_id
-    let new_node_id = api::AtCommandFrame("NI", None);
+    let new_node_id = api::AtCmd::Ni.query()?;
 
     // returns dyn trait RecieveApiFrame
     let response = device.send_frame(new_node_id)?;
@@ -7,10 +7,10 @@ use rustbee::{api, device::DigiMeshDevice};
 use std::error;
 
 #[cfg(target_os = "linux")]
-static PORT: &'static str = "/dev/ttyUSB0";
+static PORT: &str = "/dev/ttyUSB0";
 
 #[cfg(target_os = "windows")]
-static PORT: &'static str = "COM1";
+static PORT: &str = "COM1";
 
 static DEST_ADDR: u64 = 0xabcdef0101020304;
 
@@ -18,25 +18,23 @@ fn main() -> Result<(), Box<dyn error::Error>> {
     // first create instance of device
     let mut device = DigiMeshDevice::new(PORT, 9600)?;
 
-    let set_all_id = api::RemoteAtCommandFrame {
-        dest_addr: api::BROADCAST_ADDR,
-        options: &api::RemoteCommandOptions {
-            apply_changes: true,
-        },
-        atcmd: "ID",
-        cmd_param: Some(b"\x7f\xff"), // change all devices on same ID to a new ID (0x7fff)
-    };
-
-    let _ = device.send_frame(set_all_id)?;
-
-    let get_all_id = api::RemoteAtCommandFrame {
-        dest_addr: DEST_ADDR,
-        options: &api::RemoteCommandOptions {
-            apply_changes: true,
-        },
-        atcmd: "ID",
-        cmd_param: None,
-    };
+    // change all devices on same ID to a new ID (0x7fff)
+    let set_all_id = api::RemoteAtCommandFrame::builder()
+        .broadcast()
+        .command("ID")
+        .param(b"\x7f\xff")
+        .build()?;
+
+    // every node on the network answers a broadcast, collect all of them
+    let responses = device.collect_remote_at(set_all_id)?;
+    for (addr, resp) in &responses {
+        println!("{}: status {}", addr, resp.command_status);
+    }
+
+    let get_all_id = api::RemoteAtCommandFrame::builder()
+        .dest(DEST_ADDR)
+        .command("ID")
+        .build()?;
 
     let response = device.send_frame(get_all_id)?;
 
@@ -4,73 +4,404 @@
 //!
 //!
 
-use bytes::{BufMut, BytesMut};
+use crate::constants;
+use bytes::{BufMut, Bytes, BytesMut};
 use downcast_rs::{impl_downcast, DowncastSync};
-use rand::Rng;
+use lazy_static::lazy_static;
 use serialport::prelude::*;
 use std::convert::TryFrom;
+use std::net::Ipv4Addr;
+use std::sync::{Mutex, PoisonError};
+use thiserror::Error;
 
-pub static BROADCAST_ADDR: u64 = 0xffff;
+#[deprecated(note = "use `constants::BROADCAST` instead")]
+pub static BROADCAST_ADDR: u64 = constants::BROADCAST;
+#[deprecated(note = "use `constants::UNKNOWN_16BIT` instead")]
+pub static ADDR16_UNKNOWN: u16 = constants::UNKNOWN_16BIT;
+#[deprecated(note = "use `constants::COORDINATOR_16BIT` instead")]
+pub static ADDR16_COORDINATOR: u16 = constants::COORDINATOR_16BIT;
 
-static DELIM: u8 = 0x7e;
+/// Digi data endpoint used for explicit addressing on Digi devices
+pub static DIGI_DATA_ENDPOINT: u8 = 0xe8;
+/// Digi application profile id
+pub static DIGI_PROFILE_ID: u16 = 0xc105;
+/// Cluster that echoes any data it recieves back to the sender
+pub static LOOPBACK_CLUSTER_ID: u16 = 0x0012;
 
-#[derive(Debug)]
+const DELIM: u8 = 0x7e;
+/// Marks an escaped byte in API mode 2; the following byte is XORed with 0x20
+pub(crate) const ESCAPE: u8 = 0x7d;
+
+/// Longest frame data (frame type through payload) any firmware sends. Cellular
+/// socket frames are the largest, with up to 1500 bytes of payload.
+pub const MAX_FRAME_DATA: usize = 2048;
+
+/// Longest AT command parameter accepted when building a frame
+pub static MAX_AT_PARAM_LEN: usize = 256;
+
+/// Largest RF payload of 802.15.4 (XBee S1) firmware
+pub static MAX_802154_PAYLOAD: usize = 100;
+
+/// Most relays a source route may list
+pub static MAX_SOURCE_ROUTE_HOPS: usize = 40;
+
+/// 64-bit device address, displayed the way XCTU prints it (`0013A20040XXXXXX`).
+/// The alternate form (`{:#}`) splits it into SH and SL like the label on the
+/// radio (`0013A200-40XXXXXX`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Addr64(pub u64);
+
+impl Addr64 {
+    /// Every node of the network
+    pub const BROADCAST: Addr64 = Addr64(constants::BROADCAST);
+
+    pub fn from_be_bytes(bytes: [u8; 8]) -> Self {
+        Addr64(u64::from_be_bytes(bytes))
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+
+    /// Upper half, as reported by SH
+    pub fn high(self) -> u32 {
+        (self.0 >> 32) as u32
+    }
+
+    /// Lower half, as reported by SL
+    pub fn low(self) -> u32 {
+        self.0 as u32
+    }
+}
+
+impl std::fmt::Display for Addr64 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if f.alternate() {
+            write!(f, "{:08X}-{:08X}", self.high(), self.low())
+        } else {
+            write!(f, "{:016X}", self.0)
+        }
+    }
+}
+
+impl std::str::FromStr for Addr64 {
+    type Err = Error;
+
+    /// Accepts 16 hex digits, optionally split by `-` or `:`
+    fn from_str(s: &str) -> Result<Self> {
+        parse_hex_addr(s, 16).map(Addr64)
+    }
+}
+
+impl From<u64> for Addr64 {
+    fn from(addr: u64) -> Self {
+        Addr64(addr)
+    }
+}
+
+impl From<Addr64> for u64 {
+    fn from(addr: Addr64) -> Self {
+        addr.0
+    }
+}
+
+/// 16-bit network address, displayed as four hex digits (`FFFE`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Addr16(pub u16);
+
+impl Addr16 {
+    /// Network address of a node whose address is not known
+    pub const UNKNOWN: Addr16 = Addr16(constants::UNKNOWN_16BIT);
+
+    pub fn from_be_bytes(bytes: [u8; 2]) -> Self {
+        Addr16(u16::from_be_bytes(bytes))
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 2] {
+        self.0.to_be_bytes()
+    }
+
+    /// Whether this is the placeholder used when the network address is not known
+    pub fn is_unknown(self) -> bool {
+        self == Self::UNKNOWN
+    }
+}
+
+impl std::fmt::Display for Addr16 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04X}", self.0)
+    }
+}
+
+impl std::str::FromStr for Addr16 {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        parse_hex_addr(s, 4).map(|addr| Addr16(addr as u16))
+    }
+}
+
+impl From<u16> for Addr16 {
+    fn from(addr: u16) -> Self {
+        Addr16(addr)
+    }
+}
+
+impl From<Addr16> for u16 {
+    fn from(addr: Addr16) -> Self {
+        addr.0
+    }
+}
+
+fn parse_hex_addr(s: &str, digits: usize) -> Result<u64> {
+    let hex: String = s.chars().filter(|c| *c != '-' && *c != ':').collect();
+    if hex.len() != digits || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidAddress(s.to_string()));
+    }
+    Ok(u64::from_str_radix(&hex, 16).unwrap())
+}
+
+#[derive(Debug, Error)]
 pub enum Error {
+    #[error("{0}")]
     FrameError(String),
+    #[error("{0}")]
     PayloadError(String),
-    IOError(std::io::Error),
-    SerialPortError(serialport::Error),
+    #[error("Payload of {got} bytes exceeds the maximum of {max} bytes")]
+    PayloadTooLarge { max: usize, got: usize },
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    SerialPortError(#[from] serialport::Error),
+    #[error("{0}")]
+    InvalidAtCommand(String),
+    #[error("Invalid address {0:?}")]
+    InvalidAddress(String),
+    #[error("{0}")]
+    InvalidParameter(String),
+    #[error("Timed out waiting for {operation}")]
+    Timeout { operation: &'static str },
+    #[error("Checksum mismatch: expected 0x{expected:02x}, got 0x{actual:02x}")]
+    ChecksumMismatch { expected: u8, actual: u8 },
+    #[error("Expected {expected:?} frame, got frame type 0x{got:02x}")]
+    UnexpectedFrame { expected: FrameId, got: u8 },
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error("AT command {command} returned status {status:?}")]
+    AtCommandStatus {
+        command: String,
+        status: AtCommandStatus,
+    },
+    #[error("Unable to deref trait")]
     DerefError,
 }
 
-impl std::error::Error for Error {}
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Why a received frame could not be decoded, and where. Offsets count from
+/// the start delimiter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodeError {
+    pub frame: FrameId,
+    pub field: &'static str,
+    pub offset: usize,
+    pub kind: DecodeErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeErrorKind {
+    /// The frame, `len` bytes including the checksum, ends before the field does
+    Truncated { len: usize },
+    /// The field holds a value the frame type does not allow
+    Invalid(String),
+}
+
+impl DecodeError {
+    fn truncated(frame: FrameId, field: &'static str, offset: usize, len: usize) -> Error {
+        Error::Decode(DecodeError {
+            frame,
+            field,
+            offset,
+            kind: DecodeErrorKind::Truncated { len },
+        })
+    }
+
+    fn invalid(frame: FrameId, field: &'static str, offset: usize, reason: String) -> Error {
+        Error::Decode(DecodeError {
+            frame,
+            field,
+            offset,
+            kind: DecodeErrorKind::Invalid(reason),
+        })
+    }
+}
 
-impl std::fmt::Display for Error {
+impl std::fmt::Display for DecodeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match *self {
-            Error::FrameError(ref err) => write!(f, "{}", err),
-            Error::PayloadError(ref err) => write!(f, "{}", err),
-            Error::IOError(ref err) => write!(f, "{}", err),
-            Error::SerialPortError(ref err) => write!(f, "{}", err),
-            Error::DerefError => write!(f, "Unable to deref trait"),
+        match self.kind {
+            DecodeErrorKind::Truncated { len } => write!(
+                f,
+                "{:?} frame of {} bytes ends before its {} at offset {}",
+                self.frame, len, self.field, self.offset
+            ),
+            DecodeErrorKind::Invalid(ref reason) => write!(
+                f,
+                "{:?} frame has an invalid {} at offset {}: {}",
+                self.frame, self.field, self.offset, reason
+            ),
         }
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Error::IOError(err)
+impl std::error::Error for DecodeError {}
+
+/// Fails unless `width` bytes of `field` at `offset` fit in `frame` before its checksum
+fn require(
+    frame: &[u8],
+    id: FrameId,
+    field: &'static str,
+    offset: usize,
+    width: usize,
+) -> Result<()> {
+    if frame.len() < offset + width + 1 {
+        return Err(DecodeError::truncated(id, field, offset, frame.len()));
     }
+    Ok(())
 }
 
-impl From<serialport::Error> for Error {
-    fn from(err: serialport::Error) -> Self {
-        Error::SerialPortError(err)
-    }
+/// `RecieveApiFrame::payload` of frames that keep the bytes they were parsed from
+fn kept_payload(payload: &Option<Bytes>) -> Result<Bytes> {
+    payload
+        .clone()
+        .ok_or_else(|| Error::FrameError("Empty payload".to_string()))
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
+/// Checks the frame type, then that every fixed field up to and including
+/// `field` is present, so the parser can index them without panicking
+fn check_fixed(
+    frame: &[u8],
+    expected: FrameId,
+    field: &'static str,
+    offset: usize,
+    width: usize,
+) -> Result<()> {
+    require(frame, expected, "frame type", 3, 1)?;
+    if frame[3] != expected.id() {
+        return Err(Error::UnexpectedFrame {
+            expected,
+            got: frame[3],
+        });
+    }
+    require(frame, expected, field, offset, width)
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FrameId {
+    TxRequest64,
+    TxRequest16,
+    Rx64,
+    Rx16,
+    TxStatus,
     TransmitRequest,
+    ReceivePacket,
     TransmitStatus,
     AtCommand,
+    AtCommandQueue,
     AtCommandResponse,
     RemoteAtCommand,
     RemoteAtCommandResponse,
+    ExplicitAddressing,
+    ExplicitRxIndicator,
+    ModemStatus,
+    ExtendedModemStatus,
+    CreateSourceRoute,
+    RegisterJoiningDevice,
+    RegisterJoiningDeviceStatus,
+    UserDataRelay,
+    UserDataRelayOutput,
+    SocketCreate,
+    SocketCreateResponse,
+    SocketConnect,
+    SocketConnectResponse,
+    SocketClose,
+    SocketCloseResponse,
+    SocketSend,
+    SocketReceive,
+    TxIpv4,
+    RxIpv4,
+    TxSms,
+    RxSms,
+    FileSystemRequest,
+    FileSystemResponse,
+    RemoteFileSystemRequest,
+    RemoteFileSystemResponse,
+    DeviceRequest,
+    DeviceResponse,
+    DeviceResponseStatus,
+    NodeIdentificationIndicator,
+    IoSampleRxIndicator,
+    SensorReadIndicator,
+    RouteRecordIndicator,
+    ManyToOneRouteRequest,
+    OtaUpdateStatus,
+    RouteInformation,
     Null,
 }
 
 impl FrameId {
-    fn id(&self) -> u8 {
+    pub(crate) fn id(&self) -> u8 {
         match *self {
-            FrameId::TransmitRequest => 0x90,
+            FrameId::TxRequest64 => 0x00,
+            FrameId::TxRequest16 => 0x01,
+            FrameId::Rx64 => 0x80,
+            FrameId::Rx16 => 0x81,
+            FrameId::TxStatus => 0x89,
+            FrameId::TransmitRequest => 0x10,
+            FrameId::ReceivePacket => 0x90,
             FrameId::TransmitStatus => 0x8b,
             FrameId::AtCommand => 0x08,
+            FrameId::AtCommandQueue => 0x09,
             FrameId::AtCommandResponse => 0x88,
             FrameId::RemoteAtCommand => 0x17,
             FrameId::RemoteAtCommandResponse => 0x97,
+            FrameId::ExplicitAddressing => 0x11,
+            FrameId::ExplicitRxIndicator => 0x91,
+            FrameId::ModemStatus => 0x8a,
+            FrameId::ExtendedModemStatus => 0x98,
+            FrameId::CreateSourceRoute => 0x21,
+            FrameId::RegisterJoiningDevice => 0x24,
+            FrameId::RegisterJoiningDeviceStatus => 0xa4,
+            FrameId::UserDataRelay => 0x2d,
+            FrameId::UserDataRelayOutput => 0xad,
+            FrameId::SocketCreate => 0x40,
+            FrameId::SocketCreateResponse => 0xc0,
+            FrameId::SocketConnect => 0x42,
+            FrameId::SocketConnectResponse => 0xc2,
+            FrameId::SocketClose => 0x43,
+            FrameId::SocketCloseResponse => 0xc3,
+            FrameId::SocketSend => 0x44,
+            FrameId::SocketReceive => 0xcd,
+            FrameId::TxIpv4 => 0x20,
+            FrameId::RxIpv4 => 0xb0,
+            FrameId::TxSms => 0x1f,
+            FrameId::RxSms => 0x9f,
+            FrameId::FileSystemRequest => 0x3b,
+            FrameId::FileSystemResponse => 0xbb,
+            FrameId::RemoteFileSystemRequest => 0x3c,
+            FrameId::RemoteFileSystemResponse => 0xbc,
+            FrameId::DeviceRequest => 0xb9,
+            FrameId::DeviceResponse => 0x2a,
+            FrameId::DeviceResponseStatus => 0xba,
+            FrameId::NodeIdentificationIndicator => 0x95,
+            FrameId::IoSampleRxIndicator => 0x92,
+            FrameId::SensorReadIndicator => 0x94,
+            FrameId::RouteRecordIndicator => 0xa1,
+            FrameId::ManyToOneRouteRequest => 0xa3,
+            FrameId::OtaUpdateStatus => 0xa0,
+            FrameId::RouteInformation => 0x8d,
             FrameId::Null => 0xff,
         }
     }
@@ -82,16 +413,42 @@ pub trait RecieveApiFrame: std::fmt::Debug + DowncastSync {
         Self: std::marker::Sized;
 
     fn id(&self) -> FrameId;
-    fn summary(&self) {
-        println!("{:#x?}", self);
+    /// Writes a human readable summary of the frame into `w`, so it can be
+    /// routed to a logger instead of stdout
+    fn summary(&self, w: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        write!(w, "{:#x?}", self)
     }
-    fn payload(&self) -> Result<BytesMut>;
+    fn payload(&self) -> Result<Bytes>;
+    /// Regenerates the frame, delimiter through checksum, from the parsed
+    /// fields. Fields the type does not keep, such as 16-bit addresses, are
+    /// written with their "unknown" value, so the result can differ from the
+    /// bytes originally received.
+    fn encode(&self) -> Result<BytesMut>;
 }
 
 impl_downcast!(sync RecieveApiFrame);
 
 pub trait TransmitApiFrame {
     fn gen(&self) -> Result<BytesMut>;
+    /// Like `gen`, but with `frame_id` instead of an allocated id, so the caller
+    /// can correlate the response itself. Id 0 tells the radio not to respond.
+    fn gen_with_frame_id(&self, frame_id: u8) -> Result<BytesMut> {
+        let mut packet = self.gen()?;
+        if packet.len() < 6 {
+            return Err(Error::FrameError(
+                "Frame length does not meet minimum requirements".to_string(),
+            ));
+        }
+        // the id `gen` allocated is not used
+        FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release(packet[4]);
+        let end = packet.len() - 1;
+        packet[4] = frame_id;
+        packet[end] = self.calc_checksum(&packet[..end])?;
+        Ok(packet)
+    }
     fn delim(&self) -> u8 {
         0x7e
     }
@@ -113,11 +470,631 @@ pub trait TransmitApiFrame {
         Ok(0xff - (checksum as u8))
     }
 
+    /// Allocates a frame id and marks it as in flight, so no other frame gets
+    /// it until it is released with `FRAME_ID_ALLOCATOR`
     fn gen_frame_id(&self) -> u8 {
-        let mut rng = rand::thread_rng();
-        let r: u8 = rng.gen();
-        r
+        FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .allocate()
+    }
+}
+
+/// Hands out frame ids sequentially, wrapping around after 255. Id 0 is never
+/// returned since it tells the radio not to send a response, and ids that are
+/// reserved as in flight are skipped so two outstanding requests never share one.
+#[derive(Debug)]
+pub struct FrameIdAllocator {
+    last: u8,
+    in_flight: [bool; 256],
+}
+
+impl FrameIdAllocator {
+    pub fn new() -> Self {
+        Self {
+            last: 0,
+            in_flight: [false; 256],
+        }
+    }
+
+    /// Returns the next id that is not in flight. If every id is in flight the
+    /// scan stops after one full cycle and the previously returned id is
+    /// handed out again, or 255 on a fresh allocator.
+    pub fn next_id(&mut self) -> u8 {
+        for _ in 0..255 {
+            self.last = match self.last.wrapping_add(1) {
+                0 => 1,
+                id => id,
+            };
+            if !self.in_flight[self.last as usize] {
+                break;
+            }
+        }
+        self.last
+    }
+
+    /// Returns the next free id and marks it as in flight
+    pub fn allocate(&mut self) -> u8 {
+        let id = self.next_id();
+        self.reserve(id);
+        id
+    }
+
+    /// Marks `id` as awaiting a response
+    pub fn reserve(&mut self, id: u8) {
+        if id != 0 {
+            self.in_flight[id as usize] = true;
+        }
+    }
+
+    /// Marks `id` as free once its response has been recieved (or given up on)
+    pub fn release(&mut self, id: u8) {
+        self.in_flight[id as usize] = false;
+    }
+
+    pub fn is_in_flight(&self, id: u8) -> bool {
+        self.in_flight[id as usize]
+    }
+}
+
+impl Default for FrameIdAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static! {
+    /// Process wide allocator used by `TransmitApiFrame::gen_frame_id`
+    pub static ref FRAME_ID_ALLOCATOR: Mutex<FrameIdAllocator> =
+        Mutex::new(FrameIdAllocator::new());
+}
+
+/// `read_exact` that reports a serial timeout as `Error::Timeout`
+fn read_exact(ser: &mut dyn SerialPort, buf: &mut [u8], operation: &'static str) -> Result<()> {
+    ser.read_exact(buf).map_err(|err| match err.kind() {
+        std::io::ErrorKind::TimedOut => Error::Timeout { operation },
+        _ => Error::IOError(err),
+    })
+}
+
+/// Serial framing of the radio, set with AP
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiMode {
+    /// AP=1
+    Unescaped,
+    /// AP=2, where 0x7e, 0x7d, 0x11 and 0x13 are escaped after the start
+    /// delimiter so they never appear in the frame as is
+    Escaped,
+}
+
+impl ApiMode {
+    pub fn value(&self) -> u8 {
+        match self {
+            ApiMode::Unescaped => 1,
+            ApiMode::Escaped => 2,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(ApiMode::Unescaped),
+            2 => Some(ApiMode::Escaped),
+            _ => None,
+        }
+    }
+}
+
+fn needs_escape(byte: u8) -> bool {
+    matches!(byte, DELIM | ESCAPE | 0x11 | 0x13)
+}
+
+/// Escapes a complete frame for API mode 2. The start delimiter is left as is.
+pub fn escape_frame(frame: &[u8]) -> BytesMut {
+    let mut escaped = BytesMut::with_capacity(frame.len() * 2);
+    for (pos, byte) in frame.iter().enumerate() {
+        if pos > 0 && needs_escape(*byte) {
+            escaped.put_u8(ESCAPE);
+            escaped.put_u8(byte ^ 0x20);
+        } else {
+            escaped.put_u8(*byte);
+        }
+    }
+    escaped
+}
+
+/// Reads a single API frame, from the start delimiter through the checksum,
+/// using the length field to find the end of the frame
+pub fn read_frame(ser: &mut dyn SerialPort) -> Result<BytesMut> {
+    read_frame_with_mode(ser, ApiMode::Unescaped)
+}
+
+/// Like `read_frame`, but undoes the escaping of API mode 2 when `mode` asks for it.
+/// The returned frame is always unescaped.
+pub fn read_frame_with_mode(ser: &mut dyn SerialPort, mode: ApiMode) -> Result<BytesMut> {
+    read_frame_skipping(ser, mode, &mut BytesMut::new()).map(|(frame, _)| frame)
+}
+
+/// Discards bytes up to and including the next start delimiter, returning how
+/// many bytes came before it
+pub fn skip_to_delimiter(ser: &mut dyn SerialPort) -> Result<usize> {
+    let mut byte = [0u8; 1];
+    let mut skipped = 0;
+    loop {
+        read_exact(ser, &mut byte, "API frame")?;
+        if byte[0] == DELIM {
+            return Ok(skipped);
+        }
+        skipped += 1;
+    }
+}
+
+/// Like `read_frame_with_mode`, also returning how many bytes were skipped
+/// because they did not belong to a frame, e.g. boot messages or line noise.
+///
+/// A start delimiter whose length is impossible or whose checksum fails is
+/// skipped as well, and the bytes read after it are scanned again. Those
+/// bytes are kept in `pending`, still escaped in API mode 2, and read before
+/// anything new from `ser`; whatever a call does not consume is left there
+/// for the next one.
+pub fn read_frame_skipping(
+    ser: &mut dyn SerialPort,
+    mode: ApiMode,
+    pending: &mut BytesMut,
+) -> Result<(BytesMut, usize)> {
+    let mut skipped = 0;
+    loop {
+        match pending.iter().position(|b| *b == DELIM) {
+            Some(start) => {
+                skipped += start;
+                let _ = pending.split_to(start);
+            }
+            None => {
+                skipped += pending.len();
+                pending.clear();
+                skipped += skip_to_delimiter(ser)?;
+                pending.put_u8(DELIM);
+            }
+        }
+
+        let mut reader = PendingReader {
+            ser: &mut *ser,
+            pending: &mut *pending,
+            pos: 1,
+            mode,
+        };
+        let mut header: [u8; 3] = [DELIM, 0, 0];
+        reader.read(&mut header[1..])?;
+        let len = u16::from_be_bytes([header[1], header[2]]) as usize;
+        if len > 0 && len <= MAX_FRAME_DATA {
+            let mut body = vec![0; len + 1]; // frame data + checksum
+            reader.read(&mut body)?;
+            let consumed = reader.pos;
+
+            let mut frame = BytesMut::with_capacity(header.len() + body.len());
+            frame.put(&header[..]);
+            frame.put(&body[..]);
+            if verify_checksum(&frame).is_ok() {
+                let _ = pending.split_to(consumed);
+                return Ok((frame, skipped));
+            }
+        }
+        // not a frame after all, rescan from the byte after the delimiter
+        let _ = pending.split_to(1);
+        skipped += 1;
+    }
+}
+
+/// Reads the bytes of a frame from `pending` first, then from `ser`, keeping
+/// everything read from `ser` in `pending` so it can be scanned again
+struct PendingReader<'a> {
+    ser: &'a mut dyn SerialPort,
+    pending: &'a mut BytesMut,
+    /// Raw bytes of `pending` used so far
+    pos: usize,
+    mode: ApiMode,
+}
+
+impl PendingReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<()> {
+        match self.mode {
+            ApiMode::Unescaped => {
+                let missing = (self.pos + buf.len()).saturating_sub(self.pending.len());
+                if missing > 0 {
+                    let mut more = vec![0; missing];
+                    read_exact(self.ser, &mut more, "API frame")?;
+                    self.pending.extend_from_slice(&more);
+                }
+                buf.copy_from_slice(&self.pending[self.pos..self.pos + buf.len()]);
+                self.pos += buf.len();
+            }
+            ApiMode::Escaped => {
+                for slot in buf.iter_mut() {
+                    let mut byte = self.next()?;
+                    if byte == ESCAPE {
+                        byte = self.next()? ^ 0x20;
+                    }
+                    *slot = byte;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<u8> {
+        if self.pos == self.pending.len() {
+            let mut byte = [0u8; 1];
+            read_exact(self.ser, &mut byte, "API frame")?;
+            self.pending.put_u8(byte[0]);
+        }
+        self.pos += 1;
+        Ok(self.pending[self.pos - 1])
+    }
+}
+
+/// Checks the checksum of a complete frame, delimiter through checksum
+pub fn verify_checksum(frame: &[u8]) -> Result<()> {
+    if frame.len() < 5 {
+        return Err(Error::FrameError(
+            "Frame length does not meet minimum requirements".to_string(),
+        ));
+    }
+    let (data, checksum) = frame[3..].split_at(frame.len() - 4);
+    let sum = data.iter().fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+    if 0xff - sum != checksum[0] {
+        return Err(Error::ChecksumMismatch {
+            expected: 0xff - sum,
+            actual: checksum[0],
+        });
+    }
+    Ok(())
+}
+
+/// Splits a byte stream from any source into API frames. Bytes are fed in
+/// chunks of any size; frames split across chunks are held back until the
+/// rest arrives and bytes outside of a frame are skipped. Only frames with a
+/// valid checksum are returned; a start delimiter whose length is impossible or
+/// whose checksum fails is dropped and the bytes after it are scanned again, so
+/// stray delimiters in line noise do not cost the frames behind them.
+#[derive(Debug, Default)]
+pub struct FrameParser {
+    buffer: BytesMut,
+}
+
+impl FrameParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends `data` and returns every frame it completed, delimiter through checksum
+    pub fn feed(&mut self, data: &[u8]) -> Vec<BytesMut> {
+        self.push(data);
+        let mut frames = Vec::new();
+        while let Some(frame) = self.next_frame() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    /// Appends `data` without taking frames out, for use with `next_frame`
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Removes the next complete frame from the buffered bytes, if there is one
+    pub fn next_frame(&mut self) -> Option<BytesMut> {
+        loop {
+            match self.buffer.iter().position(|b| *b == DELIM) {
+                Some(start) => {
+                    let _ = self.buffer.split_to(start);
+                }
+                None => {
+                    self.buffer.clear();
+                    return None;
+                }
+            }
+            match Self::frame_at(&self.buffer) {
+                Some(Ok(len)) => return Some(self.buffer.split_to(len)),
+                // not a frame after all, rescan from the next delimiter
+                Some(Err(())) => {
+                    let _ = self.buffer.split_to(1);
+                }
+                None => return None,
+            }
+        }
+    }
+
+    /// Length of the frame starting at the delimiter `data` begins with,
+    /// `Err` if it is not a valid frame, `None` if it is incomplete
+    fn frame_at(data: &[u8]) -> Option<std::result::Result<usize, ()>> {
+        if data.len() < 3 {
+            return None;
+        }
+        let len = u16::from_be_bytes([data[1], data[2]]) as usize;
+        if len == 0 || len > MAX_FRAME_DATA {
+            return Some(Err(()));
+        }
+        if data.len() < len + 4 {
+            return None;
+        }
+        Some(
+            verify_checksum(&data[..len + 4])
+                .map(|_| len + 4)
+                .map_err(|_| ()),
+        )
+    }
+
+    /// Bytes of an incomplete frame waiting for the rest to arrive
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Wraps `body` (everything after the frame type) in a complete API frame with
+/// start delimiter, length and checksum. This allows sending frame types the
+/// crate does not model yet.
+pub fn encode_frame(frame_type: u8, body: &[u8]) -> Result<BytesMut> {
+    if body.len() >= 0xffff {
+        return Err(Error::PayloadError(
+            "Frame body exceeds max size".to_string(),
+        ));
+    }
+
+    let mut packet = BytesMut::with_capacity(body.len() + 5);
+    packet.put_u8(DELIM);
+    packet.put_u16((body.len() + 1) as u16);
+    packet.put_u8(frame_type);
+    packet.put(body);
+
+    let sum = packet[3..]
+        .iter()
+        .fold(0u8, |acc, byte| acc.wrapping_add(*byte));
+    packet.put_u8(0xff - sum);
+    Ok(packet)
+}
+
+/// Compile time counterpart of `encode_frame`, used by the `xbee_frame!` macro.
+/// `data` is the frame type followed by the frame body and `N` must be
+/// `data.len() + 4` to make room for the delimiter, length and checksum.
+pub const fn encode_frame_const<const N: usize>(data: &[u8]) -> [u8; N] {
+    assert!(N == data.len() + 4, "frame size must be data length + 4");
+
+    let mut frame = [0u8; N];
+    frame[0] = DELIM;
+    frame[1] = (data.len() >> 8) as u8;
+    frame[2] = (data.len() & 0xff) as u8;
+
+    let mut sum: u8 = 0;
+    let mut i = 0;
+    while i < data.len() {
+        frame[3 + i] = data[i];
+        sum = sum.wrapping_add(data[i]);
+        i += 1;
+    }
+    frame[N - 1] = 0xff - sum;
+    frame
+}
+
+/// Any frame the radio can send to the host
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum XBeeFrame {
+    TransmitStatus(TransmitStatus),
+    ModemStatus(ModemStatus),
+    ExtendedModemStatus(ExtendedModemStatus),
+    ReceivePacket(ReceivePacket),
+    ExplicitRxIndicator(ExplicitRxIndicator),
+    AtCommandResponse(AtCommandResponse),
+    RemoteAtCommandResponse(RemoteAtCommandResponse),
+    RegisterJoiningDeviceStatus(RegisterJoiningDeviceStatus),
+    TxStatus(TxStatus),
+    Rx64(Rx64Packet),
+    Rx16(Rx16Packet),
+    UserDataRelayOutput(UserDataRelayOutput),
+    SocketResponse(SocketResponse),
+    SocketReceive(SocketReceive),
+    RxIpv4(RxIpv4),
+    RxSms(RxSms),
+    FileSystemResponse(FileSystemResponse),
+    DeviceRequest(DeviceRequest),
+    DeviceResponseStatus(DeviceResponseStatus),
+    IoSample(IoSampleRxIndicator),
+    RouteInformation(RouteInformation),
+    SensorRead(SensorReadIndicator),
+    RouteRecord(RouteRecordIndicator),
+    ManyToOneRouteRequest(ManyToOneRouteRequest),
+    OtaUpdateStatus(OtaUpdateStatus),
+    NodeIdentification(NodeIdentificationIndicator),
+    /// Frame type the crate does not model, kept as the complete frame
+    Unknown {
+        frame_type: u8,
+        frame: BytesMut,
+    },
+}
+
+/// Decodes one complete frame, delimiter through checksum, into the matching
+/// `XBeeFrame` variant
+pub fn parse_frame(bytes: &[u8]) -> Result<XBeeFrame> {
+    check_frame(bytes)?;
+
+    let frame = BytesMut::from(bytes);
+    Ok(match frame[3] {
+        0x8b => XBeeFrame::TransmitStatus(TransmitStatus::from_frame(frame)?),
+        0x8a => XBeeFrame::ModemStatus(ModemStatus::from_frame(frame)?),
+        0x98 => XBeeFrame::ExtendedModemStatus(ExtendedModemStatus::from_frame(frame)?),
+        0x90 => XBeeFrame::ReceivePacket(ReceivePacket::from_frame(frame)?),
+        0x91 => XBeeFrame::ExplicitRxIndicator(ExplicitRxIndicator::from_frame(frame)?),
+        0x88 => XBeeFrame::AtCommandResponse(AtCommandResponse::from_frame(frame)?),
+        0x97 => XBeeFrame::RemoteAtCommandResponse(RemoteAtCommandResponse::from_frame(frame)?),
+        0xa4 => {
+            XBeeFrame::RegisterJoiningDeviceStatus(RegisterJoiningDeviceStatus::from_frame(frame)?)
+        }
+        0x89 => XBeeFrame::TxStatus(TxStatus::from_frame(frame)?),
+        0x80 => XBeeFrame::Rx64(Rx64Packet::from_frame(frame)?),
+        0x81 => XBeeFrame::Rx16(Rx16Packet::from_frame(frame)?),
+        0xad => XBeeFrame::UserDataRelayOutput(UserDataRelayOutput::from_frame(frame)?),
+        0xc0 | 0xc2 | 0xc3 => XBeeFrame::SocketResponse(SocketResponse::from_frame(frame)?),
+        0xcd => XBeeFrame::SocketReceive(SocketReceive::from_frame(frame)?),
+        0xb0 => XBeeFrame::RxIpv4(RxIpv4::from_frame(frame)?),
+        0x9f => XBeeFrame::RxSms(RxSms::from_frame(frame)?),
+        0xbb | 0xbc => XBeeFrame::FileSystemResponse(FileSystemResponse::from_frame(frame)?),
+        0xb9 => XBeeFrame::DeviceRequest(DeviceRequest::from_frame(frame)?),
+        0xba => XBeeFrame::DeviceResponseStatus(DeviceResponseStatus::from_frame(frame)?),
+        0x92 => XBeeFrame::IoSample(IoSampleRxIndicator::from_frame(frame)?),
+        0x8d => XBeeFrame::RouteInformation(RouteInformation::from_frame(frame)?),
+        0x94 => XBeeFrame::SensorRead(SensorReadIndicator::from_frame(frame)?),
+        0xa1 => XBeeFrame::RouteRecord(RouteRecordIndicator::from_frame(frame)?),
+        0xa3 => XBeeFrame::ManyToOneRouteRequest(ManyToOneRouteRequest::from_frame(frame)?),
+        0xa0 => XBeeFrame::OtaUpdateStatus(OtaUpdateStatus::from_frame(frame)?),
+        0x95 => XBeeFrame::NodeIdentification(NodeIdentificationIndicator::from_frame(frame)?),
+        frame_type => XBeeFrame::Unknown { frame_type, frame },
+    })
+}
+
+/// Checks that `bytes` hold exactly one frame with a valid checksum
+fn check_frame(bytes: &[u8]) -> Result<()> {
+    if bytes.len() < 5 || bytes[0] != DELIM {
+        return Err(Error::FrameError("Not an API frame".to_string()));
+    }
+    let len = u16::from_be_bytes([bytes[1], bytes[2]]) as usize;
+    if bytes.len() != len + 4 {
+        return Err(Error::FrameError(format!(
+            "Frame length field is {} but {} bytes of frame data follow",
+            len,
+            bytes.len() - 4
+        )));
+    }
+    verify_checksum(bytes)
+}
+
+impl TryFrom<&[u8]> for XBeeFrame {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        parse_frame(bytes)
+    }
+}
+
+/// Decodes captured bytes, e.g. from a log or a socket, holding exactly one
+/// complete frame
+macro_rules! impl_try_from_bytes {
+    ($($frame:ty),* $(,)?) => {
+        $(
+            impl TryFrom<&[u8]> for $frame {
+                type Error = Error;
+
+                fn try_from(bytes: &[u8]) -> Result<Self> {
+                    check_frame(bytes)?;
+                    Self::from_frame(BytesMut::from(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_try_from_bytes!(
+    TransmitStatus,
+    ModemStatus,
+    ExtendedModemStatus,
+    ReceivePacket,
+    ExplicitRxIndicator,
+    AtCommandResponse,
+    RemoteAtCommandResponse,
+    RegisterJoiningDeviceStatus,
+    TxStatus,
+    Rx64Packet,
+    Rx16Packet,
+    UserDataRelayOutput,
+    SocketResponse,
+    SocketReceive,
+    RxIpv4,
+    RxSms,
+    FileSystemResponse,
+    DeviceRequest,
+    DeviceResponseStatus,
+    IoSampleRxIndicator,
+    RouteInformation,
+    SensorReadIndicator,
+    RouteRecordIndicator,
+    ManyToOneRouteRequest,
+    OtaUpdateStatus,
+    NodeIdentificationIndicator,
+);
+
+const fn is_at_code(code: &[u8]) -> bool {
+    code.len() == 2 && code[0].is_ascii_graphic() && code[1].is_ascii_graphic()
+}
+
+/// Two character AT command code checked when it is constructed. Use the
+/// `at!` macro to have malformed codes rejected at compile time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtCode(&'static str);
+
+impl AtCode {
+    /// Panics unless `code` is two printable ASCII characters, which fails
+    /// the build when evaluated in a const context
+    pub const fn new(code: &'static str) -> Self {
+        assert!(
+            is_at_code(code.as_bytes()),
+            "AT command must be exactly two ASCII characters"
+        );
+        AtCode(code)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    /// Frame querying (or executing) the command
+    pub fn query(self) -> AtCommandFrame<'static> {
+        AtCommandFrame(self.0, None)
+    }
+
+    /// Frame setting the command's parameter
+    pub fn set(self, param: &[u8]) -> Result<AtCommandFrame<'_>> {
+        AtCommandFrame::new(self.0, Some(param))
+    }
+}
+
+impl std::fmt::Display for AtCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl TryFrom<&'static str> for AtCode {
+    type Error = Error;
+
+    fn try_from(code: &'static str) -> Result<Self> {
+        validate_at_command(code, None)?;
+        Ok(AtCode(code))
+    }
+}
+
+/// Checks that `cmd` is exactly two ASCII characters and that `param` is not
+/// longer than `MAX_AT_PARAM_LEN`, so malformed commands are caught before
+/// they reach the radio (which silently ignores them)
+pub fn validate_at_command(cmd: &str, param: Option<&[u8]>) -> Result<()> {
+    if !is_at_code(cmd.as_bytes()) {
+        return Err(Error::InvalidAtCommand(format!(
+            "AT command must be exactly two ASCII characters, got {:?}",
+            cmd
+        )));
     }
+    if let Some(param) = param {
+        if param.len() > MAX_AT_PARAM_LEN {
+            return Err(Error::InvalidAtCommand(format!(
+                "Parameter for AT command {} is {} bytes, maximum is {}",
+                cmd,
+                param.len(),
+                MAX_AT_PARAM_LEN
+            )));
+        }
+    }
+    Ok(())
 }
 
 /**
@@ -139,7 +1116,7 @@ pub enum AtCommands<'a> {
 }
 
 impl AtCommands<'_> {
-    pub fn create(&self) -> AtCommand {
+    pub fn create(&self) -> AtCommand<'_> {
         match *self {
             AtCommands::CmdMode(ref state) => match state {
                 true => AtCommand {
@@ -158,7 +1135,7 @@ impl AtCommands<'_> {
                 parameter: param,
                 rcr_len: 10 + 1,
             },
-            AtCommands::AtCmd((ref cmd, ref param)) => AtCommand {
+            AtCommands::AtCmd((cmd, ref param)) => AtCommand {
                 command: cmd,
                 parameter: param,
                 rcr_len: 1,
@@ -178,26 +1155,233 @@ impl RecieveApiFrame for NullRecieve {
         Ok(Self)
     }
 
-    fn summary(&self) {
-        println!("{:#?}", self);
+    fn summary(&self, w: &mut dyn std::fmt::Write) -> std::fmt::Result {
+        write!(w, "{:#?}", self)
     }
 
-    fn payload(&self) -> Result<BytesMut> {
+    fn payload(&self) -> Result<Bytes> {
         Err(Error::FrameError(
             "Uncallabe method for Null Recieve Frame".to_string(),
         ))
     }
+
+    fn encode(&self) -> Result<BytesMut> {
+        Err(Error::FrameError(
+            "Null Recieve Frame has no encoding".to_string(),
+        ))
+    }
 }
 
 /************ Transmit Status **********************/
 
-#[derive(Debug)]
-pub struct TransmitStatus {
-    frame_id: u8,
-    transmit_retry_count: u8,
-    deliver_status: u8,
-    discovery_status: u8,
-    payload: Option<BytesMut>,
+/// Outcome of a transmission, from the delivery status of a Transmit Status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeliveryStatus {
+    Success,
+    MacAckFailure,
+    CcaFailure,
+    Purged,
+    PhysicalError,
+    InvalidEndpoint,
+    NoBuffers,
+    NetworkAckFailure,
+    NotJoined,
+    SelfAddressed,
+    AddressNotFound,
+    RouteNotFound,
+    /// Broadcast source did not hear a neighbor relay the message
+    BroadcastRelayNotHeard,
+    InvalidBindingIndex,
+    /// Radio ran out of buffers or timers (0x2c, 0x31 or 0x32)
+    ResourceError(u8),
+    /// Broadcast with APS transmission, which is not supported
+    ApsBroadcast,
+    /// Unicast with APS transmission while EE=0
+    ApsUnicastWithoutEncryption,
+    NoSecureSession,
+    EncryptionFailure,
+    PayloadTooLarge,
+    /// The sleeping destination did not poll for the message
+    IndirectMessageUnrequested,
+    InvalidRelayInterface,
+    /// The User Data Relay interface is not accepting data
+    RelayInterfaceRejected,
+    Other(u8),
+}
+
+impl DeliveryStatus {
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0x00 => DeliveryStatus::Success,
+            0x01 => DeliveryStatus::MacAckFailure,
+            0x02 => DeliveryStatus::CcaFailure,
+            0x03 => DeliveryStatus::Purged,
+            0x04 => DeliveryStatus::PhysicalError,
+            0x15 => DeliveryStatus::InvalidEndpoint,
+            0x18 => DeliveryStatus::NoBuffers,
+            0x21 => DeliveryStatus::NetworkAckFailure,
+            0x22 => DeliveryStatus::NotJoined,
+            0x23 => DeliveryStatus::SelfAddressed,
+            0x24 => DeliveryStatus::AddressNotFound,
+            0x25 => DeliveryStatus::RouteNotFound,
+            0x26 => DeliveryStatus::BroadcastRelayNotHeard,
+            0x2b => DeliveryStatus::InvalidBindingIndex,
+            0x2d => DeliveryStatus::ApsBroadcast,
+            0x2e => DeliveryStatus::ApsUnicastWithoutEncryption,
+            0x34 => DeliveryStatus::NoSecureSession,
+            0x35 => DeliveryStatus::EncryptionFailure,
+            0x74 => DeliveryStatus::PayloadTooLarge,
+            0x75 => DeliveryStatus::IndirectMessageUnrequested,
+            0x7c => DeliveryStatus::InvalidRelayInterface,
+            0x7d => DeliveryStatus::RelayInterfaceRejected,
+            0x2c | 0x31 | 0x32 => DeliveryStatus::ResourceError(value),
+            other => DeliveryStatus::Other(other),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            DeliveryStatus::Success => 0x00,
+            DeliveryStatus::MacAckFailure => 0x01,
+            DeliveryStatus::CcaFailure => 0x02,
+            DeliveryStatus::Purged => 0x03,
+            DeliveryStatus::PhysicalError => 0x04,
+            DeliveryStatus::InvalidEndpoint => 0x15,
+            DeliveryStatus::NoBuffers => 0x18,
+            DeliveryStatus::NetworkAckFailure => 0x21,
+            DeliveryStatus::NotJoined => 0x22,
+            DeliveryStatus::SelfAddressed => 0x23,
+            DeliveryStatus::AddressNotFound => 0x24,
+            DeliveryStatus::RouteNotFound => 0x25,
+            DeliveryStatus::BroadcastRelayNotHeard => 0x26,
+            DeliveryStatus::InvalidBindingIndex => 0x2b,
+            DeliveryStatus::ApsBroadcast => 0x2d,
+            DeliveryStatus::ApsUnicastWithoutEncryption => 0x2e,
+            DeliveryStatus::NoSecureSession => 0x34,
+            DeliveryStatus::EncryptionFailure => 0x35,
+            DeliveryStatus::PayloadTooLarge => 0x74,
+            DeliveryStatus::IndirectMessageUnrequested => 0x75,
+            DeliveryStatus::InvalidRelayInterface => 0x7c,
+            DeliveryStatus::RelayInterfaceRejected => 0x7d,
+            DeliveryStatus::ResourceError(value) | DeliveryStatus::Other(value) => value,
+        }
+    }
+}
+
+/// Discovery the radio had to do before sending, from a Transmit Status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DiscoveryStatus {
+    NoOverhead,
+    Address,
+    Route,
+    AddressAndRoute,
+    /// The destination is a sleeping end device, so an extended timeout was used
+    ExtendedTimeout,
+    Other(u8),
+}
+
+impl DiscoveryStatus {
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0x00 => DiscoveryStatus::NoOverhead,
+            0x01 => DiscoveryStatus::Address,
+            0x02 => DiscoveryStatus::Route,
+            0x03 => DiscoveryStatus::AddressAndRoute,
+            0x40 => DiscoveryStatus::ExtendedTimeout,
+            other => DiscoveryStatus::Other(other),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            DiscoveryStatus::NoOverhead => 0x00,
+            DiscoveryStatus::Address => 0x01,
+            DiscoveryStatus::Route => 0x02,
+            DiscoveryStatus::AddressAndRoute => 0x03,
+            DiscoveryStatus::ExtendedTimeout => 0x40,
+            DiscoveryStatus::Other(value) => value,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TransmitStatus {
+    pub frame_id: u8,
+    pub transmit_retry_count: u8,
+    pub deliver_status: u8,
+    pub discovery_status: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl TransmitStatus {
+    /// Parses a complete Transmit Status frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::TransmitStatus, "discovery status", 9, 1)?;
+        Ok(Self {
+            frame_id: frame[4],
+            transmit_retry_count: frame[7],
+            deliver_status: frame[8],
+            discovery_status: frame[9],
+            payload: Some(frame),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.deliver_status == 0x00
+    }
+
+    pub fn delivery(&self) -> DeliveryStatus {
+        DeliveryStatus::from_value(self.deliver_status)
+    }
+
+    pub fn discovery(&self) -> DiscoveryStatus {
+        DiscoveryStatus::from_value(self.discovery_status)
+    }
+
+    /// Whether sending the same frame again has a reasonable chance of succeeding.
+    /// Transient link and resource failures are retryable, configuration and
+    /// addressing problems are not.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.deliver_status,
+            0x01 | 0x02 | 0x03 | 0x04 | 0x18 | 0x21 | 0x25 | 0x26 | 0x2c | 0x31 | 0x32
+        )
+    }
+
+    /// Explains the delivery status and what can be done about a failure
+    pub fn guidance(&self) -> &'static str {
+        match self.deliver_status {
+            0x00 => "Delivered",
+            0x01 => "MAC ACK failure: the next hop did not acknowledge; check range and retry",
+            0x02 => "CCA/LBT failure: the channel was busy; retry after a short backoff",
+            0x03 => "Transmission purged: it was stale by the time it could be sent; retry",
+            0x04 => "Physical error on the radio interface; retry and check the hardware if it persists",
+            0x15 => "Invalid destination endpoint; check the endpoint used for explicit addressing",
+            0x18 => "No buffers available on the radio; slow down and retry",
+            0x21 => "Network ACK failure: the destination did not acknowledge; check the node is awake and in range, or increase NH",
+            0x22 => "Not joined to a network; wait for the radio to join before transmitting",
+            0x23 => "Self addressed: the destination is the local device",
+            0x24 => "Address not found; discover the node again or check the destination address",
+            0x25 => "Route not found; run route discovery or increase NH (network hops) and retry",
+            0x26 => "Broadcast source did not hear a neighbor relay the message; retry",
+            0x2b => "Invalid binding table index",
+            0x2c | 0x31 | 0x32 => "Resource error on the radio (buffers, timers); slow down and retry",
+            0x2d => "Attempted broadcast with APS transmission, which is not supported",
+            0x2e => "Attempted unicast with APS transmission but EE=0",
+            0x34 => "No secure session connection to the destination",
+            0x35 => "Encryption failure; check the encryption settings on both nodes",
+            0x74 => "Payload too large; split the data or check NP for the maximum payload",
+            0x75 => "Indirect message unrequested: the sleeping destination did not poll for it",
+            0x7c => "Invalid User Data Relay interface",
+            0x7d => "The User Data Relay interface is not accepting data; check it is connected or running",
+            _ => "Unknown delivery status",
+        }
+    }
 }
 
 impl RecieveApiFrame for TransmitStatus {
@@ -208,32 +1392,317 @@ impl RecieveApiFrame for TransmitStatus {
     fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
         // wait for first
         let mut response: [u8; 11] = [0; 11];
-        ser.read_exact(&mut response)?;
+        read_exact(&mut *ser, &mut response, "Transmit Status")?;
+        Self::from_frame(BytesMut::from(&response[..]))
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        encode_frame(
+            self.id().id(),
+            &[
+                self.frame_id,
+                0xff,
+                0xfe,
+                self.transmit_retry_count,
+                self.deliver_status,
+                self.discovery_status,
+            ],
+        )
+    }
+}
+
+/************ Modem Status **********************/
+
+/// State change reported in a Modem Status frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ModemState {
+    HardwareReset,
+    WatchdogReset,
+    Joined,
+    Disassociated,
+    /// Configuration error or loss of synchronization on 802.15.4 firmware
+    SynchronizationLost,
+    CoordinatorRealignment,
+    CoordinatorStarted,
+    SecurityKeyUpdated,
+    NetworkWokeUp,
+    NetworkWentToSleep,
+    VoltageSupplyExceeded,
+    RemoteManagerConnected,
+    RemoteManagerDisconnected,
+    ConfigChangedWhileJoining,
+    AccessFault,
+    FatalError,
+    BluetoothConnected,
+    BluetoothDisconnected,
+    BandmaskConfigFailed,
+    CellularUpdateStarted,
+    CellularUpdateFailed,
+    CellularUpdateCompleted,
+    FirmwareUpdateStarted,
+    FirmwareUpdateFailed,
+    FirmwareUpdateApplying,
+    SecureSessionEstablished,
+    SecureSessionEnded,
+    SecureSessionAuthFailed,
+    PanIdConflict,
+    /// PAN ID changed after a conflict was detected
+    PanIdChanged,
+    /// The coordinator changed the PAN ID of this router
+    RouterPanIdChanged,
+    /// The network watchdog timed out three times
+    NetworkWatchdogExpired,
+    /// Ember ZigBee stack status, 0x80 and above
+    StackError(u8),
+    Other(u8),
+}
+
+impl ModemState {
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0x00 => ModemState::HardwareReset,
+            0x01 => ModemState::WatchdogReset,
+            0x02 => ModemState::Joined,
+            0x03 => ModemState::Disassociated,
+            0x04 => ModemState::SynchronizationLost,
+            0x05 => ModemState::CoordinatorRealignment,
+            0x06 => ModemState::CoordinatorStarted,
+            0x07 => ModemState::SecurityKeyUpdated,
+            0x0b => ModemState::NetworkWokeUp,
+            0x0c => ModemState::NetworkWentToSleep,
+            0x0d => ModemState::VoltageSupplyExceeded,
+            0x0e => ModemState::RemoteManagerConnected,
+            0x0f => ModemState::RemoteManagerDisconnected,
+            0x11 => ModemState::ConfigChangedWhileJoining,
+            0x12 => ModemState::AccessFault,
+            0x13 => ModemState::FatalError,
+            0x32 => ModemState::BluetoothConnected,
+            0x33 => ModemState::BluetoothDisconnected,
+            0x34 => ModemState::BandmaskConfigFailed,
+            0x35 => ModemState::CellularUpdateStarted,
+            0x36 => ModemState::CellularUpdateFailed,
+            0x37 => ModemState::CellularUpdateCompleted,
+            0x38 => ModemState::FirmwareUpdateStarted,
+            0x39 => ModemState::FirmwareUpdateFailed,
+            0x3a => ModemState::FirmwareUpdateApplying,
+            0x3b => ModemState::SecureSessionEstablished,
+            0x3c => ModemState::SecureSessionEnded,
+            0x3d => ModemState::SecureSessionAuthFailed,
+            0x3e => ModemState::PanIdConflict,
+            0x3f => ModemState::PanIdChanged,
+            0x40 => ModemState::RouterPanIdChanged,
+            0x42 => ModemState::NetworkWatchdogExpired,
+            0x80..=0xff => ModemState::StackError(value),
+            other => ModemState::Other(other),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            ModemState::HardwareReset => 0x00,
+            ModemState::WatchdogReset => 0x01,
+            ModemState::Joined => 0x02,
+            ModemState::Disassociated => 0x03,
+            ModemState::SynchronizationLost => 0x04,
+            ModemState::CoordinatorRealignment => 0x05,
+            ModemState::CoordinatorStarted => 0x06,
+            ModemState::SecurityKeyUpdated => 0x07,
+            ModemState::NetworkWokeUp => 0x0b,
+            ModemState::NetworkWentToSleep => 0x0c,
+            ModemState::VoltageSupplyExceeded => 0x0d,
+            ModemState::RemoteManagerConnected => 0x0e,
+            ModemState::RemoteManagerDisconnected => 0x0f,
+            ModemState::ConfigChangedWhileJoining => 0x11,
+            ModemState::AccessFault => 0x12,
+            ModemState::FatalError => 0x13,
+            ModemState::BluetoothConnected => 0x32,
+            ModemState::BluetoothDisconnected => 0x33,
+            ModemState::BandmaskConfigFailed => 0x34,
+            ModemState::CellularUpdateStarted => 0x35,
+            ModemState::CellularUpdateFailed => 0x36,
+            ModemState::CellularUpdateCompleted => 0x37,
+            ModemState::FirmwareUpdateStarted => 0x38,
+            ModemState::FirmwareUpdateFailed => 0x39,
+            ModemState::FirmwareUpdateApplying => 0x3a,
+            ModemState::SecureSessionEstablished => 0x3b,
+            ModemState::SecureSessionEnded => 0x3c,
+            ModemState::SecureSessionAuthFailed => 0x3d,
+            ModemState::PanIdConflict => 0x3e,
+            ModemState::PanIdChanged => 0x3f,
+            ModemState::RouterPanIdChanged => 0x40,
+            ModemState::NetworkWatchdogExpired => 0x42,
+            ModemState::StackError(value) | ModemState::Other(value) => value,
+        }
+    }
+
+    /// Whether the radio just restarted, losing any state that was not written
+    pub fn is_reset(&self) -> bool {
+        matches!(self, ModemState::HardwareReset | ModemState::WatchdogReset)
+    }
+}
+
+/// Sent by the radio on its own whenever its state changes, e.g. after a reset
+/// or when a sleeping network wakes up
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ModemStatus {
+    pub state: ModemState,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl ModemStatus {
+    /// Parses a complete Modem Status frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::ModemStatus, "status", 4, 1)?;
+        Ok(Self {
+            state: ModemState::from_value(frame[4]),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for ModemStatus {
+    fn id(&self) -> FrameId {
+        FrameId::ModemStatus
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        encode_frame(self.id().id(), &[self.state.value()])
+    }
+}
+
+/************ Extended Modem Status **********************/
+
+/// Diagnostic emitted while joining when verbose joining (VJ=1) is enabled. The
+/// meaning of `data` depends on `status_code` and the firmware.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtendedModemStatus {
+    pub status_code: u8,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl ExtendedModemStatus {
+    /// Parses a complete Extended Modem Status frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::ExtendedModemStatus, "status code", 4, 1)?;
         Ok(Self {
-            frame_id: response[4],
-            transmit_retry_count: response[7],
-            deliver_status: response[8],
-            discovery_status: response[9],
-            payload: Some(BytesMut::from(&response[..])),
+            status_code: frame[4],
+            data: frame.slice(5..frame.len() - 1),
+            payload: Some(frame),
         })
     }
+}
+
+impl RecieveApiFrame for ExtendedModemStatus {
+    fn id(&self) -> FrameId {
+        FrameId::ExtendedModemStatus
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![self.status_code];
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* API Options ****************************************/
+
+/// How the radio reports recieved RF data, as configured by the AO command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ApiOptions {
+    /// AO=0, data arrives as Receive Packet (0x90) frames
+    Native,
+    /// AO=1, data arrives as Explicit Rx Indicator (0x91) frames
+    Explicit,
+    /// AO=3, explicit frames and ZDO requests are passed through to the host
+    ExplicitZdoPassthrough,
+}
+
+impl ApiOptions {
+    pub fn value(&self) -> u8 {
+        match *self {
+            ApiOptions::Native => 0x00,
+            ApiOptions::Explicit => 0x01,
+            ApiOptions::ExplicitZdoPassthrough => 0x03,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(ApiOptions::Native),
+            0x01 => Some(ApiOptions::Explicit),
+            0x03 => Some(ApiOptions::ExplicitZdoPassthrough),
+            _ => None,
+        }
+    }
 
-    fn payload(&self) -> Result<BytesMut> {
-        match &self.payload {
-            Some(p) => Ok(p.clone()),
-            None => Err(Error::FrameError("Empty payload".to_string())),
+    /// Frame type that recieved RF data arrives in under this setting
+    pub fn rx_frame(&self) -> FrameId {
+        match *self {
+            ApiOptions::Native => FrameId::ReceivePacket,
+            ApiOptions::Explicit | ApiOptions::ExplicitZdoPassthrough => {
+                FrameId::ExplicitRxIndicator
+            }
         }
     }
 }
 
 /********************* Transmit Request ****************************************/
 
+/// Bytes of a Transmit Request counted by the length field besides the payload
+const TX_REQUEST_OVERHEAD: usize = 14;
+/// Bytes of an Explicit Addressing frame counted by the length field besides
+/// the payload
+const EXPLICIT_OVERHEAD: usize = 20;
+
+/// Fails with `Error::PayloadTooLarge` if `payload` is longer than `max`. Pass
+/// the NP of the radio to catch payloads it would reject with delivery status
+/// 0x74 before they are sent.
+pub fn check_payload_len(payload: &[u8], max: usize) -> Result<()> {
+    if payload.len() > max {
+        return Err(Error::PayloadTooLarge {
+            max,
+            got: payload.len(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MessagingMode {
     PointToPoint,
     Repeater,
     DigiMesh,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TransmitRequestOptions {
     pub disable_ack: bool,
     pub disable_route_discovery: bool,
@@ -246,17 +1715,17 @@ impl TransmitRequestOptions {
     pub fn compile(&self) -> u8 {
         let mut val: u8 = 0;
 
-        if self.disable_ack == true {
+        if self.disable_ack {
             val |= 1 << 0;
         }
-        if self.disable_route_discovery == true {
+        if self.disable_route_discovery {
             val |= 1 << 1;
         }
-        if self.enable_unicast_nack == true {
+        if self.enable_unicast_nack {
             val |= 1 << 2;
         }
 
-        if self.enable_unicast_trace_route == true {
+        if self.enable_unicast_trace_route {
             val |= 1 << 3;
         }
 
@@ -268,11 +1737,156 @@ impl TransmitRequestOptions {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TransmitRequestFrame<'a> {
-    pub dest_addr: u64,
+    pub dest_addr: Addr64,
+    /// 16-bit network address of the destination if known, which saves a Zigbee
+    /// radio the address discovery. `None` sends `constants::UNKNOWN_16BIT`.
+    pub dest_addr_16: Option<Addr16>,
     pub broadcast_radius: u8,
-    pub options: Option<&'a TransmitRequestOptions>,
+    pub options: Option<TransmitRequestOptions>,
     pub payload: &'a [u8],
+    /// Send with frame id 0 so the radio does not generate a Transmit Status
+    pub fire_and_forget: bool,
+}
+
+impl<'a> TransmitRequestFrame<'a> {
+    pub fn builder() -> TransmitRequestBuilder<'a> {
+        TransmitRequestBuilder::default()
+    }
+}
+
+/// Builds a `TransmitRequestFrame`, checking the payload size and that the
+/// options make sense for the destination before anything is sent
+#[derive(Debug, Default)]
+pub struct TransmitRequestBuilder<'a> {
+    dest_addr: Option<Addr64>,
+    dest_addr_16: Option<Addr16>,
+    broadcast_radius: u8,
+    payload: &'a [u8],
+    disable_ack: bool,
+    disable_route_discovery: bool,
+    enable_unicast_nack: bool,
+    enable_unicast_trace_route: bool,
+    mode: Option<MessagingMode>,
+    fire_and_forget: bool,
+    max_payload: Option<usize>,
+}
+
+impl<'a> TransmitRequestBuilder<'a> {
+    pub fn dest(mut self, addr: impl Into<Addr64>) -> Self {
+        self.dest_addr = Some(addr.into());
+        self
+    }
+
+    /// Sends to every node of the network
+    pub fn broadcast(self) -> Self {
+        self.dest(constants::BROADCAST)
+    }
+
+    pub fn dest_16(mut self, addr: impl Into<Addr16>) -> Self {
+        self.dest_addr_16 = Some(addr.into());
+        self
+    }
+
+    pub fn payload(mut self, payload: &'a [u8]) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Most hops a broadcast may take, 0 for the NH setting of the radio
+    pub fn broadcast_radius(mut self, radius: u8) -> Self {
+        self.broadcast_radius = radius;
+        self
+    }
+
+    pub fn disable_ack(mut self) -> Self {
+        self.disable_ack = true;
+        self
+    }
+
+    pub fn disable_route_discovery(mut self) -> Self {
+        self.disable_route_discovery = true;
+        self
+    }
+
+    pub fn unicast_nack(mut self) -> Self {
+        self.enable_unicast_nack = true;
+        self
+    }
+
+    pub fn trace_route(mut self) -> Self {
+        self.enable_unicast_trace_route = true;
+        self
+    }
+
+    /// Delivery method; defaults to `MessagingMode::DigiMesh` once any option is set
+    pub fn mode(mut self, mode: MessagingMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    pub fn fire_and_forget(mut self) -> Self {
+        self.fire_and_forget = true;
+        self
+    }
+
+    /// Largest payload the radio accepts, as reported by NP. Without it the
+    /// payload is not checked until the frame is generated, and then only
+    /// against what the length field can hold.
+    pub fn max_payload(mut self, max: usize) -> Self {
+        self.max_payload = Some(max);
+        self
+    }
+
+    pub fn build(self) -> Result<TransmitRequestFrame<'a>> {
+        let dest_addr = self
+            .dest_addr
+            .ok_or_else(|| Error::FrameError("Transmit Request has no destination".to_string()))?;
+        if let Some(max) = self.max_payload {
+            check_payload_len(self.payload, max)?;
+        }
+
+        let unicast_only = self.enable_unicast_nack || self.enable_unicast_trace_route;
+        if unicast_only && dest_addr == Addr64::BROADCAST {
+            return Err(Error::FrameError(
+                "Unicast NACK and trace route cannot be used for broadcasts".to_string(),
+            ));
+        }
+        let mode = self.mode.unwrap_or(MessagingMode::DigiMesh);
+        if (unicast_only || self.disable_route_discovery) && mode != MessagingMode::DigiMesh {
+            return Err(Error::FrameError(format!(
+                "Route options require DigiMesh messaging, not {:?}",
+                mode
+            )));
+        }
+        if self.enable_unicast_nack && self.disable_ack {
+            return Err(Error::FrameError(
+                "Unicast NACK needs acknowledgements enabled".to_string(),
+            ));
+        }
+
+        let any_option =
+            self.disable_ack || self.disable_route_discovery || unicast_only || self.mode.is_some();
+        Ok(TransmitRequestFrame {
+            dest_addr,
+            dest_addr_16: self.dest_addr_16,
+            broadcast_radius: self.broadcast_radius,
+            options: if any_option {
+                Some(TransmitRequestOptions {
+                    disable_ack: self.disable_ack,
+                    disable_route_discovery: self.disable_route_discovery,
+                    enable_unicast_nack: self.enable_unicast_nack,
+                    enable_unicast_trace_route: self.enable_unicast_trace_route,
+                    mode,
+                })
+            } else {
+                None
+            },
+            payload: self.payload,
+            fire_and_forget: self.fire_and_forget,
+        })
+    }
 }
 
 impl TransmitApiFrame for TransmitRequestFrame<'_> {
@@ -282,19 +1896,20 @@ impl TransmitApiFrame for TransmitRequestFrame<'_> {
 
     fn gen(&self) -> Result<BytesMut> {
         let mut packet = BytesMut::new();
-        let mut rng = rand::thread_rng();
-        if self.payload.len() > 65535 - 112 {
-            return Err(Error::PayloadError("Payload exceeds max size".to_string()));
-        }
+        check_payload_len(self.payload, 0xffff - TX_REQUEST_OVERHEAD)?;
 
-        let frame_id: u8 = rng.gen();
+        let frame_id: u8 = if self.fire_and_forget {
+            0
+        } else {
+            self.gen_frame_id()
+        };
 
         packet.put_u8(self.delim());
-        packet.put_u16((self.payload.len() as u16) + (0x0e as u16));
-        packet.put_u8(0x10);
+        packet.put_u16((self.payload.len() + TX_REQUEST_OVERHEAD) as u16);
+        packet.put_u8(self.id().id());
         packet.put_u8(frame_id);
-        packet.put_u64(self.dest_addr);
-        packet.put_u16(0xfffe);
+        packet.put_u64(self.dest_addr.0);
+        packet.put_u16(self.dest_addr_16.unwrap_or(Addr16::UNKNOWN).0);
         packet.put_u8(self.broadcast_radius);
 
         match self.options {
@@ -310,141 +1925,2526 @@ impl TransmitApiFrame for TransmitRequestFrame<'_> {
     }
 }
 
-/********************* Remote AtCommand Frame ****************************************/
-pub struct RemoteCommandOptions {
-    pub apply_changes: bool,
-}
+/********************* Receive Packet ****************************************/
 
-pub struct RemoteAtCommandFrame<'a> {
-    pub dest_addr: u64,
-    pub options: &'a RemoteCommandOptions,
-    pub atcmd: &'a str,
-    pub cmd_param: Option<&'a [u8]>,
+/// RF data from a remote node, as reported with AO=0
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ReceivePacket {
+    pub source_addr: Addr64,
+    pub receive_options: u8,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
 }
 
-impl TransmitApiFrame for RemoteAtCommandFrame<'_> {
-    fn id(&self) -> FrameId {
-        FrameId::RemoteAtCommand
-    }
+impl ReceivePacket {
+    /// Parses a complete Receive Packet frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::ReceivePacket, "receive options", 14, 1)?;
 
-    fn gen(&self) -> Result<BytesMut> {
-        let mut packet = BytesMut::with_capacity(64);
-        let frame_id: u8 = self.gen_frame_id();
-        packet.put_u8(DELIM);
-        packet.put_u16(0); // length; just to initalize
-        packet.put_u8(self.id().id());
-        packet.put_u8(frame_id);
-        packet.put_u64(self.dest_addr);
-        packet.put_u16(0xfffe);
+        let source_addr = Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap());
+        Ok(Self {
+            source_addr,
+            receive_options: frame[14],
+            data: frame.slice(15..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
 
-        if self.options.apply_changes == true {
-            packet.put_u8(0x02);
-        } else {
-            packet.put_u8(0);
-        }
+    /// Whether the packet was sent as a broadcast
+    pub fn is_broadcast(&self) -> bool {
+        self.receive_options & 0x02 != 0
+    }
+}
 
-        packet.put(self.atcmd.as_bytes());
+impl RecieveApiFrame for ReceivePacket {
+    fn id(&self) -> FrameId {
+        FrameId::ReceivePacket
+    }
 
-        if let Some(param) = self.cmd_param {
-            packet.put(&param[..]);
-        }
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
 
-        // change length here
-        let packet_len = (packet.len() - 3) as u16;
-        packet[1] = (packet_len >> 8) as u8;
-        packet[2] = (packet_len & 0xff) as u8;
-        // checksum now
-        let chksum = self.calc_checksum(&packet[..])?;
-        packet.put_u8(chksum);
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
 
-        Ok(packet)
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, self.receive_options]);
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
     }
 }
 
-/********************* Remote Command Response Frame ****************************************/
-pub struct RemoteAtCommandResponse {
-    frame_id: u8,
-    pub dest_addr: u64,
-    pub at_command: Vec<u8>,
-    command_status: u8,
-    pub command_data: Option<BytesMut>,
-    payload: Option<BytesMut>,
+/********************* Create Source Route ****************************************/
+
+/// Tells the radio which relays to use for unicasts to `dest_addr`. The radio
+/// does not answer this frame.
+pub struct CreateSourceRouteFrame<'a> {
+    pub dest_addr: Addr64,
+    pub dest_addr_16: Addr16,
+    /// 16-bit addresses of the relays, closest to the destination first
+    pub hops: &'a [u16],
 }
 
-impl std::fmt::Debug for RemoteAtCommandResponse {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let atcmd = std::str::from_utf8(&self.at_command[..]).ok();
+impl TransmitApiFrame for CreateSourceRouteFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::CreateSourceRoute
+    }
 
-        let cmd_data = match self.command_data {
-            Some(ref data) => format!("{:x?}", &data[..]),
-            None => format!("None"),
-        };
+    fn gen(&self) -> Result<BytesMut> {
+        if self.hops.is_empty() || self.hops.len() > MAX_SOURCE_ROUTE_HOPS {
+            return Err(Error::FrameError(format!(
+                "Source route needs 1 to {} hops, got {}",
+                MAX_SOURCE_ROUTE_HOPS,
+                self.hops.len()
+            )));
+        }
 
-        f.debug_struct("AtCommandResponse")
-            .field("FrameId", &format!("0x{:02x?}", self.frame_id))
-            .field("Dest Addr", &format!("0x{:016x?}", self.dest_addr))
-            .field("AtCommand", &format!("{}", atcmd.unwrap()))
-            .field("Command Status", &format!("{}", self.command_status))
-            .field("Command Data", &cmd_data)
-            .finish()
+        // frame id is always 0, there is no response
+        let mut body = vec![0x00];
+        body.extend_from_slice(&self.dest_addr.to_be_bytes());
+        body.extend_from_slice(&self.dest_addr_16.to_be_bytes());
+        body.push(0x00); // route command options
+        body.push(self.hops.len() as u8);
+        for hop in self.hops {
+            body.extend_from_slice(&hop.to_be_bytes());
+        }
+        encode_frame(self.id().id(), &body)
     }
 }
 
-impl RecieveApiFrame for RemoteAtCommandResponse {
+/********************* Register Joining Device ****************************************/
+
+/// Adds a device to the trust center's key table so it can join a secure
+/// Zigbee network, either with a link key or with its install code
+pub struct RegisterJoiningDeviceFrame<'a> {
+    pub dest_addr: Addr64,
+    /// Link key of up to 16 bytes, or the install code including its CRC
+    pub key: &'a [u8],
+    pub install_code: bool,
+}
+
+impl TransmitApiFrame for RegisterJoiningDeviceFrame<'_> {
     fn id(&self) -> FrameId {
-        FrameId::RemoteAtCommandResponse
+        FrameId::RegisterJoiningDevice
     }
 
-    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
-        let mut buffer = BytesMut::with_capacity(1024);
-        let mut mini_buf: [u8; 1] = [0];
-        loop {
-            if let Err(err) = ser.read_exact(&mut mini_buf) {
-                if err.kind() == std::io::ErrorKind::TimedOut {
-                    break;
+    fn gen(&self) -> Result<BytesMut> {
+        let valid = if self.install_code {
+            // 6, 8, 12 or 16 byte codes followed by a 2 byte CRC
+            matches!(self.key.len(), 8 | 10 | 14 | 18)
+        } else {
+            !self.key.is_empty() && self.key.len() <= 16
+        };
+        if !valid {
+            return Err(Error::PayloadError(format!(
+                "{} byte {} is not valid",
+                self.key.len(),
+                if self.install_code {
+                    "install code"
                 } else {
-                    return Err(Error::IOError(err));
+                    "link key"
                 }
-            }
-            buffer.put_u8(mini_buf[0]);
+            )));
         }
 
-        let mut cmd_data = None;
-        if buffer.len() > 18 {
-            cmd_data = Some(BytesMut::from(&buffer[18..buffer.len() - 1]));
-        }
-        let mut at_cmd: Vec<u8> = Vec::new();
-        at_cmd.push(buffer[15]);
-        at_cmd.push(buffer[16]);
-        let dest_buf = &buffer[5..13];
-        let dest_addr = u64::from_be_bytes(<[u8; 8]>::try_from(dest_buf).unwrap()); // messy but works
+        let mut body = vec![self.gen_frame_id()];
+        body.extend_from_slice(&self.dest_addr.to_be_bytes());
+        body.extend_from_slice(&[0xff, 0xfe]);
+        body.push(self.install_code as u8);
+        body.extend_from_slice(self.key);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RegisterJoiningDeviceStatus {
+    pub frame_id: u8,
+    pub status: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl RegisterJoiningDeviceStatus {
+    /// Parses a complete Register Joining Device Status frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::RegisterJoiningDeviceStatus, "status", 5, 1)?;
         Ok(Self {
-            frame_id: buffer[4],
-            dest_addr: dest_addr,
-            at_command: at_cmd,
-            command_status: buffer[17],
-            command_data: cmd_data,
-            payload: Some(buffer),
+            frame_id: frame[4],
+            status: frame[5],
+            payload: Some(frame),
         })
     }
 
-    fn payload(&self) -> Result<BytesMut> {
-        match &self.payload {
-            Some(p) => Ok(p.clone()),
-            None => Err(Error::FrameError("Empty payload".to_string())),
+    pub fn is_success(&self) -> bool {
+        self.status == 0x00
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self.status {
+            0x00 => "Success",
+            0x01 => "Key too long",
+            0xb1 => "Address not found in the key table",
+            0xb2 => "Key is invalid (all zeros or reserved)",
+            0xb3 => "Invalid address",
+            0xb4 => "Key table is full",
+            0xbd => "Install code CRC does not match",
+            _ => "Unknown status",
         }
     }
 }
-/********************* AtCommand Frame ****************************************/
 
-pub struct AtCommandFrame<'a>(pub &'a str, pub Option<&'a [u8]>);
-impl TransmitApiFrame for AtCommandFrame<'_> {
+impl RecieveApiFrame for RegisterJoiningDeviceStatus {
     fn id(&self) -> FrameId {
-        FrameId::AtCommand
+        FrameId::RegisterJoiningDeviceStatus
     }
 
-    fn gen(&self) -> Result<BytesMut> {
-        let mut packet = BytesMut::with_capacity(9);
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        encode_frame(self.id().id(), &[self.frame_id, self.status])
+    }
+}
+
+/********************* 802.15.4 Tx Request ****************************************/
+
+/// Transmit option of 802.15.4 firmware that disables the MAC ACK
+pub static TX_OPTION_DISABLE_ACK: u8 = 0x01;
+/// Transmit option of 802.15.4 firmware that sends with the broadcast PAN ID
+pub static TX_OPTION_BROADCAST_PAN: u8 = 0x04;
+
+/// Transmit Request of legacy 802.15.4 firmware, addressed by 64-bit address
+pub struct TxRequest64Frame<'a> {
+    pub dest_addr: Addr64,
+    pub options: u8,
+    pub payload: &'a [u8],
+    /// Send with frame id 0 so the radio does not generate a Tx Status
+    pub fire_and_forget: bool,
+}
+
+/// Transmit Request of legacy 802.15.4 firmware, addressed by 16-bit address (MY)
+pub struct TxRequest16Frame<'a> {
+    pub dest_addr: Addr16,
+    pub options: u8,
+    pub payload: &'a [u8],
+    /// Send with frame id 0 so the radio does not generate a Tx Status
+    pub fire_and_forget: bool,
+}
+
+/// Body shared by both legacy Tx Requests
+fn legacy_tx_body(frame_id: u8, dest: &[u8], options: u8, payload: &[u8]) -> Vec<u8> {
+    let mut body = vec![frame_id];
+    body.extend_from_slice(dest);
+    body.push(options);
+    body.extend_from_slice(payload);
+    body
+}
+
+impl TransmitApiFrame for TxRequest64Frame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::TxRequest64
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        check_payload_len(self.payload, MAX_802154_PAYLOAD)?;
+        let frame_id = if self.fire_and_forget {
+            0
+        } else {
+            self.gen_frame_id()
+        };
+        let body = legacy_tx_body(
+            frame_id,
+            &self.dest_addr.to_be_bytes(),
+            self.options,
+            self.payload,
+        );
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+impl TransmitApiFrame for TxRequest16Frame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::TxRequest16
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        check_payload_len(self.payload, MAX_802154_PAYLOAD)?;
+        let frame_id = if self.fire_and_forget {
+            0
+        } else {
+            self.gen_frame_id()
+        };
+        let body = legacy_tx_body(
+            frame_id,
+            &self.dest_addr.to_be_bytes(),
+            self.options,
+            self.payload,
+        );
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* 802.15.4 Tx Status ****************************************/
+
+/// Delivery report for a legacy 802.15.4 Tx Request
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TxStatus {
+    pub frame_id: u8,
+    pub status: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl TxStatus {
+    /// Parses a complete Tx Status frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::TxStatus, "status", 5, 1)?;
+        Ok(Self {
+            frame_id: frame[4],
+            status: frame[5],
+            payload: Some(frame),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == 0x00
+    }
+
+    /// Explains the status and what can be done about a failure
+    pub fn guidance(&self) -> &'static str {
+        match self.status {
+            0x00 => "Delivered",
+            0x01 => {
+                "No ACK: the destination did not acknowledge; check range and that it is powered"
+            }
+            0x02 => "CCA failure: the channel was busy; retry after a short backoff",
+            0x03 => "Purged: the destination is an end device that did not poll in time",
+            _ => "Unknown delivery status",
+        }
+    }
+}
+
+impl RecieveApiFrame for TxStatus {
+    fn id(&self) -> FrameId {
+        FrameId::TxStatus
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        encode_frame(self.id().id(), &[self.frame_id, self.status])
+    }
+}
+
+/********************* 802.15.4 Rx Packet ****************************************/
+
+/// RF data recieved by legacy 802.15.4 firmware from a 64-bit addressed sender
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rx64Packet {
+    pub source_addr: Addr64,
+    /// Signal strength in -dBm
+    pub rssi: u8,
+    pub options: u8,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+/// RF data recieved by legacy 802.15.4 firmware from a 16-bit addressed sender
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rx16Packet {
+    pub source_addr: Addr16,
+    /// Signal strength in -dBm
+    pub rssi: u8,
+    pub options: u8,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+/// Checks the type and length of a legacy Rx frame whose source address is
+/// `addr_len` bytes long
+fn check_legacy_rx(frame: &[u8], expected: FrameId, addr_len: usize) -> Result<()> {
+    check_fixed(frame, expected, "options", 5 + addr_len, 1)
+}
+
+impl Rx64Packet {
+    /// Parses a complete Rx (64-bit) frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_legacy_rx(&frame, FrameId::Rx64, 8)?;
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            rssi: frame[12],
+            options: frame[13],
+            data: frame.slice(14..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl Rx16Packet {
+    /// Parses a complete Rx (16-bit) frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_legacy_rx(&frame, FrameId::Rx16, 2)?;
+        Ok(Self {
+            source_addr: Addr16::from_be_bytes([frame[4], frame[5]]),
+            rssi: frame[6],
+            options: frame[7],
+            data: frame.slice(8..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for Rx64Packet {
+    fn id(&self) -> FrameId {
+        FrameId::Rx64
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[self.rssi, self.options]);
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+impl RecieveApiFrame for Rx16Packet {
+    fn id(&self) -> FrameId {
+        FrameId::Rx16
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[self.rssi, self.options]);
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* User Data Relay ****************************************/
+
+/// Local interfaces of an XBee 3 that User Data Relay frames pass data between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RelayInterface {
+    Serial,
+    Bluetooth,
+    MicroPython,
+}
+
+impl RelayInterface {
+    pub fn value(&self) -> u8 {
+        match self {
+            RelayInterface::Serial => 0,
+            RelayInterface::Bluetooth => 1,
+            RelayInterface::MicroPython => 2,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(RelayInterface::Serial),
+            1 => Some(RelayInterface::Bluetooth),
+            2 => Some(RelayInterface::MicroPython),
+            _ => None,
+        }
+    }
+}
+
+/// Hands `payload` to another interface of the local radio, e.g. to code running
+/// in MicroPython. The radio only answers when the relay fails, so the frame is
+/// sent with frame id 0.
+pub struct UserDataRelayFrame<'a> {
+    pub interface: RelayInterface,
+    pub payload: &'a [u8],
+}
+
+impl TransmitApiFrame for UserDataRelayFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::UserDataRelay
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![0x00, self.interface.value()];
+        body.extend_from_slice(self.payload);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/// Data relayed to the serial interface from another interface of the radio
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct UserDataRelayOutput {
+    pub source: RelayInterface,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl UserDataRelayOutput {
+    /// Parses a complete User Data Relay Output frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(
+            &frame,
+            FrameId::UserDataRelayOutput,
+            "source interface",
+            4,
+            1,
+        )?;
+        let source = RelayInterface::from_value(frame[4]).ok_or_else(|| {
+            DecodeError::invalid(
+                FrameId::UserDataRelayOutput,
+                "source interface",
+                4,
+                format!("unknown relay interface 0x{:02x}", frame[4]),
+            )
+        })?;
+        Ok(Self {
+            source,
+            data: frame.slice(5..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for UserDataRelayOutput {
+    fn id(&self) -> FrameId {
+        FrameId::UserDataRelayOutput
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![self.source.value()];
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Cellular Sockets ****************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SocketProtocol {
+    Udp,
+    Tcp,
+    Tls,
+}
+
+impl SocketProtocol {
+    pub fn value(&self) -> u8 {
+        match self {
+            SocketProtocol::Udp => 0,
+            SocketProtocol::Tcp => 1,
+            SocketProtocol::Tls => 4,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(SocketProtocol::Udp),
+            1 => Some(SocketProtocol::Tcp),
+            4 => Some(SocketProtocol::Tls),
+            _ => None,
+        }
+    }
+}
+
+/// Asks an XBee Cellular modem for a new socket; answered with a Socket Create
+/// Response carrying the socket id
+pub struct SocketCreateFrame {
+    pub protocol: SocketProtocol,
+}
+
+impl TransmitApiFrame for SocketCreateFrame {
+    fn id(&self) -> FrameId {
+        FrameId::SocketCreate
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        encode_frame(
+            self.id().id(),
+            &[self.gen_frame_id(), self.protocol.value()],
+        )
+    }
+}
+
+/// Where a socket connects to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SocketAddr<'a> {
+    Ipv4([u8; 4]),
+    /// Host name or dotted address, resolved by the modem
+    Host(&'a str),
+}
+
+pub struct SocketConnectFrame<'a> {
+    pub socket_id: u8,
+    pub port: u16,
+    pub addr: SocketAddr<'a>,
+}
+
+impl TransmitApiFrame for SocketConnectFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::SocketConnect
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![self.gen_frame_id(), self.socket_id];
+        body.extend_from_slice(&self.port.to_be_bytes());
+        match &self.addr {
+            SocketAddr::Ipv4(addr) => {
+                body.push(0x00);
+                body.extend_from_slice(addr);
+            }
+            SocketAddr::Host(host) => {
+                body.push(0x01);
+                body.extend_from_slice(host.as_bytes());
+            }
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+pub struct SocketCloseFrame {
+    pub socket_id: u8,
+}
+
+impl TransmitApiFrame for SocketCloseFrame {
+    fn id(&self) -> FrameId {
+        FrameId::SocketClose
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        encode_frame(self.id().id(), &[self.gen_frame_id(), self.socket_id])
+    }
+}
+
+/// Sends data over a connected socket; answered with a Tx Status
+pub struct SocketSendFrame<'a> {
+    pub socket_id: u8,
+    pub payload: &'a [u8],
+}
+
+impl TransmitApiFrame for SocketSendFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::SocketSend
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![self.gen_frame_id(), self.socket_id, 0x00];
+        body.extend_from_slice(self.payload);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/// Answer to a Socket Create, Connect or Close request; `kind` tells which
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SocketResponse {
+    pub kind: FrameId,
+    pub frame_id: u8,
+    /// Id of the socket, 0xff when a create request failed
+    pub socket_id: u8,
+    pub status: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl SocketResponse {
+    /// Parses a complete Socket Create, Connect or Close Response, delimiter
+    /// through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        require(&frame, FrameId::SocketCreateResponse, "frame type", 3, 1)?;
+        let kind = match frame[3] {
+            0xc0 => FrameId::SocketCreateResponse,
+            0xc2 => FrameId::SocketConnectResponse,
+            0xc3 => FrameId::SocketCloseResponse,
+            got => {
+                return Err(Error::UnexpectedFrame {
+                    expected: FrameId::SocketCreateResponse,
+                    got,
+                })
+            }
+        };
+        require(&frame, kind, "status", 6, 1)?;
+        Ok(Self {
+            kind,
+            frame_id: frame[4],
+            socket_id: frame[5],
+            status: frame[6],
+            payload: Some(frame),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == 0x00
+    }
+}
+
+impl RecieveApiFrame for SocketResponse {
+    fn id(&self) -> FrameId {
+        self.kind
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        encode_frame(
+            self.kind.id(),
+            &[self.frame_id, self.socket_id, self.status],
+        )
+    }
+}
+
+/// Data recieved on a connected socket
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SocketReceive {
+    pub socket_id: u8,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl SocketReceive {
+    /// Parses a complete Socket Receive frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::SocketReceive, "status", 6, 1)?;
+        Ok(Self {
+            socket_id: frame[5],
+            data: frame.slice(7..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for SocketReceive {
+    fn id(&self) -> FrameId {
+        FrameId::SocketReceive
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![0x00, self.socket_id, 0x00];
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* IPv4 ****************************************/
+
+/// Sends data to an IPv4 host from an XBee Wi-Fi or Cellular module without
+/// managing a socket; answered with a Tx Status
+pub struct TxIpv4Frame<'a> {
+    pub dest_addr: Ipv4Addr,
+    pub dest_port: u16,
+    /// 0 lets the module pick one
+    pub source_port: u16,
+    pub protocol: SocketProtocol,
+    /// Close the TCP connection once the data is sent
+    pub close_after: bool,
+    pub payload: &'a [u8],
+}
+
+impl TransmitApiFrame for TxIpv4Frame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::TxIpv4
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![self.gen_frame_id()];
+        body.extend_from_slice(&self.dest_addr.octets());
+        body.extend_from_slice(&self.dest_port.to_be_bytes());
+        body.extend_from_slice(&self.source_port.to_be_bytes());
+        body.push(self.protocol.value());
+        body.push(if self.close_after { 0x02 } else { 0x00 });
+        body.extend_from_slice(self.payload);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/// Data recieved from an IPv4 host
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RxIpv4 {
+    pub source_addr: Ipv4Addr,
+    pub dest_port: u16,
+    pub source_port: u16,
+    pub protocol: SocketProtocol,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl RxIpv4 {
+    /// Parses a complete Rx IPv4 frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::RxIpv4, "status", 13, 1)?;
+        let protocol = SocketProtocol::from_value(frame[12]).ok_or_else(|| {
+            DecodeError::invalid(
+                FrameId::RxIpv4,
+                "protocol",
+                12,
+                format!("unknown IP protocol 0x{:02x}", frame[12]),
+            )
+        })?;
+        Ok(Self {
+            source_addr: Ipv4Addr::new(frame[4], frame[5], frame[6], frame[7]),
+            dest_port: u16::from_be_bytes([frame[8], frame[9]]),
+            source_port: u16::from_be_bytes([frame[10], frame[11]]),
+            protocol,
+            data: frame.slice(14..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for RxIpv4 {
+    fn id(&self) -> FrameId {
+        FrameId::RxIpv4
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.octets().to_vec();
+        body.extend_from_slice(&self.dest_port.to_be_bytes());
+        body.extend_from_slice(&self.source_port.to_be_bytes());
+        body.extend_from_slice(&[self.protocol.value(), 0x00]);
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* SMS ****************************************/
+
+/// Phone numbers are sent in a fixed, zero padded field of this size
+static SMS_NUMBER_LEN: usize = 20;
+/// Longest text message a single SMS carries
+pub static MAX_SMS_LEN: usize = 160;
+
+/// Sends a text message from an XBee Cellular modem; answered with a Tx Status
+pub struct TxSmsFrame<'a> {
+    /// Digits, optionally with a leading `+`
+    pub phone_number: &'a str,
+    pub message: &'a str,
+}
+
+impl TransmitApiFrame for TxSmsFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::TxSms
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let digits = self
+            .phone_number
+            .strip_prefix('+')
+            .unwrap_or(self.phone_number);
+        if self.phone_number.len() > SMS_NUMBER_LEN
+            || digits.is_empty()
+            || !digits.bytes().all(|b| b.is_ascii_digit())
+        {
+            return Err(Error::PayloadError(format!(
+                "{:?} is not a phone number of up to {} characters",
+                self.phone_number, SMS_NUMBER_LEN
+            )));
+        }
+        check_payload_len(self.message.as_bytes(), MAX_SMS_LEN)?;
+
+        let mut body = vec![self.gen_frame_id(), 0x00];
+        let mut number = [0u8; 20];
+        number[..self.phone_number.len()].copy_from_slice(self.phone_number.as_bytes());
+        body.extend_from_slice(&number);
+        body.extend_from_slice(self.message.as_bytes());
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/// Text message recieved by an XBee Cellular modem
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RxSms {
+    pub phone_number: String,
+    /// Message text; not guaranteed to be UTF-8
+    pub message: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl RxSms {
+    /// Parses a complete Rx SMS frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::RxSms, "phone number", 4, SMS_NUMBER_LEN)?;
+        let number = &frame[4..4 + SMS_NUMBER_LEN];
+        let len = number
+            .iter()
+            .position(|b| *b == 0)
+            .unwrap_or(SMS_NUMBER_LEN);
+        Ok(Self {
+            phone_number: String::from_utf8_lossy(&number[..len]).into_owned(),
+            message: frame.slice(4 + SMS_NUMBER_LEN..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for RxSms {
+    fn id(&self) -> FrameId {
+        FrameId::RxSms
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![0u8; SMS_NUMBER_LEN];
+        let number = self.phone_number.as_bytes();
+        if number.len() > SMS_NUMBER_LEN {
+            return Err(Error::PayloadError(format!(
+                "Phone number is longer than {} digits",
+                SMS_NUMBER_LEN
+            )));
+        }
+        body[..number.len()].copy_from_slice(number);
+        body.extend_from_slice(&self.message);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* File System ****************************************/
+
+/// Open option: create the file if it does not exist
+pub static FS_OPEN_CREATE: u8 = 0x01;
+/// Open option: fail if the file already exists
+pub static FS_OPEN_EXCLUSIVE: u8 = 0x02;
+/// Open option: truncate the file to zero length
+pub static FS_OPEN_TRUNCATE: u8 = 0x04;
+/// Open option: every write goes to the end of the file
+pub static FS_OPEN_APPEND: u8 = 0x08;
+/// Read/write offset meaning the current position of the file handle
+pub static FS_CURRENT_OFFSET: u32 = 0xffff_ffff;
+
+/// Operation on the XBee 3 file system. Paths are relative to the current
+/// directory of the API file system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsCommand<'a> {
+    Open {
+        path: &'a str,
+        options: u8,
+    },
+    Close {
+        handle: u16,
+    },
+    Read {
+        handle: u16,
+        offset: u32,
+        len: u16,
+    },
+    Write {
+        handle: u16,
+        offset: u32,
+        data: &'a [u8],
+    },
+    Delete {
+        path: &'a str,
+    },
+    /// Used, free and bad space of a volume such as `/flash`, answered as a
+    /// `VolumeStat`
+    VolumeStat {
+        volume: &'a str,
+    },
+}
+
+impl FsCommand<'_> {
+    /// Appends the command id and its fields to a request body. Path id 0 is
+    /// the current directory.
+    fn put(&self, body: &mut Vec<u8>) {
+        body.push(self.value());
+        match self {
+            FsCommand::Open { path, options } => {
+                body.extend_from_slice(&[0x00, 0x00, *options]);
+                body.extend_from_slice(path.as_bytes());
+            }
+            FsCommand::Close { handle } => body.extend_from_slice(&handle.to_be_bytes()),
+            FsCommand::Read {
+                handle,
+                offset,
+                len,
+            } => {
+                body.extend_from_slice(&handle.to_be_bytes());
+                body.extend_from_slice(&offset.to_be_bytes());
+                body.extend_from_slice(&len.to_be_bytes());
+            }
+            FsCommand::Write {
+                handle,
+                offset,
+                data,
+            } => {
+                body.extend_from_slice(&handle.to_be_bytes());
+                body.extend_from_slice(&offset.to_be_bytes());
+                body.extend_from_slice(data);
+            }
+            FsCommand::Delete { path } => {
+                body.extend_from_slice(&[0x00, 0x00]);
+                body.extend_from_slice(path.as_bytes());
+            }
+            FsCommand::VolumeStat { volume } => body.extend_from_slice(volume.as_bytes()),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match self {
+            FsCommand::Open { .. } => 0x01,
+            FsCommand::Close { .. } => 0x02,
+            FsCommand::Read { .. } => 0x03,
+            FsCommand::Write { .. } => 0x04,
+            FsCommand::Delete { .. } => 0x2f,
+            FsCommand::VolumeStat { .. } => 0x40,
+        }
+    }
+}
+
+/// Space on a file system volume, in bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VolumeStat {
+    pub used: u32,
+    pub free: u32,
+    /// Space lost to bad sectors
+    pub bad: u32,
+}
+
+pub struct FileSystemRequestFrame<'a>(pub FsCommand<'a>);
+
+impl TransmitApiFrame for FileSystemRequestFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::FileSystemRequest
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![self.gen_frame_id()];
+        self.0.put(&mut body);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/// File system request executed by the remote node `dest_addr`, answered with
+/// a `FileSystemResponse` that carries the node's address
+pub struct RemoteFileSystemRequestFrame<'a> {
+    pub dest_addr: Addr64,
+    pub command: FsCommand<'a>,
+}
+
+impl TransmitApiFrame for RemoteFileSystemRequestFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::RemoteFileSystemRequest
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![self.gen_frame_id()];
+        body.extend_from_slice(&self.dest_addr.to_be_bytes());
+        body.push(0x00); // transmit options
+        self.command.put(&mut body);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FileSystemResponse {
+    pub frame_id: u8,
+    /// Node that answered a remote request, `None` for the local radio
+    pub source_addr: Option<Addr64>,
+    /// Command id of the request, see `FsCommand::value`
+    pub command: u8,
+    pub status: u8,
+    /// Command specific data following the status
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl FileSystemResponse {
+    /// Parses a complete local or remote File System Response, delimiter
+    /// through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        let remote = match frame.get(3) {
+            Some(0xbb) => false,
+            Some(0xbc) => true,
+            got => {
+                return Err(Error::UnexpectedFrame {
+                    expected: FrameId::FileSystemResponse,
+                    got: got.copied().unwrap_or(0),
+                })
+            }
+        };
+        // source address and receive options precede the command of remote responses
+        let at = if remote { 14 } else { 5 };
+        require(&frame, FrameId::FileSystemResponse, "status", at + 1, 1)?;
+        Ok(Self {
+            frame_id: frame[4],
+            source_addr: if remote {
+                Some(Addr64::from_be_bytes(
+                    <[u8; 8]>::try_from(&frame[5..13]).unwrap(),
+                ))
+            } else {
+                None
+            },
+            command: frame[at],
+            status: frame[at + 1],
+            data: frame.slice(at + 2..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == 0x00
+    }
+
+    /// File handle answered to open, close, read and write
+    pub fn handle(&self) -> Option<u16> {
+        match self.command {
+            0x01..=0x04 if self.data.len() >= 2 => {
+                Some(u16::from_be_bytes([self.data[0], self.data[1]]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Size of the file answered to open
+    pub fn file_size(&self) -> Option<u32> {
+        match self.command {
+            0x01 => {
+                let size = self.data.get(2..6)?;
+                Some(u32::from_be_bytes(<[u8; 4]>::try_from(size).unwrap()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Space on the volume answered to a volume stat
+    pub fn volume_stat(&self) -> Option<VolumeStat> {
+        if self.command != 0x40 || self.data.len() < 12 {
+            return None;
+        }
+        let field =
+            |at: usize| u32::from_be_bytes(<[u8; 4]>::try_from(&self.data[at..at + 4]).unwrap());
+        Some(VolumeStat {
+            used: field(0),
+            free: field(4),
+            bad: field(8),
+        })
+    }
+
+    /// Position in the file after a read or write
+    pub fn offset(&self) -> Option<u32> {
+        match self.command {
+            0x03 | 0x04 => {
+                let offset = self.data.get(2..6)?;
+                Some(u32::from_be_bytes(<[u8; 4]>::try_from(offset).unwrap()))
+            }
+            _ => None,
+        }
+    }
+
+    /// File contents answered to a read
+    pub fn read_data(&self) -> Option<&[u8]> {
+        match self.command {
+            0x03 => self.data.get(6..),
+            _ => None,
+        }
+    }
+}
+
+impl RecieveApiFrame for FileSystemResponse {
+    fn id(&self) -> FrameId {
+        match self.source_addr {
+            Some(_) => FrameId::RemoteFileSystemResponse,
+            None => FrameId::FileSystemResponse,
+        }
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![self.frame_id];
+        let frame_type = match self.source_addr {
+            Some(addr) => {
+                body.extend_from_slice(&addr.to_be_bytes());
+                body.push(0x00); // receive options
+                FrameId::RemoteFileSystemResponse.id()
+            }
+            None => FrameId::FileSystemResponse.id(),
+        };
+        body.extend_from_slice(&[self.command, self.status]);
+        body.extend_from_slice(&self.data);
+        encode_frame(frame_type, &body)
+    }
+}
+
+/********************* Device Request ****************************************/
+
+/// Request forwarded by Digi Remote Manager to a target name the host registered
+/// for; answered with a `DeviceResponseFrame` carrying the same `request_id`
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceRequest {
+    pub request_id: u8,
+    pub target: String,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl DeviceRequest {
+    /// Parses a complete Device Request frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::DeviceRequest, "target length", 7, 1)?;
+        require(
+            &frame,
+            FrameId::DeviceRequest,
+            "target",
+            8,
+            frame[7] as usize,
+        )?;
+        let target_end = 8 + frame[7] as usize;
+        Ok(Self {
+            request_id: frame[4],
+            target: String::from_utf8_lossy(&frame[8..target_end]).into_owned(),
+            data: frame.slice(target_end..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for DeviceRequest {
+    fn id(&self) -> FrameId {
+        FrameId::DeviceRequest
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let target = self.target.as_bytes();
+        if target.len() > 0xff {
+            return Err(Error::PayloadError(
+                "Device Request target exceeds 255 bytes".to_string(),
+            ));
+        }
+        let mut body = vec![self.request_id, 0x00, 0x00, target.len() as u8];
+        body.extend_from_slice(target);
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/// Answer to the `DeviceRequest` with id `request_id`
+pub struct DeviceResponseFrame<'a> {
+    pub request_id: u8,
+    pub data: &'a [u8],
+}
+
+impl TransmitApiFrame for DeviceResponseFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::DeviceResponse
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        let mut body = vec![self.gen_frame_id(), self.request_id, 0x00];
+        body.extend_from_slice(self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceResponseStatus {
+    pub frame_id: u8,
+    pub status: u8,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl DeviceResponseStatus {
+    /// Parses a complete Device Response Status frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::DeviceResponseStatus, "status", 5, 1)?;
+        Ok(Self {
+            frame_id: frame[4],
+            status: frame[5],
+            payload: Some(frame),
+        })
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.status == 0x00
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self.status {
+            0x00 => "Success",
+            0x20 => "Request id is unknown or has expired",
+            0x21 => "Device response timed out",
+            _ => "Unknown status",
+        }
+    }
+}
+
+impl RecieveApiFrame for DeviceResponseStatus {
+    fn id(&self) -> FrameId {
+        FrameId::DeviceResponseStatus
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        encode_frame(self.id().id(), &[self.frame_id, self.status])
+    }
+}
+
+/********************* Explicit Addressing Command Frame ****************************************/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExplicitAddressingFrame<'a> {
+    pub dest_addr: Addr64,
+    pub source_endpoint: u8,
+    pub dest_endpoint: u8,
+    pub cluster_id: u16,
+    pub profile_id: u16,
+    pub broadcast_radius: u8,
+    pub options: Option<TransmitRequestOptions>,
+    pub payload: &'a [u8],
+    /// Send with frame id 0 so the radio does not generate a Transmit Status
+    pub fire_and_forget: bool,
+}
+
+impl TransmitApiFrame for ExplicitAddressingFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::ExplicitAddressing
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        check_payload_len(self.payload, 0xffff - EXPLICIT_OVERHEAD)?;
+
+        let mut packet = BytesMut::with_capacity(64);
+        let frame_id: u8 = if self.fire_and_forget {
+            0
+        } else {
+            self.gen_frame_id()
+        };
+        packet.put_u8(DELIM);
+        packet.put_u16(0); // length placeholder
+        packet.put_u8(self.id().id());
+        packet.put_u8(frame_id);
+        packet.put_u64(self.dest_addr.0);
+        packet.put_u16(0xfffe);
+        packet.put_u8(self.source_endpoint);
+        packet.put_u8(self.dest_endpoint);
+        packet.put_u16(self.cluster_id);
+        packet.put_u16(self.profile_id);
+        packet.put_u8(self.broadcast_radius);
+
+        match self.options {
+            Some(opts) => packet.put_u8(opts.compile()),
+            None => packet.put_u8(0),
+        }
+        packet.put(self.payload);
+
+        let packet_len = (packet.len() - 3) as u16;
+        packet[1] = (packet_len >> 8) as u8;
+        packet[2] = (packet_len & 0xff) as u8;
+        let chksum = self.calc_checksum(&packet[..])?;
+        packet.put_u8(chksum);
+
+        Ok(packet)
+    }
+}
+
+/********************* Explicit Rx Indicator ****************************************/
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExplicitRxIndicator {
+    pub source_addr: Addr64,
+    pub source_endpoint: u8,
+    pub dest_endpoint: u8,
+    pub cluster_id: u16,
+    pub profile_id: u16,
+    pub receive_options: u8,
+    pub data: Bytes,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl ExplicitRxIndicator {
+    /// Parses a complete Explicit Rx Indicator frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(
+            &frame,
+            FrameId::ExplicitRxIndicator,
+            "receive options",
+            20,
+            1,
+        )?;
+
+        let source_addr = Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap());
+        Ok(Self {
+            source_addr,
+            source_endpoint: frame[14],
+            dest_endpoint: frame[15],
+            cluster_id: u16::from_be_bytes([frame[16], frame[17]]),
+            profile_id: u16::from_be_bytes([frame[18], frame[19]]),
+            receive_options: frame[20],
+            data: frame.slice(21..frame.len() - 1),
+            payload: Some(frame),
+        })
+    }
+
+    /// Whether the packet was sent as a broadcast
+    pub fn is_broadcast(&self) -> bool {
+        self.receive_options & 0x02 != 0
+    }
+}
+
+impl RecieveApiFrame for ExplicitRxIndicator {
+    fn id(&self) -> FrameId {
+        FrameId::ExplicitRxIndicator
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, self.source_endpoint, self.dest_endpoint]);
+        body.extend_from_slice(&self.cluster_id.to_be_bytes());
+        body.extend_from_slice(&self.profile_id.to_be_bytes());
+        body.push(self.receive_options);
+        body.extend_from_slice(&self.data);
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* I/O Data Sample Rx Indicator ****************************************/
+
+/// One I/O sample from a remote node, sent periodically (IR) or on change (IC)
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IoSampleRxIndicator {
+    pub source_addr: Addr64,
+    pub receive_options: u8,
+    /// Bit n set when DIOn is sampled
+    pub digital_mask: u16,
+    /// Bit n set when ADn is sampled, bit 7 for the supply voltage
+    pub analog_mask: u8,
+    /// Levels of the sampled digital lines, bit n for DIOn
+    pub digital_samples: u16,
+    /// Readings of the sampled analog channels, lowest channel first
+    pub analog_samples: Vec<u16>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl IoSampleRxIndicator {
+    /// Parses a complete I/O Data Sample Rx Indicator frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(
+            &frame,
+            FrameId::IoSampleRxIndicator,
+            "analog channel mask",
+            18,
+            1,
+        )?;
+
+        let digital_mask = u16::from_be_bytes([frame[16], frame[17]]);
+        let analog_mask = frame[18];
+        let samples = &frame[19..frame.len() - 1];
+        let digital_len = if digital_mask != 0 { 2 } else { 0 };
+        let expected = digital_len + 2 * analog_mask.count_ones() as usize;
+        require(
+            &frame,
+            FrameId::IoSampleRxIndicator,
+            "samples",
+            19,
+            expected,
+        )?;
+
+        let digital_samples = if digital_mask != 0 {
+            u16::from_be_bytes([samples[0], samples[1]])
+        } else {
+            0
+        };
+        let analog_samples = samples[digital_len..expected]
+            .chunks(2)
+            .map(|reading| u16::from_be_bytes([reading[0], reading[1]]))
+            .collect();
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            receive_options: frame[14],
+            digital_mask,
+            analog_mask,
+            digital_samples,
+            analog_samples,
+            payload: Some(frame),
+        })
+    }
+
+    /// Level of DIO`line`, `None` if it was not sampled
+    pub fn digital(&self, line: u8) -> Option<bool> {
+        let bit = 1u16.checked_shl(line as u32)?;
+        if self.digital_mask & bit == 0 {
+            return None;
+        }
+        Some(self.digital_samples & bit != 0)
+    }
+
+    /// Reading of AD`channel` (7 for the supply voltage), `None` if it was not sampled
+    pub fn analog(&self, channel: u8) -> Option<u16> {
+        let bit = 1u8.checked_shl(channel as u32)?;
+        if self.analog_mask & bit == 0 {
+            return None;
+        }
+        let index = (self.analog_mask & (bit - 1)).count_ones() as usize;
+        self.analog_samples.get(index).copied()
+    }
+}
+
+impl RecieveApiFrame for IoSampleRxIndicator {
+    fn id(&self) -> FrameId {
+        FrameId::IoSampleRxIndicator
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        // one sample set
+        body.extend_from_slice(&[0xff, 0xfe, self.receive_options, 0x01]);
+        body.extend_from_slice(&self.digital_mask.to_be_bytes());
+        body.push(self.analog_mask);
+        if self.digital_mask != 0 {
+            body.extend_from_slice(&self.digital_samples.to_be_bytes());
+        }
+        for reading in &self.analog_samples {
+            body.extend_from_slice(&reading.to_be_bytes());
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Route Information Packet ****************************************/
+
+/// One hop of a DigiMesh unicast, reported when the transmission was sent with
+/// trace route or NACK enabled in `TransmitRequestOptions`
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteInformation {
+    /// 0x11 for a NACK, 0x12 for a trace route hop
+    pub source_event: u8,
+    /// Timer of the reporting node, in microseconds
+    pub timestamp: u32,
+    pub ack_timeout_count: u8,
+    pub tx_blocked_count: u8,
+    pub dest_addr: Addr64,
+    pub source_addr: Addr64,
+    /// Node that relayed the packet on this hop
+    pub responder_addr: Addr64,
+    /// Node the responder handed the packet to
+    pub receiver_addr: Addr64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl RouteInformation {
+    /// Parses a complete Route Information Packet, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::RouteInformation, "receiver address", 37, 8)?;
+
+        let addr =
+            |at: usize| Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[at..at + 8]).unwrap());
+        Ok(Self {
+            source_event: frame[4],
+            timestamp: u32::from_be_bytes(<[u8; 4]>::try_from(&frame[6..10]).unwrap()),
+            ack_timeout_count: frame[10],
+            tx_blocked_count: frame[11],
+            dest_addr: addr(13),
+            source_addr: addr(21),
+            responder_addr: addr(29),
+            receiver_addr: addr(37),
+            payload: Some(frame),
+        })
+    }
+
+    /// Whether the hop was reported because it failed to be acknowledged
+    pub fn is_nack(&self) -> bool {
+        self.source_event == 0x11
+    }
+}
+
+impl RecieveApiFrame for RouteInformation {
+    fn id(&self) -> FrameId {
+        FrameId::RouteInformation
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![self.source_event, 0x2a];
+        body.extend_from_slice(&self.timestamp.to_be_bytes());
+        body.extend_from_slice(&[self.ack_timeout_count, self.tx_blocked_count, 0x00]);
+        for addr in &[
+            self.dest_addr,
+            self.source_addr,
+            self.responder_addr,
+            self.receiver_addr,
+        ] {
+            body.extend_from_slice(&addr.to_be_bytes());
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* XBee Sensor Read Indicator ****************************************/
+
+/// Reading from a Digi XBee Sensor or 1-wire sensor adapter. The unit
+/// conversions follow the channel assignment of the XBee Sensor /L/T/H:
+/// light on AD1, humidity on AD3 and temperature from the onboard sensor.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SensorReadIndicator {
+    pub source_addr: Addr64,
+    pub receive_options: u8,
+    /// Bitmask of the attached 1-wire sensor types
+    pub sensors: u8,
+    /// Raw A/D readings AD0..AD3, 0xffff where the channel is not present
+    pub analog: [u16; 4],
+    /// Raw onboard temperature reading, 0xffff when not present
+    pub raw_temperature: u16,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl SensorReadIndicator {
+    /// Parses a complete XBee Sensor Read Indicator, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::SensorReadIndicator, "temperature", 24, 2)?;
+
+        let word = |at: usize| u16::from_be_bytes([frame[at], frame[at + 1]]);
+        let analog = [word(16), word(18), word(20), word(22)];
+        let raw_temperature = word(24);
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            receive_options: frame[14],
+            sensors: frame[15],
+            analog,
+            raw_temperature,
+            payload: Some(frame),
+        })
+    }
+
+    /// Voltage on AD`channel` in millivolts, from the 10-bit reading of a 5.1 V range
+    pub fn analog_mv(&self, channel: usize) -> Option<f32> {
+        match self.analog.get(channel) {
+            Some(&raw) if raw != 0xffff => Some(raw as f32 * 5100.0 / 1023.0),
+            _ => None,
+        }
+    }
+
+    /// Onboard temperature in degrees Celsius, a 12-bit two's complement
+    /// value in 1/16 degree steps
+    pub fn temperature_c(&self) -> Option<f32> {
+        if self.raw_temperature == 0xffff {
+            return None;
+        }
+        let raw = self.raw_temperature & 0x0fff;
+        let signed = if raw >= 0x0800 {
+            raw as i32 - 0x1000
+        } else {
+            raw as i32
+        };
+        Some(signed as f32 / 16.0)
+    }
+
+    /// Illuminance in lux, which the light sensor reports as millivolts on AD1
+    pub fn light_lux(&self) -> Option<f32> {
+        self.analog_mv(1)
+    }
+
+    /// Relative humidity in percent from AD3, compensated for temperature when
+    /// the onboard sensor is present
+    pub fn humidity(&self) -> Option<f32> {
+        let mv = self.analog_mv(3)?;
+        let rh = ((mv * 108.2 / 33.2) / 5000.0 - 0.16) / 0.0062;
+        Some(match self.temperature_c() {
+            Some(t) => rh / (1.0546 - 0.00216 * t),
+            None => rh,
+        })
+    }
+}
+
+impl RecieveApiFrame for SensorReadIndicator {
+    fn id(&self) -> FrameId {
+        FrameId::SensorReadIndicator
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, self.receive_options, self.sensors]);
+        for reading in self.analog.iter().chain(Some(&self.raw_temperature)) {
+            body.extend_from_slice(&reading.to_be_bytes());
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Route Record Indicator ****************************************/
+
+/// Path a packet took to a many-to-one concentrator
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RouteRecordIndicator {
+    pub source_addr: Addr64,
+    pub source_addr_16: Addr16,
+    pub receive_options: u8,
+    /// 16-bit addresses of the relaying nodes, closest to the source first
+    pub hops: Vec<u16>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl RouteRecordIndicator {
+    /// Parses a complete Route Record Indicator frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(
+            &frame,
+            FrameId::RouteRecordIndicator,
+            "number of addresses",
+            15,
+            1,
+        )?;
+
+        let count = frame[15] as usize;
+        require(
+            &frame,
+            FrameId::RouteRecordIndicator,
+            "addresses",
+            16,
+            2 * count,
+        )?;
+        let addresses = &frame[16..frame.len() - 1];
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            source_addr_16: Addr16::from_be_bytes([frame[12], frame[13]]),
+            receive_options: frame[14],
+            hops: addresses[..2 * count]
+                .chunks(2)
+                .map(|addr| u16::from_be_bytes([addr[0], addr[1]]))
+                .collect(),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for RouteRecordIndicator {
+    fn id(&self) -> FrameId {
+        FrameId::RouteRecordIndicator
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        if self.hops.len() > 0xff {
+            return Err(Error::PayloadError(
+                "Route record lists more than 255 hops".to_string(),
+            ));
+        }
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&self.source_addr_16.to_be_bytes());
+        body.extend_from_slice(&[self.receive_options, self.hops.len() as u8]);
+        for hop in &self.hops {
+            body.extend_from_slice(&hop.to_be_bytes());
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Many-to-One Route Request Indicator ****************************************/
+
+/// A remote concentrator announced itself with a many-to-one route request
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ManyToOneRouteRequest {
+    pub source_addr: Addr64,
+    pub source_addr_16: Addr16,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl ManyToOneRouteRequest {
+    /// Parses a complete Many-to-One Route Request Indicator, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::ManyToOneRouteRequest, "reserved", 14, 1)?;
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            source_addr_16: Addr16::from_be_bytes([frame[12], frame[13]]),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for ManyToOneRouteRequest {
+    fn id(&self) -> FrameId {
+        FrameId::ManyToOneRouteRequest
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&self.source_addr_16.to_be_bytes());
+        body.push(0x00); // reserved
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Over-the-Air Firmware Update Status ****************************************/
+
+/// Bootloader message reported while updating a remote node over the air
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OtaMessage {
+    /// The target accepted the block
+    Ack,
+    /// The target rejected the block; it should be sent again
+    Nack,
+    /// The target did not answer at the MAC level
+    NoMacAck,
+    /// The target asked whether an update is available
+    Query,
+    /// The target answered a query from the update server
+    QueryResponse,
+    Other(u8),
+}
+
+impl OtaMessage {
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0x06 => OtaMessage::Ack,
+            0x15 => OtaMessage::Nack,
+            0x00 => OtaMessage::NoMacAck,
+            0x51 => OtaMessage::Query,
+            0x52 => OtaMessage::QueryResponse,
+            other => OtaMessage::Other(other),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            OtaMessage::Ack => 0x06,
+            OtaMessage::Nack => 0x15,
+            OtaMessage::NoMacAck => 0x00,
+            OtaMessage::Query => 0x51,
+            OtaMessage::QueryResponse => 0x52,
+            OtaMessage::Other(value) => value,
+        }
+    }
+}
+
+/// Progress of an over-the-air firmware update driven through the local radio
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OtaUpdateStatus {
+    pub source_addr: Addr64,
+    pub receive_options: u8,
+    pub message: OtaMessage,
+    pub block_number: u8,
+    /// Node being updated
+    pub target_addr: Addr64,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl OtaUpdateStatus {
+    /// Parses a complete Over-the-Air Firmware Update Status, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        check_fixed(&frame, FrameId::OtaUpdateStatus, "target address", 17, 8)?;
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            receive_options: frame[14],
+            message: OtaMessage::from_value(frame[15]),
+            block_number: frame[16],
+            target_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[17..25]).unwrap()),
+            payload: Some(frame),
+        })
+    }
+
+    /// Whether the block failed and has to be sent again
+    pub fn is_failure(&self) -> bool {
+        matches!(self.message, OtaMessage::Nack | OtaMessage::NoMacAck)
+    }
+}
+
+impl RecieveApiFrame for OtaUpdateStatus {
+    fn id(&self) -> FrameId {
+        FrameId::OtaUpdateStatus
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[
+            0xff,
+            0xfe,
+            self.receive_options,
+            self.message.value(),
+            self.block_number,
+        ]);
+        body.extend_from_slice(&self.target_addr.to_be_bytes());
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Node Identification Indicator ****************************************/
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceType {
+    Coordinator,
+    Router,
+    EndDevice,
+    Other(u8),
+}
+
+impl DeviceType {
+    pub fn value(&self) -> u8 {
+        match *self {
+            DeviceType::Coordinator => 0,
+            DeviceType::Router => 1,
+            DeviceType::EndDevice => 2,
+            DeviceType::Other(value) => value,
+        }
+    }
+}
+
+/// What made the remote node identify itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NodeIdEvent {
+    Pushbutton,
+    Joined,
+    PowerCycle,
+    Other(u8),
+}
+
+impl NodeIdEvent {
+    pub fn value(&self) -> u8 {
+        match *self {
+            NodeIdEvent::Pushbutton => 1,
+            NodeIdEvent::Joined => 2,
+            NodeIdEvent::PowerCycle => 3,
+            NodeIdEvent::Other(value) => value,
+        }
+    }
+}
+
+/// Sent by a remote node when its commissioning button is pressed, or when it
+/// joins or powers up
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NodeIdentificationIndicator {
+    /// Node that relayed the indicator, which is the remote node itself on DigiMesh
+    pub source_addr: Addr64,
+    pub receive_options: u8,
+    pub remote_addr: Addr64,
+    /// Raw NI bytes of the remote node
+    pub node_id: Vec<u8>,
+    pub device_type: DeviceType,
+    pub source_event: NodeIdEvent,
+    pub profile_id: u16,
+    pub manufacturer_id: u16,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl NodeIdentificationIndicator {
+    /// Parses a complete Node Identification Indicator frame, delimiter through checksum
+    pub fn from_frame(frame: BytesMut) -> Result<Self> {
+        let frame = frame.freeze();
+        // fixed fields before and after NI, NI terminator and checksum
+        check_fixed(
+            &frame,
+            FrameId::NodeIdentificationIndicator,
+            "remote address",
+            17,
+            8,
+        )?;
+
+        let ni_len = frame[25..frame.len() - 1]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| {
+                DecodeError::invalid(
+                    FrameId::NodeIdentificationIndicator,
+                    "node identifier",
+                    25,
+                    "no terminating null byte".to_string(),
+                )
+            })?;
+        // parent address, device type, source event, profile and manufacturer
+        require(
+            &frame,
+            FrameId::NodeIdentificationIndicator,
+            "parent address",
+            25 + ni_len + 1,
+            8,
+        )?;
+        let trailer = &frame[25 + ni_len + 1..frame.len() - 1];
+
+        Ok(Self {
+            source_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[4..12]).unwrap()),
+            receive_options: frame[14],
+            remote_addr: Addr64::from_be_bytes(<[u8; 8]>::try_from(&frame[17..25]).unwrap()),
+            node_id: frame[25..25 + ni_len].to_vec(),
+            device_type: match trailer[2] {
+                0 => DeviceType::Coordinator,
+                1 => DeviceType::Router,
+                2 => DeviceType::EndDevice,
+                other => DeviceType::Other(other),
+            },
+            source_event: match trailer[3] {
+                1 => NodeIdEvent::Pushbutton,
+                2 => NodeIdEvent::Joined,
+                3 => NodeIdEvent::PowerCycle,
+                other => NodeIdEvent::Other(other),
+            },
+            profile_id: u16::from_be_bytes([trailer[4], trailer[5]]),
+            manufacturer_id: u16::from_be_bytes([trailer[6], trailer[7]]),
+            payload: Some(frame),
+        })
+    }
+}
+
+impl RecieveApiFrame for NodeIdentificationIndicator {
+    fn id(&self) -> FrameId {
+        FrameId::NodeIdentificationIndicator
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = self.source_addr.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, self.receive_options, 0xff, 0xfe]);
+        body.extend_from_slice(&self.remote_addr.to_be_bytes());
+        body.extend_from_slice(&self.node_id);
+        // NI terminator, then the unknown parent address
+        body.extend_from_slice(&[0x00, 0xff, 0xfe]);
+        body.extend_from_slice(&[self.device_type.value(), self.source_event.value()]);
+        body.extend_from_slice(&self.profile_id.to_be_bytes());
+        body.extend_from_slice(&self.manufacturer_id.to_be_bytes());
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/********************* Remote AtCommand Frame ****************************************/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RemoteCommandOptions {
+    pub disable_ack: bool,
+    /// Apply a new value right away instead of waiting for AC or WR
+    pub apply_changes: bool,
+    /// Encrypt with the secure session set up with the remote device (XBee 3)
+    pub secure_session: bool,
+    /// Use the extended transmission timeout, for destinations that sleep
+    pub extended_timeout: bool,
+}
+
+impl RemoteCommandOptions {
+    pub fn compile(&self) -> u8 {
+        let mut val: u8 = 0;
+        if self.disable_ack {
+            val |= 0x01;
+        }
+        if self.apply_changes {
+            val |= 0x02;
+        }
+        if self.secure_session {
+            val |= 0x10;
+        }
+        if self.extended_timeout {
+            val |= 0x40;
+        }
+        val
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteAtCommandFrame<'a> {
+    pub dest_addr: Addr64,
+    pub options: RemoteCommandOptions,
+    pub atcmd: &'a str,
+    pub cmd_param: Option<&'a [u8]>,
+    /// Send with frame id 0 so the remote device does not send a response
+    pub fire_and_forget: bool,
+}
+
+impl<'a> RemoteAtCommandFrame<'a> {
+    pub fn builder() -> RemoteAtCommandBuilder<'a> {
+        RemoteAtCommandBuilder {
+            dest_addr: None,
+            atcmd: "",
+            cmd_param: None,
+            options: RemoteCommandOptions {
+                apply_changes: true,
+                ..RemoteCommandOptions::default()
+            },
+            fire_and_forget: false,
+        }
+    }
+}
+
+/// Builds a `RemoteAtCommandFrame`. Changes are applied right away unless
+/// `queue_changes` is called.
+#[derive(Debug)]
+pub struct RemoteAtCommandBuilder<'a> {
+    dest_addr: Option<Addr64>,
+    atcmd: &'a str,
+    cmd_param: Option<&'a [u8]>,
+    options: RemoteCommandOptions,
+    fire_and_forget: bool,
+}
+
+impl<'a> RemoteAtCommandBuilder<'a> {
+    pub fn dest(mut self, addr: impl Into<Addr64>) -> Self {
+        self.dest_addr = Some(addr.into());
+        self
+    }
+
+    pub fn broadcast(self) -> Self {
+        self.dest(constants::BROADCAST)
+    }
+
+    pub fn command(mut self, cmd: &'a str) -> Self {
+        self.atcmd = cmd;
+        self
+    }
+
+    pub fn param(mut self, param: &'a [u8]) -> Self {
+        self.cmd_param = Some(param);
+        self
+    }
+
+    /// Only queue a new value; it takes effect once AC or WR is sent
+    pub fn queue_changes(mut self) -> Self {
+        self.options.apply_changes = false;
+        self
+    }
+
+    pub fn disable_ack(mut self) -> Self {
+        self.options.disable_ack = true;
+        self
+    }
+
+    pub fn secure_session(mut self) -> Self {
+        self.options.secure_session = true;
+        self
+    }
+
+    pub fn extended_timeout(mut self) -> Self {
+        self.options.extended_timeout = true;
+        self
+    }
+
+    pub fn fire_and_forget(mut self) -> Self {
+        self.fire_and_forget = true;
+        self
+    }
+
+    pub fn build(self) -> Result<RemoteAtCommandFrame<'a>> {
+        let dest_addr = self
+            .dest_addr
+            .ok_or_else(|| Error::FrameError("Remote AT Command has no destination".to_string()))?;
+        validate_at_command(self.atcmd, self.cmd_param)?;
+        if self.options.secure_session && dest_addr == Addr64::BROADCAST {
+            return Err(Error::FrameError(
+                "Secure sessions cannot be used for broadcasts".to_string(),
+            ));
+        }
+        Ok(RemoteAtCommandFrame {
+            dest_addr,
+            options: self.options,
+            atcmd: self.atcmd,
+            cmd_param: self.cmd_param,
+            fire_and_forget: self.fire_and_forget,
+        })
+    }
+}
+
+impl TransmitApiFrame for RemoteAtCommandFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::RemoteAtCommand
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        validate_at_command(self.atcmd, self.cmd_param)?;
+
+        let mut packet = BytesMut::with_capacity(64);
+        let frame_id: u8 = if self.fire_and_forget {
+            0
+        } else {
+            self.gen_frame_id()
+        };
+        packet.put_u8(DELIM);
+        packet.put_u16(0); // length; just to initalize
+        packet.put_u8(self.id().id());
+        packet.put_u8(frame_id);
+        packet.put_u64(self.dest_addr.0);
+        packet.put_u16(0xfffe);
+
+        packet.put_u8(self.options.compile());
+
+        packet.put(self.atcmd.as_bytes());
+
+        if let Some(param) = self.cmd_param {
+            packet.put(param);
+        }
+
+        // change length here
+        let packet_len = (packet.len() - 3) as u16;
+        packet[1] = (packet_len >> 8) as u8;
+        packet[2] = (packet_len & 0xff) as u8;
+        // checksum now
+        let chksum = self.calc_checksum(&packet[..])?;
+        packet.put_u8(chksum);
+
+        Ok(packet)
+    }
+}
+
+/// Status byte of an AT Command Response or Remote AT Command Response
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AtCommandStatus {
+    Ok,
+    Error,
+    InvalidCommand,
+    InvalidParameter,
+    /// Only in remote responses: the request never reached the remote device
+    TxFailure,
+    Other(u8),
+}
+
+impl AtCommandStatus {
+    pub fn from_value(value: u8) -> Self {
+        match value {
+            0x00 => AtCommandStatus::Ok,
+            0x01 => AtCommandStatus::Error,
+            0x02 => AtCommandStatus::InvalidCommand,
+            0x03 => AtCommandStatus::InvalidParameter,
+            0x04 => AtCommandStatus::TxFailure,
+            other => AtCommandStatus::Other(other),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            AtCommandStatus::Ok => 0x00,
+            AtCommandStatus::Error => 0x01,
+            AtCommandStatus::InvalidCommand => 0x02,
+            AtCommandStatus::InvalidParameter => 0x03,
+            AtCommandStatus::TxFailure => 0x04,
+            AtCommandStatus::Other(value) => value,
+        }
+    }
+}
+
+/// Command data of a response, as long as its status is OK
+fn checked_command_data(at_command: &[u8], status: u8, data: &Option<Bytes>) -> Result<Bytes> {
+    match AtCommandStatus::from_value(status) {
+        AtCommandStatus::Ok => Ok(data.clone().unwrap_or_default()),
+        status => Err(Error::AtCommandStatus {
+            command: String::from_utf8_lossy(at_command).into_owned(),
+            status,
+        }),
+    }
+}
+
+/********************* Remote Command Response Frame ****************************************/
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RemoteAtCommandResponse {
+    pub frame_id: u8,
+    pub dest_addr: Addr64,
+    pub at_command: Vec<u8>,
+    pub command_status: u8,
+    pub command_data: Option<Bytes>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    payload: Option<Bytes>,
+}
+
+impl std::fmt::Debug for RemoteAtCommandResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let atcmd = String::from_utf8_lossy(&self.at_command[..]);
+
+        let cmd_data = match self.command_data {
+            Some(ref data) => format!("{:x?}", &data[..]),
+            None => "None".to_string(),
+        };
+
+        f.debug_struct("AtCommandResponse")
+            .field("FrameId", &format!("0x{:02x?}", self.frame_id))
+            .field("Dest Addr", &self.dest_addr.to_string())
+            .field("AtCommand", &atcmd)
+            .field("Command Status", &format!("{}", self.command_status))
+            .field("Command Data", &cmd_data)
+            .finish()
+    }
+}
+
+impl RemoteAtCommandResponse {
+    /// Parses a complete Remote AT Command Response frame, delimiter through checksum
+    pub fn from_frame(buffer: BytesMut) -> Result<Self> {
+        let buffer = buffer.freeze();
+        check_fixed(
+            &buffer,
+            FrameId::RemoteAtCommandResponse,
+            "command status",
+            17,
+            1,
+        )?;
+
+        let mut cmd_data = None;
+        if buffer.len() > 18 {
+            cmd_data = Some(buffer.slice(18..buffer.len() - 1));
+        }
+        let at_cmd: Vec<u8> = vec![buffer[15], buffer[16]];
+        let dest_buf = &buffer[5..13];
+        let dest_addr = Addr64::from_be_bytes(<[u8; 8]>::try_from(dest_buf).unwrap()); // messy but works
+        Ok(Self {
+            frame_id: buffer[4],
+            dest_addr,
+            at_command: at_cmd,
+            command_status: buffer[17],
+            command_data: cmd_data,
+            payload: Some(buffer),
+        })
+    }
+
+    pub fn status(&self) -> AtCommandStatus {
+        AtCommandStatus::from_value(self.command_status)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status() == AtCommandStatus::Ok
+    }
+
+    /// Command data of the response, empty if there was none. Fails when
+    /// the status is not OK, since the data is meaningless then.
+    pub fn command_data(&self) -> Result<Bytes> {
+        checked_command_data(&self.at_command, self.command_status, &self.command_data)
+    }
+}
+
+impl RecieveApiFrame for RemoteAtCommandResponse {
+    fn id(&self) -> FrameId {
+        FrameId::RemoteAtCommandResponse
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![self.frame_id];
+        body.extend_from_slice(&self.dest_addr.to_be_bytes());
+        body.extend_from_slice(&[0xff, 0xfe]);
+        body.extend_from_slice(&self.at_command);
+        body.push(self.command_status);
+        if let Some(ref data) = self.command_data {
+            body.extend_from_slice(data);
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+/********************* AtCommand Frame ****************************************/
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtCommandFrame<'a>(pub &'a str, pub Option<&'a [u8]>);
+
+impl<'a> AtCommandFrame<'a> {
+    /// Creates the frame, rejecting commands that are not two ASCII characters
+    /// or parameters that are too long
+    pub fn new(cmd: &'a str, param: Option<&'a [u8]>) -> Result<Self> {
+        validate_at_command(cmd, param)?;
+        Ok(AtCommandFrame(cmd, param))
+    }
+}
+
+impl TransmitApiFrame for AtCommandFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::AtCommand
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        validate_at_command(self.0, self.1)?;
+
+        let mut packet = BytesMut::with_capacity(9);
         let frame_id: u8 = self.gen_frame_id();
         packet.put_u8(DELIM);
         packet.put_u16(0); // length 0 just a placeholder
@@ -452,7 +4452,7 @@ impl TransmitApiFrame for AtCommandFrame<'_> {
         packet.put_u8(frame_id);
         packet.put(self.0.as_bytes());
         if let Some(param) = self.1 {
-            packet.put(&param[..])
+            packet.put(param)
         }
 
         let packet_len = (packet.len() - 3) as u16;
@@ -464,62 +4464,819 @@ impl TransmitApiFrame for AtCommandFrame<'_> {
     }
 }
 
+/******************* AT Command Catalog *******************/
+
+/// Parameter an AT command takes or returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtParam {
+    /// No parameter
+    None,
+    /// Big endian unsigned integer of at most this many bytes
+    Uint(usize),
+    /// ASCII text of at most this many characters
+    Text(usize),
+    /// Raw bytes of at most this many bytes
+    Bytes(usize),
+}
+
+impl AtParam {
+    /// Largest parameter in bytes
+    pub fn max_len(&self) -> usize {
+        match *self {
+            AtParam::None => 0,
+            AtParam::Uint(len) | AtParam::Text(len) | AtParam::Bytes(len) => len,
+        }
+    }
+
+    fn check(&self, cmd: AtCmd, value: &[u8]) -> Result<()> {
+        if value.len() > self.max_len() {
+            return Err(Error::InvalidAtCommand(format!(
+                "Parameter for AT command {} is {} bytes, maximum is {}",
+                cmd,
+                value.len(),
+                self.max_len()
+            )));
+        }
+        if let AtParam::Text(_) = self {
+            if !value.iter().all(|b| b.is_ascii() && !b.is_ascii_control()) {
+                return Err(Error::InvalidAtCommand(format!(
+                    "Parameter for AT command {} must be printable ASCII",
+                    cmd
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtAccess {
+    Read,
+    /// Can be set but never read back, e.g. encryption keys
+    Write,
+    ReadWrite,
+    /// Runs an action instead of reading or writing a setting
+    Exec,
+}
+
+/// AT commands of the DigiMesh firmware. Using these instead of strings
+/// catches misspelled commands at compile time, and lets `query`, `set` and
+/// `exec` reject parameters the radio would refuse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AtCmd {
+    /// Network identifier
+    Id,
+    /// Operating channel
+    Ch,
+    /// Preamble id
+    Hp,
+    /// Extra transmissions of each broadcast
+    Mt,
+    /// Unicast MAC retries
+    Rr,
+    /// Routing and messaging mode
+    Ce,
+    /// Maximum hops a message may take
+    Nh,
+    /// Maximum hops of a broadcast
+    Bh,
+    /// Network delay slots
+    Nn,
+    /// Mesh unicast retries
+    Mr,
+    /// Largest RF payload of a unicast
+    Np,
+    /// Network discovery timeout, in milliseconds
+    Nq,
+    /// Node discovery back-off, in units of 100 ms
+    Nt,
+    /// Node discovery options
+    No,
+    /// Cluster id used for transparent mode
+    Ci,
+    /// Upper half of the transparent mode destination
+    Dh,
+    /// Lower half of the transparent mode destination
+    Dl,
+    /// Upper half of the 64-bit address
+    Sh,
+    /// Lower half of the 64-bit address
+    Sl,
+    /// Node identifier
+    Ni,
+    /// Default transmit options
+    To,
+    /// Discovers every node, or the one with the given identifier
+    Nd,
+    /// Resolves a node identifier to an address
+    Dn,
+    /// Discovers neighbors one hop away
+    Fn,
+    /// Encryption enable
+    Ee,
+    /// AES encryption key, which cannot be read back
+    Ky,
+    /// Transmit power level
+    Pl,
+    /// RSSI of the last packet, in -dBm
+    Db,
+    /// Sleep mode
+    Sm,
+    /// Sleep period, in units of 10 ms
+    Sp,
+    /// Wake time, in milliseconds
+    St,
+    /// Sleep options
+    So,
+    /// Number of sleep periods between asserting ON_SLEEP
+    Sn,
+    /// Wake host delay, in milliseconds
+    Wh,
+    /// Serial baud rate
+    Bd,
+    /// Serial parity
+    Nb,
+    /// Serial stop bits
+    Sb,
+    /// Packetization timeout
+    Ro,
+    /// Guard time around the command mode escape sequence, in ms
+    Gt,
+    /// Command mode character
+    Cc,
+    /// API mode
+    Ap,
+    /// API output options
+    Ao,
+    /// Firmware version
+    Vr,
+    /// Hardware version
+    Hv,
+    /// Supply voltage, in millivolts
+    Pv,
+    /// Module temperature, in degrees Celsius
+    Tp,
+    /// Count of received packets with errors
+    Er,
+    /// Count of good received packets
+    Gd,
+    /// Count of MAC ACK timeouts
+    Ea,
+    /// Applies queued parameter changes
+    Ac,
+    /// Writes parameters to non-volatile memory
+    Wr,
+    /// Restores factory defaults
+    Re,
+    /// Resets the radio
+    Fr,
+    /// Puts a cyclic sleeping radio to sleep right away
+    Si,
+    /// Leaves command mode
+    Cn,
+}
+
+impl AtCmd {
+    pub const ALL: &'static [AtCmd] = &[
+        AtCmd::Id,
+        AtCmd::Ch,
+        AtCmd::Hp,
+        AtCmd::Mt,
+        AtCmd::Rr,
+        AtCmd::Ce,
+        AtCmd::Nh,
+        AtCmd::Bh,
+        AtCmd::Nn,
+        AtCmd::Mr,
+        AtCmd::Np,
+        AtCmd::Nq,
+        AtCmd::Nt,
+        AtCmd::No,
+        AtCmd::Ci,
+        AtCmd::Dh,
+        AtCmd::Dl,
+        AtCmd::Sh,
+        AtCmd::Sl,
+        AtCmd::Ni,
+        AtCmd::To,
+        AtCmd::Nd,
+        AtCmd::Dn,
+        AtCmd::Fn,
+        AtCmd::Ee,
+        AtCmd::Ky,
+        AtCmd::Pl,
+        AtCmd::Db,
+        AtCmd::Sm,
+        AtCmd::Sp,
+        AtCmd::St,
+        AtCmd::So,
+        AtCmd::Sn,
+        AtCmd::Wh,
+        AtCmd::Bd,
+        AtCmd::Nb,
+        AtCmd::Sb,
+        AtCmd::Ro,
+        AtCmd::Gt,
+        AtCmd::Cc,
+        AtCmd::Ap,
+        AtCmd::Ao,
+        AtCmd::Vr,
+        AtCmd::Hv,
+        AtCmd::Pv,
+        AtCmd::Tp,
+        AtCmd::Er,
+        AtCmd::Gd,
+        AtCmd::Ea,
+        AtCmd::Ac,
+        AtCmd::Wr,
+        AtCmd::Re,
+        AtCmd::Fr,
+        AtCmd::Si,
+        AtCmd::Cn,
+    ];
+
+    fn spec(&self) -> (&'static str, AtParam, AtAccess) {
+        match *self {
+            AtCmd::Id => ("ID", AtParam::Uint(2), AtAccess::ReadWrite),
+            AtCmd::Ch => ("CH", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Hp => ("HP", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Mt => ("MT", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Rr => ("RR", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Ce => ("CE", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Nh => ("NH", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Bh => ("BH", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Nn => ("NN", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Mr => ("MR", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Np => ("NP", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Nq => ("N?", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Nt => ("NT", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::No => ("NO", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Ci => ("CI", AtParam::Uint(2), AtAccess::ReadWrite),
+            AtCmd::Dh => ("DH", AtParam::Uint(4), AtAccess::ReadWrite),
+            AtCmd::Dl => ("DL", AtParam::Uint(4), AtAccess::ReadWrite),
+            AtCmd::Sh => ("SH", AtParam::Uint(4), AtAccess::Read),
+            AtCmd::Sl => ("SL", AtParam::Uint(4), AtAccess::Read),
+            AtCmd::Ni => ("NI", AtParam::Text(20), AtAccess::ReadWrite),
+            AtCmd::To => ("TO", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Nd => ("ND", AtParam::Text(20), AtAccess::Exec),
+            AtCmd::Dn => ("DN", AtParam::Text(20), AtAccess::Exec),
+            AtCmd::Fn => ("FN", AtParam::Text(20), AtAccess::Exec),
+            AtCmd::Ee => ("EE", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Ky => ("KY", AtParam::Bytes(16), AtAccess::Write),
+            AtCmd::Pl => ("PL", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Db => ("DB", AtParam::Uint(1), AtAccess::Read),
+            AtCmd::Sm => ("SM", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Sp => ("SP", AtParam::Uint(3), AtAccess::ReadWrite),
+            AtCmd::St => ("ST", AtParam::Uint(3), AtAccess::ReadWrite),
+            AtCmd::So => ("SO", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Sn => ("SN", AtParam::Uint(2), AtAccess::ReadWrite),
+            AtCmd::Wh => ("WH", AtParam::Uint(2), AtAccess::ReadWrite),
+            AtCmd::Bd => ("BD", AtParam::Uint(4), AtAccess::ReadWrite),
+            AtCmd::Nb => ("NB", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Sb => ("SB", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Ro => ("RO", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Gt => ("GT", AtParam::Uint(2), AtAccess::ReadWrite),
+            AtCmd::Cc => ("CC", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Ap => ("AP", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Ao => ("AO", AtParam::Uint(1), AtAccess::ReadWrite),
+            AtCmd::Vr => ("VR", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Hv => ("HV", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Pv => ("%V", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Tp => ("TP", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Er => ("ER", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Gd => ("GD", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Ea => ("EA", AtParam::Uint(2), AtAccess::Read),
+            AtCmd::Ac => ("AC", AtParam::None, AtAccess::Exec),
+            AtCmd::Wr => ("WR", AtParam::None, AtAccess::Exec),
+            AtCmd::Re => ("RE", AtParam::None, AtAccess::Exec),
+            AtCmd::Fr => ("FR", AtParam::None, AtAccess::Exec),
+            AtCmd::Si => ("SI", AtParam::None, AtAccess::Exec),
+            AtCmd::Cn => ("CN", AtParam::None, AtAccess::Exec),
+        }
+    }
+
+    /// Two character command as sent to the radio
+    pub fn name(&self) -> &'static str {
+        self.spec().0
+    }
+
+    pub fn param(&self) -> AtParam {
+        self.spec().1
+    }
+
+    pub fn access(&self) -> AtAccess {
+        self.spec().2
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        AtCmd::ALL
+            .iter()
+            .copied()
+            .find(|cmd| cmd.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Decodes the command data of a response to this command
+    pub fn decode(&self, data: &[u8]) -> Result<ParamValue> {
+        ParamValue::decode(self.param(), data)
+    }
+
+    /// Frame reading the current value of a setting
+    pub fn query(self) -> Result<AtCommandFrame<'static>> {
+        match self.access() {
+            AtAccess::Read | AtAccess::ReadWrite => Ok(AtCommandFrame(self.name(), None)),
+            _ => Err(Error::InvalidAtCommand(format!(
+                "AT command {} cannot be read",
+                self
+            ))),
+        }
+    }
+
+    /// Frame changing a setting to `value`
+    pub fn set(self, value: &[u8]) -> Result<AtCommandFrame<'_>> {
+        match self.access() {
+            AtAccess::Write | AtAccess::ReadWrite => {
+                self.param().check(self, value)?;
+                Ok(AtCommandFrame(self.name(), Some(value)))
+            }
+            _ => Err(Error::InvalidAtCommand(format!(
+                "AT command {} cannot be set",
+                self
+            ))),
+        }
+    }
+
+    /// Frame running an action, with its optional argument
+    pub fn exec(self, arg: Option<&[u8]>) -> Result<AtCommandFrame<'_>> {
+        if self.access() != AtAccess::Exec {
+            return Err(Error::InvalidAtCommand(format!(
+                "AT command {} is a setting, not an action",
+                self
+            )));
+        }
+        if let Some(arg) = arg {
+            self.param().check(self, arg)?;
+        }
+        Ok(AtCommandFrame(self.name(), arg))
+    }
+}
+
+impl std::fmt::Display for AtCmd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl std::str::FromStr for AtCmd {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        AtCmd::from_name(s)
+            .ok_or_else(|| Error::InvalidAtCommand(format!("Unknown AT command {:?}", s)))
+    }
+}
+
+/// Value of an AT command parameter. Integers are sent big endian, the way
+/// the radio reports them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamValue {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    Text(String),
+    Bytes(Vec<u8>),
+}
+
+impl ParamValue {
+    /// Bytes to send as the parameter of an AT command
+    pub fn encode(&self) -> Vec<u8> {
+        match *self {
+            ParamValue::U8(value) => vec![value],
+            ParamValue::U16(value) => value.to_be_bytes().to_vec(),
+            ParamValue::U32(value) => value.to_be_bytes().to_vec(),
+            ParamValue::U64(value) => value.to_be_bytes().to_vec(),
+            ParamValue::Text(ref text) => text.as_bytes().to_vec(),
+            ParamValue::Bytes(ref bytes) => bytes.clone(),
+        }
+    }
+
+    /// Parameter as typed in command mode, where numbers and raw bytes are
+    /// written in hexadecimal
+    pub fn encode_command_mode(&self) -> Vec<u8> {
+        match *self {
+            ParamValue::Text(ref text) => text.as_bytes().to_vec(),
+            ParamValue::Bytes(ref bytes) => bytes
+                .iter()
+                .map(|b| format!("{:02X}", b))
+                .collect::<String>()
+                .into_bytes(),
+            _ => format!("{:X}", self.as_u64().unwrap_or_default()).into_bytes(),
+        }
+    }
+
+    /// Decodes a command mode reply, without its carriage return, the way
+    /// `param` describes it
+    pub fn decode_command_mode(param: AtParam, reply: &[u8]) -> Result<Self> {
+        let invalid = || {
+            Error::InvalidParameter(format!(
+                "{:?} is not a hexadecimal value",
+                String::from_utf8_lossy(reply)
+            ))
+        };
+        match param {
+            AtParam::Text(_) => Ok(ParamValue::Text(Self::decode_string(reply)?)),
+            AtParam::Uint(_) => {
+                let value = std::str::from_utf8(reply)
+                    .ok()
+                    .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+                    .ok_or_else(invalid)?;
+                Self::decode(param, &value.to_be_bytes())
+            }
+            AtParam::None | AtParam::Bytes(_) => {
+                let pairs = reply.chunks_exact(2);
+                if !pairs.remainder().is_empty() {
+                    return Err(invalid());
+                }
+                pairs
+                    .map(|pair| {
+                        std::str::from_utf8(pair)
+                            .ok()
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                            .ok_or_else(invalid)
+                    })
+                    .collect::<Result<Vec<u8>>>()
+                    .map(ParamValue::Bytes)
+            }
+        }
+    }
+
+    /// Decodes command data the way `param` describes it. Integers get the
+    /// narrowest variant that holds `param`'s width.
+    pub fn decode(param: AtParam, data: &[u8]) -> Result<Self> {
+        match param {
+            AtParam::Uint(1) => Ok(ParamValue::U8(Self::decode_u8(data)?)),
+            AtParam::Uint(2) => Ok(ParamValue::U16(Self::decode_u16(data)?)),
+            AtParam::Uint(3) | AtParam::Uint(4) => Ok(ParamValue::U32(Self::decode_u32(data)?)),
+            AtParam::Uint(_) => Ok(ParamValue::U64(Self::decode_u64(data)?)),
+            AtParam::Text(_) => Ok(ParamValue::Text(Self::decode_string(data)?)),
+            AtParam::None | AtParam::Bytes(_) => Ok(ParamValue::Bytes(data.to_vec())),
+        }
+    }
+
+    /// Big endian integer of any width up to 8 bytes; the radio drops leading
+    /// zero bytes for some commands, so shorter data is fine
+    pub fn decode_u64(data: &[u8]) -> Result<u64> {
+        let data = match data.iter().position(|b| *b != 0) {
+            Some(start) => &data[start..],
+            None => &[],
+        };
+        if data.len() > 8 {
+            return Err(Error::InvalidParameter(format!(
+                "{} byte value does not fit in 64 bits",
+                data.len()
+            )));
+        }
+        Ok(data.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+    }
+
+    pub fn decode_u32(data: &[u8]) -> Result<u32> {
+        Self::narrow(data, u32::try_from)
+    }
+
+    pub fn decode_u16(data: &[u8]) -> Result<u16> {
+        Self::narrow(data, u16::try_from)
+    }
+
+    pub fn decode_u8(data: &[u8]) -> Result<u8> {
+        Self::narrow(data, u8::try_from)
+    }
+
+    pub fn decode_string(data: &[u8]) -> Result<String> {
+        String::from_utf8(data.to_vec())
+            .map_err(|_| Error::InvalidParameter(format!("{:x?} is not valid UTF-8", data)))
+    }
+
+    fn narrow<T, E>(data: &[u8], convert: fn(u64) -> std::result::Result<T, E>) -> Result<T> {
+        let value = Self::decode_u64(data)?;
+        convert(value).map_err(|_| {
+            Error::InvalidParameter(format!(
+                "0x{:x} does not fit in {} bytes",
+                value,
+                std::mem::size_of::<T>()
+            ))
+        })
+    }
+
+    /// The value as an integer, if it is one
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            ParamValue::U8(value) => Some(value as u64),
+            ParamValue::U16(value) => Some(value as u64),
+            ParamValue::U32(value) => Some(value as u64),
+            ParamValue::U64(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            ParamValue::Text(ref text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_param_value_from {
+    ($($ty:ty => $variant:ident),* $(,)?) => {
+        $(
+            impl From<$ty> for ParamValue {
+                fn from(value: $ty) -> Self {
+                    ParamValue::$variant(value.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_param_value_from!(
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    u64 => U64,
+    String => Text,
+    &str => Text,
+    Vec<u8> => Bytes,
+    &[u8] => Bytes,
+);
+
+/******************* AtCommand Queue Frame *******************/
+
+/// Like `AtCommandFrame`, but a new parameter value is only queued and takes
+/// effect once AC (or WR) is sent, so several changes can be applied together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AtCommandQueueFrame<'a>(pub &'a str, pub Option<&'a [u8]>);
+
+impl<'a> AtCommandQueueFrame<'a> {
+    pub fn new(cmd: &'a str, param: Option<&'a [u8]>) -> Result<Self> {
+        validate_at_command(cmd, param)?;
+        Ok(AtCommandQueueFrame(cmd, param))
+    }
+}
+
+impl TransmitApiFrame for AtCommandQueueFrame<'_> {
+    fn id(&self) -> FrameId {
+        FrameId::AtCommandQueue
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        validate_at_command(self.0, self.1)?;
+
+        let mut body = vec![self.gen_frame_id()];
+        body.extend_from_slice(self.0.as_bytes());
+        if let Some(param) = self.1 {
+            body.extend_from_slice(param);
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+/******************* Owned Frames *******************/
+
+/// `TransmitRequestFrame` owning its payload, so it can be queued, retried or
+/// handed to another thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedTransmitRequest {
+    pub dest_addr: Addr64,
+    pub dest_addr_16: Option<Addr16>,
+    pub broadcast_radius: u8,
+    pub options: Option<TransmitRequestOptions>,
+    pub payload: Vec<u8>,
+    pub fire_and_forget: bool,
+}
+
+impl OwnedTransmitRequest {
+    pub fn as_frame(&self) -> TransmitRequestFrame<'_> {
+        TransmitRequestFrame {
+            dest_addr: self.dest_addr,
+            dest_addr_16: self.dest_addr_16,
+            broadcast_radius: self.broadcast_radius,
+            options: self.options,
+            payload: &self.payload,
+            fire_and_forget: self.fire_and_forget,
+        }
+    }
+}
+
+impl From<TransmitRequestFrame<'_>> for OwnedTransmitRequest {
+    fn from(frame: TransmitRequestFrame<'_>) -> Self {
+        Self {
+            dest_addr: frame.dest_addr,
+            dest_addr_16: frame.dest_addr_16,
+            broadcast_radius: frame.broadcast_radius,
+            options: frame.options,
+            payload: frame.payload.to_vec(),
+            fire_and_forget: frame.fire_and_forget,
+        }
+    }
+}
+
+impl TransmitApiFrame for OwnedTransmitRequest {
+    fn id(&self) -> FrameId {
+        FrameId::TransmitRequest
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        self.as_frame().gen()
+    }
+}
+
+/// `ExplicitAddressingFrame` owning its payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedExplicitAddressing {
+    pub dest_addr: Addr64,
+    pub source_endpoint: u8,
+    pub dest_endpoint: u8,
+    pub cluster_id: u16,
+    pub profile_id: u16,
+    pub broadcast_radius: u8,
+    pub options: Option<TransmitRequestOptions>,
+    pub payload: Vec<u8>,
+    pub fire_and_forget: bool,
+}
+
+impl OwnedExplicitAddressing {
+    pub fn as_frame(&self) -> ExplicitAddressingFrame<'_> {
+        ExplicitAddressingFrame {
+            dest_addr: self.dest_addr,
+            source_endpoint: self.source_endpoint,
+            dest_endpoint: self.dest_endpoint,
+            cluster_id: self.cluster_id,
+            profile_id: self.profile_id,
+            broadcast_radius: self.broadcast_radius,
+            options: self.options,
+            payload: &self.payload,
+            fire_and_forget: self.fire_and_forget,
+        }
+    }
+}
+
+impl From<ExplicitAddressingFrame<'_>> for OwnedExplicitAddressing {
+    fn from(frame: ExplicitAddressingFrame<'_>) -> Self {
+        Self {
+            dest_addr: frame.dest_addr,
+            source_endpoint: frame.source_endpoint,
+            dest_endpoint: frame.dest_endpoint,
+            cluster_id: frame.cluster_id,
+            profile_id: frame.profile_id,
+            broadcast_radius: frame.broadcast_radius,
+            options: frame.options,
+            payload: frame.payload.to_vec(),
+            fire_and_forget: frame.fire_and_forget,
+        }
+    }
+}
+
+impl TransmitApiFrame for OwnedExplicitAddressing {
+    fn id(&self) -> FrameId {
+        FrameId::ExplicitAddressing
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        self.as_frame().gen()
+    }
+}
+
+/// `RemoteAtCommandFrame` owning its command and parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedRemoteAtCommand {
+    pub dest_addr: Addr64,
+    pub options: RemoteCommandOptions,
+    pub atcmd: String,
+    pub cmd_param: Option<Vec<u8>>,
+    pub fire_and_forget: bool,
+}
+
+impl OwnedRemoteAtCommand {
+    pub fn as_frame(&self) -> RemoteAtCommandFrame<'_> {
+        RemoteAtCommandFrame {
+            dest_addr: self.dest_addr,
+            options: self.options,
+            atcmd: &self.atcmd,
+            cmd_param: self.cmd_param.as_deref(),
+            fire_and_forget: self.fire_and_forget,
+        }
+    }
+}
+
+impl From<RemoteAtCommandFrame<'_>> for OwnedRemoteAtCommand {
+    fn from(frame: RemoteAtCommandFrame<'_>) -> Self {
+        Self {
+            dest_addr: frame.dest_addr,
+            options: frame.options,
+            atcmd: frame.atcmd.to_string(),
+            cmd_param: frame.cmd_param.map(<[u8]>::to_vec),
+            fire_and_forget: frame.fire_and_forget,
+        }
+    }
+}
+
+impl TransmitApiFrame for OwnedRemoteAtCommand {
+    fn id(&self) -> FrameId {
+        FrameId::RemoteAtCommand
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        self.as_frame().gen()
+    }
+}
+
+/// Local AT command owning its command and parameter. With `queued` set it is
+/// sent as an `AtCommandQueueFrame`, otherwise as an `AtCommandFrame`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedAtCommand {
+    pub command: String,
+    pub param: Option<Vec<u8>>,
+    pub queued: bool,
+}
+
+impl From<AtCommandFrame<'_>> for OwnedAtCommand {
+    fn from(frame: AtCommandFrame<'_>) -> Self {
+        Self {
+            command: frame.0.to_string(),
+            param: frame.1.map(<[u8]>::to_vec),
+            queued: false,
+        }
+    }
+}
+
+impl From<AtCommandQueueFrame<'_>> for OwnedAtCommand {
+    fn from(frame: AtCommandQueueFrame<'_>) -> Self {
+        Self {
+            command: frame.0.to_string(),
+            param: frame.1.map(<[u8]>::to_vec),
+            queued: true,
+        }
+    }
+}
+
+impl TransmitApiFrame for OwnedAtCommand {
+    fn id(&self) -> FrameId {
+        if self.queued {
+            FrameId::AtCommandQueue
+        } else {
+            FrameId::AtCommand
+        }
+    }
+
+    fn gen(&self) -> Result<BytesMut> {
+        if self.queued {
+            AtCommandQueueFrame(&self.command, self.param.as_deref()).gen()
+        } else {
+            AtCommandFrame(&self.command, self.param.as_deref()).gen()
+        }
+    }
+}
+
 /******************* AtCommand Response Frame *******************/
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AtCommandResponse {
     pub frame_id: u8,
     pub at_command: Vec<u8>,
     pub command_status: u8,
-    pub command_data: Option<BytesMut>,
-    pub payload: Option<BytesMut>,
+    pub command_data: Option<Bytes>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub payload: Option<Bytes>,
 }
 
 impl std::fmt::Debug for AtCommandResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let atcmd = std::str::from_utf8(&self.at_command[..]).ok();
+        let atcmd = String::from_utf8_lossy(&self.at_command[..]);
 
         let cmd_data = match self.command_data {
             Some(ref data) => format!("{:x?}", &data[..]),
-            None => format!("None"),
+            None => "None".to_string(),
         };
 
         f.debug_struct("AtCommandResponse")
             .field("FrameId", &format!("0x{:02x?}", self.frame_id))
-            .field("AtCommand", &format!("{}", atcmd.unwrap()))
+            .field("AtCommand", &atcmd)
             .field("Command Status", &format!("{}", self.command_status))
             .field("Command Data", &cmd_data)
             .finish()
     }
 }
 
-impl RecieveApiFrame for AtCommandResponse {
-    fn id(&self) -> FrameId {
-        FrameId::AtCommandResponse
-    }
+impl AtCommandResponse {
+    /// Parses a complete AT Command Response frame, delimiter through checksum
+    pub fn from_frame(buffer: BytesMut) -> Result<Self> {
+        let buffer = buffer.freeze();
+        check_fixed(&buffer, FrameId::AtCommandResponse, "command status", 7, 1)?;
 
-    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
-        let mut buffer = BytesMut::with_capacity(256);
-        let mut mini_buf: [u8; 1] = [0];
-        loop {
-            if let Err(err) = ser.read_exact(&mut mini_buf) {
-                if err.kind() == std::io::ErrorKind::TimedOut {
-                    break;
-                } else {
-                    return Err(Error::IOError(err));
-                }
-            }
-            buffer.put_u8(mini_buf[0]);
-        }
         let mut cmd_data = None;
         if buffer.len() > 9 {
-            cmd_data = Some(BytesMut::from(&buffer[8..buffer.len() - 1]));
-        }
-
-        if buffer.len() == 0 {
-            return Err(Error::FrameError("No frame detected".to_string()));
+            cmd_data = Some(buffer.slice(8..buffer.len() - 1));
         }
-        let mut at_cmd: Vec<u8> = Vec::new();
-        at_cmd.push(buffer[5]);
-        at_cmd.push(buffer[6]);
+        let at_cmd: Vec<u8> = vec![buffer[5], buffer[6]];
         Ok(Self {
             frame_id: buffer[4],
             at_command: at_cmd,
@@ -529,10 +5286,1325 @@ impl RecieveApiFrame for AtCommandResponse {
         })
     }
 
-    fn payload(&self) -> Result<BytesMut> {
-        match &self.payload {
-            Some(p) => Ok(p.clone()),
-            None => Err(Error::FrameError("Emtpy payload".to_string())),
+    pub fn status(&self) -> AtCommandStatus {
+        AtCommandStatus::from_value(self.command_status)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.status() == AtCommandStatus::Ok
+    }
+
+    /// Command data of the response, empty if there was none. Fails when
+    /// the status is not OK, since the data is meaningless then.
+    pub fn command_data(&self) -> Result<Bytes> {
+        checked_command_data(&self.at_command, self.command_status, &self.command_data)
+    }
+}
+
+impl RecieveApiFrame for AtCommandResponse {
+    fn id(&self) -> FrameId {
+        FrameId::AtCommandResponse
+    }
+
+    fn recieve(mut ser: Box<dyn SerialPort>) -> Result<Self> {
+        Self::from_frame(read_frame(&mut *ser)?)
+    }
+
+    fn payload(&self) -> Result<Bytes> {
+        kept_payload(&self.payload)
+    }
+
+    fn encode(&self) -> Result<BytesMut> {
+        let mut body = vec![self.frame_id];
+        body.extend_from_slice(&self.at_command);
+        body.push(self.command_status);
+        if let Some(ref data) = self.command_data {
+            body.extend_from_slice(data);
+        }
+        encode_frame(self.id().id(), &body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addr64_display() {
+        assert_eq!(Addr64(0x0013a200_40abcdef).to_string(), "0013A20040ABCDEF");
+        assert_eq!(
+            format!("{:#}", Addr64(0x0013a200_40abcdef)),
+            "0013A200-40ABCDEF"
+        );
+        assert_eq!(Addr64(0xffff).to_string(), "000000000000FFFF");
+        assert_eq!(Addr16(0xfffe).to_string(), "FFFE");
+        assert_eq!(Addr16(0x1a).to_string(), "001A");
+    }
+
+    #[test]
+    fn addr_parsing() {
+        let addr = Addr64(0x0013a200_40abcdef);
+        assert_eq!("0013A20040ABCDEF".parse::<Addr64>().unwrap(), addr);
+        assert_eq!("0013a200-40abcdef".parse::<Addr64>().unwrap(), addr);
+        assert_eq!(addr.to_string().parse::<Addr64>().unwrap(), addr);
+        assert_eq!((addr.high(), addr.low()), (0x0013a200, 0x40abcdef));
+        assert_eq!(Addr64::from_be_bytes(addr.to_be_bytes()), addr);
+        assert!("0013A200".parse::<Addr64>().is_err());
+        assert!("0013A20040ABCDEG".parse::<Addr64>().is_err());
+
+        assert_eq!("fffe".parse::<Addr16>().unwrap(), Addr16(0xfffe));
+        assert!("fffe".parse::<Addr16>().unwrap().is_unknown());
+        assert!("+fff".parse::<Addr16>().is_err());
+    }
+
+    #[test]
+    fn at_command_validation() {
+        assert!(AtCommandFrame::new("NI", Some(b"NODE")).is_ok());
+        assert!(AtCommandFrame::new("N", None).is_err());
+        assert!(AtCommandFrame::new("NID", None).is_err());
+        assert!(AtCommandFrame::new("Ñ", None).is_err());
+        assert!(AtCommandFrame::new("NI", Some(&[0; 257])).is_err());
+        assert!(AtCommandFrame("ABC", None).gen().is_err());
+
+        const NI: AtCode = at!("NI");
+        assert_eq!(NI.as_str(), "NI");
+        assert_eq!(NI.query().gen_with_frame_id(1).unwrap()[5..7], b"NI"[..]);
+        assert!(NI.set(&[0; 257]).is_err());
+        assert_eq!(AtCode::try_from("%V").unwrap().to_string(), "%V");
+        assert!(AtCode::try_from("N I").is_err());
+    }
+
+    #[test]
+    fn summary_writes_into_buffer() {
+        let mut out = String::new();
+        NullRecieve.summary(&mut out).unwrap();
+        assert_eq!(out, "NullRecieve");
+    }
+
+    #[test]
+    fn encode_raw_frame() {
+        let frame = encode_frame(0x08, &[0x01, b'N', b'I']).unwrap();
+        assert_eq!(
+            &frame[..],
+            &[0x7e, 0x00, 0x04, 0x08, 0x01, 0x4e, 0x49, 0x5f]
+        );
+    }
+
+    #[test]
+    fn parse_remote_at_response() {
+        let mut body = vec![0x05];
+        body.extend_from_slice(&0x0013a200_40abcdef_u64.to_be_bytes());
+        body.extend_from_slice(&[0xff, 0xfe, b'V', b'R', 0x00, 0x90, 0x02]);
+        let frame = encode_frame(0x97, &body).unwrap();
+
+        let response = RemoteAtCommandResponse::from_frame(frame).unwrap();
+        assert_eq!(response.frame_id, 0x05);
+        assert_eq!(response.dest_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(response.at_command, b"VR");
+        assert_eq!(response.command_status, 0);
+        assert_eq!(&response.command_data.unwrap()[..], &[0x90, 0x02]);
+    }
+
+    #[test]
+    fn parse_receive_packet() {
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0x02, b'h', b'i']);
+        let frame = encode_frame(0x90, &body).unwrap();
+
+        let packet = ReceivePacket::from_frame(frame).unwrap();
+        assert_eq!(packet.source_addr, Addr64(0x0013a200_40abcdef));
+        assert!(packet.is_broadcast());
+        assert_eq!(&packet.data[..], b"hi");
+
+        let status = encode_frame(0x8b, &[0x01, 0xff, 0xfe, 0x00, 0x00, 0x00]).unwrap();
+        assert!(ReceivePacket::from_frame(status).is_err());
+    }
+
+    #[test]
+    fn parse_explicit_rx_indicator() {
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0xe8, 0xe6, 0x00, 0x12, 0xc1, 0x05, 0x01]);
+        body.extend_from_slice(b"echo");
+        let frame = encode_frame(0x91, &body).unwrap();
+
+        let rx = ExplicitRxIndicator::from_frame(frame).unwrap();
+        assert_eq!(rx.source_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!((rx.source_endpoint, rx.dest_endpoint), (0xe8, 0xe6));
+        assert_eq!(rx.cluster_id, LOOPBACK_CLUSTER_ID);
+        assert_eq!(rx.profile_id, DIGI_PROFILE_ID);
+        assert!(!rx.is_broadcast());
+        assert_eq!(&rx.data[..], b"echo");
+    }
+
+    #[test]
+    fn parse_modem_status() {
+        let reset = ModemStatus::from_frame(encode_frame(0x8a, &[0x00]).unwrap()).unwrap();
+        assert_eq!(reset.state, ModemState::HardwareReset);
+        let woke = ModemStatus::from_frame(encode_frame(0x8a, &[0x0b]).unwrap()).unwrap();
+        assert_eq!(woke.state, ModemState::NetworkWokeUp);
+        assert_eq!(ModemState::from_value(0x3f), ModemState::PanIdChanged);
+        assert_eq!(ModemState::from_value(0x84), ModemState::StackError(0x84));
+        assert_eq!(ModemState::from_value(0x20), ModemState::Other(0x20));
+        for value in 0..=0xff {
+            assert_eq!(ModemState::from_value(value).value(), value);
+        }
+        assert!(ModemState::WatchdogReset.is_reset());
+        assert!(!ModemState::Joined.is_reset());
+    }
+
+    #[test]
+    fn parse_node_identification_indicator() {
+        let addr = 0x0013a200_40abcdef_u64.to_be_bytes();
+        let mut body = addr.to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0xc2, 0xff, 0xfe]);
+        body.extend_from_slice(&addr);
+        body.extend_from_slice(b"SENSOR\0");
+        body.extend_from_slice(&[0xff, 0xfe, 0x01, 0x01, 0xc1, 0x05, 0x10, 0x1e]);
+        let frame = encode_frame(0x95, &body).unwrap();
+
+        let indicator = NodeIdentificationIndicator::from_frame(frame).unwrap();
+        assert_eq!(indicator.remote_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(indicator.node_id, b"SENSOR");
+        assert_eq!(indicator.device_type, DeviceType::Router);
+        assert_eq!(indicator.source_event, NodeIdEvent::Pushbutton);
+        assert_eq!(indicator.profile_id, DIGI_PROFILE_ID);
+        assert_eq!(indicator.manufacturer_id, 0x101e);
+    }
+
+    #[test]
+    fn parse_io_sample() {
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        // DIO3 and DIO4 sampled, AD1 and supply voltage sampled
+        body.extend_from_slice(&[0xff, 0xfe, 0x01, 0x01, 0x00, 0x18, 0x82]);
+        body.extend_from_slice(&[0x00, 0x10, 0x02, 0x25, 0x0c, 0xe4]);
+        let frame = encode_frame(0x92, &body).unwrap();
+
+        let sample = IoSampleRxIndicator::from_frame(frame).unwrap();
+        assert_eq!(sample.source_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(sample.digital(3), Some(false));
+        assert_eq!(sample.digital(4), Some(true));
+        assert_eq!(sample.digital(0), None);
+        assert_eq!(sample.analog(1), Some(0x0225));
+        assert_eq!(sample.analog(7), Some(0x0ce4));
+        assert_eq!(sample.analog(0), None);
+    }
+
+    #[test]
+    fn parse_route_record() {
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x7d, 0x84, 0x00, 0x02, 0xc4, 0x01, 0x1a, 0x02]);
+        let frame = encode_frame(0xa1, &body).unwrap();
+
+        let record = RouteRecordIndicator::from_frame(frame).unwrap();
+        assert_eq!(record.source_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(record.source_addr_16, Addr16(0x7d84));
+        assert_eq!(record.hops, vec![0xc401, 0x1a02]);
+    }
+
+    #[test]
+    fn parse_route_information() {
+        let mut body = vec![0x12, 0x2a, 0x00, 0x01, 0xe2, 0x40, 0x02, 0x00, 0x00];
+        for addr in &[0x0003u64, 0x0001, 0x0001, 0x0002] {
+            body.extend_from_slice(&(0x0013a200_00000000 | addr).to_be_bytes());
+        }
+        let frame = encode_frame(0x8d, &body).unwrap();
+
+        let hop = RouteInformation::from_frame(frame).unwrap();
+        assert!(!hop.is_nack());
+        assert_eq!(hop.timestamp, 123_456);
+        assert_eq!(hop.ack_timeout_count, 2);
+        assert_eq!(hop.dest_addr, Addr64(0x0013a200_00000003));
+        assert_eq!(hop.responder_addr, Addr64(0x0013a200_00000001));
+        assert_eq!(hop.receiver_addr, Addr64(0x0013a200_00000002));
+    }
+
+    #[test]
+    fn explicit_addressing_frame_layout() {
+        let frame = ExplicitAddressingFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            source_endpoint: DIGI_DATA_ENDPOINT,
+            dest_endpoint: DIGI_DATA_ENDPOINT,
+            cluster_id: LOOPBACK_CLUSTER_ID,
+            profile_id: DIGI_PROFILE_ID,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"hi",
+            fire_and_forget: true,
+        };
+        let mut body = vec![0x00];
+        body.extend_from_slice(&0x0013a200_40abcdef_u64.to_be_bytes());
+        body.extend_from_slice(&[0xff, 0xfe, 0xe8, 0xe8, 0x00, 0x12, 0xc1, 0x05, 0x00, 0x00]);
+        body.extend_from_slice(b"hi");
+        assert_eq!(
+            &frame.gen().unwrap()[..],
+            &encode_frame(0x11, &body).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn at_command_queue_frame() {
+        let frame = AtCommandQueueFrame::new("NI", Some(b"A"))
+            .unwrap()
+            .gen()
+            .unwrap();
+        assert_eq!(frame[3], 0x09);
+        assert_eq!(&frame[5..8], b"NIA");
+        assert!(AtCommandQueueFrame::new("NID", None).is_err());
+    }
+
+    #[test]
+    fn source_route_hops_are_validated() {
+        let hops = [0xeeff, 0xccdd, 0xaabb];
+        let frame = CreateSourceRouteFrame {
+            dest_addr: Addr64(0x0013a200_40401122),
+            dest_addr_16: Addr16(0x3344),
+            hops: &hops,
+        };
+        let mut body = vec![0x00];
+        body.extend_from_slice(&0x0013a200_40401122_u64.to_be_bytes());
+        body.extend_from_slice(&[0x33, 0x44, 0x00, 0x03, 0xee, 0xff, 0xcc, 0xdd, 0xaa, 0xbb]);
+        assert_eq!(
+            &frame.gen().unwrap()[..],
+            &encode_frame(0x21, &body).unwrap()[..]
+        );
+
+        let too_many = [0u16; 41];
+        let frame = CreateSourceRouteFrame {
+            hops: &too_many,
+            ..frame
+        };
+        assert!(frame.gen().is_err());
+        assert!(CreateSourceRouteFrame { hops: &[], ..frame }.gen().is_err());
+    }
+
+    #[test]
+    fn register_joining_device() {
+        let key = [0x5a; 16];
+        let frame = RegisterJoiningDeviceFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            key: &key,
+            install_code: false,
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(frame[3], 0x24);
+        assert_eq!(&frame[13..16], &[0xff, 0xfe, 0x00]);
+        assert_eq!(&frame[16..32], &key[..]);
+
+        let odd_code = RegisterJoiningDeviceFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            key: &key[..9],
+            install_code: true,
+        };
+        assert!(odd_code.gen().is_err());
+
+        let status =
+            RegisterJoiningDeviceStatus::from_frame(encode_frame(0xa4, &[0x01, 0xb4]).unwrap())
+                .unwrap();
+        assert!(!status.is_success());
+        assert_eq!(status.description(), "Key table is full");
+    }
+
+    #[test]
+    fn parse_many_to_one_route_request() {
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x00, 0x00, 0x00]);
+        let frame = encode_frame(0xa3, &body).unwrap();
+
+        let request = ManyToOneRouteRequest::from_frame(frame).unwrap();
+        assert_eq!(request.source_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(request.source_addr_16, Addr16(0x0000));
+    }
+
+    #[test]
+    fn legacy_802154_frames() {
+        let tx = TxRequest16Frame {
+            dest_addr: Addr16(0x1234),
+            options: TX_OPTION_DISABLE_ACK,
+            payload: b"s1",
+            fire_and_forget: true,
+        };
+        assert_eq!(
+            &tx.gen().unwrap()[..],
+            &encode_frame(0x01, &[0x00, 0x12, 0x34, 0x01, b's', b'1']).unwrap()[..]
+        );
+        let too_long = TxRequest64Frame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            options: 0,
+            payload: &[0; 101],
+            fire_and_forget: true,
+        };
+        assert!(too_long.gen().is_err());
+
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x28, 0x00, b'h', b'i']);
+        let rx = Rx64Packet::from_frame(encode_frame(0x80, &body).unwrap()).unwrap();
+        assert_eq!(rx.source_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(rx.rssi, 0x28);
+        assert_eq!(&rx.data[..], b"hi");
+
+        let rx =
+            Rx16Packet::from_frame(encode_frame(0x81, &[0x12, 0x34, 0x30, 0x02]).unwrap()).unwrap();
+        assert_eq!(rx.source_addr, Addr16(0x1234));
+        assert!(rx.data.is_empty());
+
+        let status = TxStatus::from_frame(encode_frame(0x89, &[0x07, 0x01]).unwrap()).unwrap();
+        assert_eq!(status.frame_id, 0x07);
+        assert!(!status.is_success());
+        assert!(TxStatus::from_frame(encode_frame(0x8b, &[0x07, 0x01]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn sensor_readings_in_engineering_units() {
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0x01, 0x03]);
+        // AD0 absent, AD1 = 1023 (5100 mV), AD2 = 0, AD3 = 0x60
+        body.extend_from_slice(&[0xff, 0xff, 0x03, 0xff, 0x00, 0x00, 0x00, 0x60]);
+        // -10.5 degrees
+        body.extend_from_slice(&[0x0f, 0x58]);
+        let frame = encode_frame(0x94, &body).unwrap();
+
+        let reading = SensorReadIndicator::from_frame(frame).unwrap();
+        assert_eq!(reading.sensors, 0x03);
+        assert_eq!(reading.analog_mv(0), None);
+        assert_eq!(reading.light_lux(), Some(5100.0));
+        assert_eq!(reading.temperature_c(), Some(-10.5));
+        let humidity = reading.humidity().unwrap();
+        assert!((humidity - 22.75).abs() < 0.01, "{}", humidity);
+
+        let mut warm = body.clone();
+        warm[20..22].copy_from_slice(&[0x01, 0x91]);
+        let reading = SensorReadIndicator::from_frame(encode_frame(0x94, &warm).unwrap()).unwrap();
+        assert_eq!(reading.temperature_c(), Some(25.0625));
+    }
+
+    #[test]
+    fn user_data_relay_frames() {
+        let frame = UserDataRelayFrame {
+            interface: RelayInterface::MicroPython,
+            payload: b"run",
+        };
+        assert_eq!(
+            &frame.gen().unwrap()[..],
+            &encode_frame(0x2d, &[0x00, 0x02, b'r', b'u', b'n']).unwrap()[..]
+        );
+
+        let output =
+            UserDataRelayOutput::from_frame(encode_frame(0xad, &[0x01, b'o', b'k']).unwrap())
+                .unwrap();
+        assert_eq!(output.source, RelayInterface::Bluetooth);
+        assert_eq!(&output.data[..], b"ok");
+        assert!(UserDataRelayOutput::from_frame(encode_frame(0xad, &[0x07]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn cellular_socket_frames() {
+        let connect = SocketConnectFrame {
+            socket_id: 0x01,
+            port: 80,
+            addr: SocketAddr::Host("digi.com"),
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(connect[3], 0x42);
+        assert_eq!(&connect[5..9], &[0x01, 0x00, 0x50, 0x01]);
+        assert_eq!(&connect[9..17], b"digi.com");
+
+        let send = SocketSendFrame {
+            socket_id: 0x01,
+            payload: b"GET",
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(&send[5..10], &[0x01, 0x00, b'G', b'E', b'T']);
+
+        let created =
+            SocketResponse::from_frame(encode_frame(0xc0, &[0x05, 0x01, 0x00]).unwrap()).unwrap();
+        assert_eq!(created.kind, FrameId::SocketCreateResponse);
+        assert_eq!(created.socket_id, 0x01);
+        assert!(created.is_success());
+        assert!(
+            SocketResponse::from_frame(encode_frame(0xc1, &[0x05, 0x01, 0x00]).unwrap()).is_err()
+        );
+
+        let rx =
+            SocketReceive::from_frame(encode_frame(0xcd, &[0x00, 0x01, 0x00, b'o', b'k']).unwrap())
+                .unwrap();
+        assert_eq!(rx.socket_id, 0x01);
+        assert_eq!(&rx.data[..], b"ok");
+    }
+
+    #[test]
+    fn ipv4_frames() {
+        let tx = TxIpv4Frame {
+            dest_addr: Ipv4Addr::new(192, 168, 1, 10),
+            dest_port: 0x2616,
+            source_port: 0,
+            protocol: SocketProtocol::Tcp,
+            close_after: true,
+            payload: b"hi",
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(tx[3], 0x20);
+        assert_eq!(
+            &tx[5..17],
+            &[192, 168, 1, 10, 0x26, 0x16, 0x00, 0x00, 0x01, 0x02, b'h', b'i']
+        );
+
+        let body = [10, 0, 0, 1, 0x26, 0x16, 0x30, 0x39, 0x00, 0x00, b'o', b'k'];
+        let rx = RxIpv4::from_frame(encode_frame(0xb0, &body).unwrap()).unwrap();
+        assert_eq!(rx.source_addr, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!((rx.dest_port, rx.source_port), (0x2616, 12345));
+        assert_eq!(rx.protocol, SocketProtocol::Udp);
+        assert_eq!(&rx.data[..], b"ok");
+    }
+
+    #[test]
+    fn sms_frames() {
+        let tx = TxSmsFrame {
+            phone_number: "+15555550100",
+            message: "hello",
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(tx[3], 0x1f);
+        assert_eq!(&tx[6..18], b"+15555550100");
+        assert!(tx[18..26].iter().all(|b| *b == 0));
+        assert_eq!(&tx[26..31], b"hello");
+
+        let bad_number = TxSmsFrame {
+            phone_number: "555-0100",
+            message: "hello",
+        };
+        assert!(bad_number.gen().is_err());
+
+        let mut body = b"15555550100".to_vec();
+        body.resize(20, 0);
+        body.extend_from_slice(b"pong");
+        let rx = RxSms::from_frame(encode_frame(0x9f, &body).unwrap()).unwrap();
+        assert_eq!(rx.phone_number, "15555550100");
+        assert_eq!(&rx.message[..], b"pong");
+    }
+
+    #[test]
+    fn file_system_frames() {
+        let open = FileSystemRequestFrame(FsCommand::Open {
+            path: "main.py",
+            options: FS_OPEN_CREATE | FS_OPEN_TRUNCATE,
+        })
+        .gen()
+        .unwrap();
+        assert_eq!(open[3], 0x3b);
+        assert_eq!(&open[5..9], &[0x01, 0x00, 0x00, 0x05]);
+        assert_eq!(&open[9..16], b"main.py");
+
+        let read = FileSystemRequestFrame(FsCommand::Read {
+            handle: 0x1234,
+            offset: FS_CURRENT_OFFSET,
+            len: 64,
+        })
+        .gen()
+        .unwrap();
+        assert_eq!(
+            &read[5..14],
+            &[0x03, 0x12, 0x34, 0xff, 0xff, 0xff, 0xff, 0x00, 0x40]
+        );
+
+        let opened = FileSystemResponse::from_frame(
+            encode_frame(
+                0xbb,
+                &[0x01, 0x01, 0x00, 0x12, 0x34, 0x00, 0x00, 0x01, 0x00],
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert!(opened.is_success());
+        assert_eq!(opened.handle(), Some(0x1234));
+        assert_eq!(opened.file_size(), Some(256));
+
+        let body = [
+            0x02, 0x03, 0x00, 0x12, 0x34, 0x00, 0x00, 0x00, 0x02, b'o', b'k',
+        ];
+        let read = FileSystemResponse::from_frame(encode_frame(0xbb, &body).unwrap()).unwrap();
+        assert_eq!(read.offset(), Some(2));
+        assert_eq!(read.read_data(), Some(&b"ok"[..]));
+        assert_eq!(read.file_size(), None);
+
+        let delete = RemoteFileSystemRequestFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            command: FsCommand::Delete { path: "old.py" },
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(delete[3], 0x3c);
+        assert_eq!(&delete[13..17], &[0x00, 0x2f, 0x00, 0x00]);
+        assert_eq!(&delete[17..23], b"old.py");
+
+        let mut body = vec![0x03];
+        body.extend_from_slice(&0x0013a200_40abcdef_u64.to_be_bytes());
+        body.extend_from_slice(&[0x01, 0x2f, 0x00]);
+        let deleted = FileSystemResponse::from_frame(encode_frame(0xbc, &body).unwrap()).unwrap();
+        assert_eq!(deleted.source_addr, Some(Addr64(0x0013a200_40abcdef)));
+        assert_eq!(deleted.command, 0x2f);
+        assert!(deleted.is_success());
+        assert!(deleted.data.is_empty());
+
+        let stat = FileSystemRequestFrame(FsCommand::VolumeStat { volume: "/flash" })
+            .gen()
+            .unwrap();
+        assert_eq!(stat[5], 0x40);
+        assert_eq!(&stat[6..12], b"/flash");
+
+        let mut body = vec![0x04, 0x40, 0x00];
+        for size in &[0x2000u32, 0x0003_e000, 0x0200] {
+            body.extend_from_slice(&size.to_be_bytes());
+        }
+        let stat = FileSystemResponse::from_frame(encode_frame(0xbb, &body).unwrap()).unwrap();
+        assert_eq!(
+            stat.volume_stat(),
+            Some(VolumeStat {
+                used: 0x2000,
+                free: 0x0003_e000,
+                bad: 0x0200,
+            })
+        );
+        assert_eq!(stat.file_size(), None);
+        assert_eq!(opened.volume_stat(), None);
+    }
+
+    #[test]
+    fn parse_ota_update_status() {
+        let mut body = 0x0013a200_40401122_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x00, 0x00, 0x01, 0x15, 0x07]);
+        body.extend_from_slice(&0x0013a200_40abcdef_u64.to_be_bytes());
+        let frame = encode_frame(0xa0, &body).unwrap();
+
+        let status = OtaUpdateStatus::from_frame(frame).unwrap();
+        assert_eq!(status.message, OtaMessage::Nack);
+        assert!(status.is_failure());
+        assert_eq!(status.block_number, 7);
+        assert_eq!(status.target_addr, Addr64(0x0013a200_40abcdef));
+    }
+
+    #[test]
+    fn device_request_round_trip() {
+        let frame = encode_frame(0xb9, b"\x05\x00\x00\x06configset=1").unwrap();
+        let request = DeviceRequest::from_frame(frame).unwrap();
+        assert_eq!(request.request_id, 5);
+        assert_eq!(request.target, "config");
+        assert_eq!(&request.data[..], b"set=1");
+        let bad = encode_frame(0xb9, b"\x05\x00\x00\x20config").unwrap();
+        assert!(DeviceRequest::from_frame(bad).is_err());
+
+        let response = DeviceResponseFrame {
+            request_id: request.request_id,
+            data: b"ok",
+        }
+        .gen()
+        .unwrap();
+        assert_eq!(response[3], 0x2a);
+        assert_eq!(&response[5..9], b"\x05\x00ok");
+    }
+
+    #[test]
+    fn parse_extended_modem_status() {
+        let frame = encode_frame(0x98, &[0x13, 0x00, 0x00, 0x07, 0x36]).unwrap();
+        let status = ExtendedModemStatus::from_frame(frame).unwrap();
+        assert_eq!(status.status_code, 0x13);
+        assert_eq!(&status.data[..], &[0x00, 0x00, 0x07, 0x36]);
+
+        let bare = ExtendedModemStatus::from_frame(encode_frame(0x98, &[0x02]).unwrap()).unwrap();
+        assert!(bare.data.is_empty());
+        assert!(ExtendedModemStatus::from_frame(encode_frame(0x8a, &[0x02]).unwrap()).is_err());
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected() {
+        let addr = 0x0013a200_40abcdef_u64.to_be_bytes();
+        let mut sms_number = b"15555550100".to_vec();
+        sms_number.resize(20, 0);
+        let mut route_info = vec![0x12, 0x2a, 0x00, 0x01, 0xe2, 0x40, 0x02, 0x00, 0x00];
+        for _ in 0..4 {
+            route_info.extend_from_slice(&addr);
+        }
+
+        // frame type and the shortest body it parses from; anything shorter,
+        // even with a valid checksum, must be rejected without panicking
+        let shortest: Vec<(u8, Vec<u8>)> = vec![
+            (0x8b, vec![0x01, 0xff, 0xfe, 0x00, 0x00, 0x00]),
+            (0x8a, vec![0x06]),
+            (0x98, vec![0x13]),
+            (0x90, [&addr[..], &[0xff, 0xfe, 0x02]].concat()),
+            (
+                0x91,
+                [
+                    &addr[..],
+                    &[0xff, 0xfe, 0xe8, 0xe6, 0x00, 0x12, 0xc1, 0x05, 0x01],
+                ]
+                .concat(),
+            ),
+            (0x88, b"\x01NI\x00".to_vec()),
+            (
+                0x97,
+                [&[0x01], &addr[..], &[0xff, 0xfe, b'S', b'L', 0x00]].concat(),
+            ),
+            (0xa4, vec![0x01, 0xb4]),
+            (0x89, vec![0x07, 0x01]),
+            (0x80, [&addr[..], &[0x28, 0x00]].concat()),
+            (0x81, vec![0x12, 0x34, 0x30, 0x02]),
+            (0xad, vec![0x01]),
+            (0xc0, vec![0x05, 0x01, 0x00]),
+            (0xcd, vec![0x00, 0x01, 0x00]),
+            (0xb0, vec![10, 0, 0, 1, 0x26, 0x16, 0x30, 0x39, 0x00, 0x00]),
+            (0x9f, sms_number),
+            (0xbb, vec![0x01, 0x2f, 0x00]),
+            (0xbc, [&[0x03], &addr[..], &[0x01, 0x2f, 0x00]].concat()),
+            (0xb9, b"\x05\x00\x00\x06config".to_vec()),
+            (0xba, vec![0x01, 0x00]),
+            (
+                0x92,
+                [
+                    &addr[..],
+                    &[0xff, 0xfe, 0x01, 0x01, 0x00, 0x18, 0x82],
+                    &[0x00, 0x10, 0x02, 0x25, 0x0c, 0xe4],
+                ]
+                .concat(),
+            ),
+            (0x8d, route_info),
+            (
+                0x94,
+                [
+                    &addr[..],
+                    &[0xff, 0xfe, 0x01, 0x03, 0xff, 0xff, 0x03, 0xff],
+                    &[0x00, 0x00, 0x00, 0x60, 0x0f, 0x58],
+                ]
+                .concat(),
+            ),
+            (
+                0xa1,
+                [&addr[..], &[0x7d, 0x84, 0x00, 0x02, 0xc4, 0x01, 0x1a, 0x02]].concat(),
+            ),
+            (0xa3, [&addr[..], &[0x00, 0x00, 0x00]].concat()),
+            (
+                0xa0,
+                [&addr[..], &[0x00, 0x00, 0x01, 0x15, 0x07], &addr[..]].concat(),
+            ),
+            (
+                0x95,
+                [
+                    &addr[..],
+                    &[0xff, 0xfe, 0xc2, 0xff, 0xfe],
+                    &addr[..],
+                    b"SENSOR\0",
+                    &[0xff, 0xfe, 0x01, 0x01, 0xc1, 0x05, 0x10, 0x1e],
+                ]
+                .concat(),
+            ),
+        ];
+        for (frame_type, body) in shortest {
+            let frame = encode_frame(frame_type, &body).unwrap();
+            if let Err(err) = parse_frame(&frame) {
+                panic!("{:#04x} does not parse: {}", frame_type, err);
+            }
+            for len in 0..body.len() {
+                let short = encode_frame(frame_type, &body[..len]).unwrap();
+                assert!(
+                    parse_frame(&short).is_err(),
+                    "{:#04x} parsed from {} of {} bytes",
+                    frame_type,
+                    len,
+                    body.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn parse_frame_dispatches_on_frame_type() {
+        let status = encode_frame(0x8a, &[0x06]).unwrap();
+        match parse_frame(&status).unwrap() {
+            XBeeFrame::ModemStatus(status) => {
+                assert_eq!(status.state, ModemState::CoordinatorStarted)
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let closed = encode_frame(0xc3, &[0x01, 0x00, 0x00]).unwrap();
+        assert!(matches!(
+            parse_frame(&closed).unwrap(),
+            XBeeFrame::SocketResponse(_)
+        ));
+
+        let unknown = encode_frame(0x7f, &[0x01]).unwrap();
+        match parse_frame(&unknown).unwrap() {
+            XBeeFrame::Unknown { frame_type, frame } => {
+                assert_eq!(frame_type, 0x7f);
+                assert_eq!(frame, unknown);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(parse_frame(&status[..status.len() - 1]).is_err());
+        assert!(parse_frame(&[0x7e, 0x00]).is_err());
+    }
+
+    #[test]
+    fn frame_parser_handles_split_frames() {
+        let first = encode_frame(0x8a, &[0x00]).unwrap();
+        let second = encode_frame(0x90, &[0x01; 13]).unwrap();
+        let mut stream = vec![0x00, 0x13];
+        stream.extend_from_slice(&first);
+        stream.extend_from_slice(&second);
+
+        let mut parser = FrameParser::new();
+        let mut frames = Vec::new();
+        for chunk in stream.chunks(4) {
+            frames.extend(parser.feed(chunk));
+        }
+        assert_eq!(frames, vec![first, second.clone()]);
+        assert_eq!(parser.pending(), 0);
+
+        assert!(parser.feed(&second[..2]).is_empty());
+        assert_eq!(parser.pending(), 2);
+        assert_eq!(parser.feed(&second[2..]), vec![second]);
+    }
+
+    #[test]
+    fn frame_parser_skips_stray_delimiters() {
+        let frame = encode_frame(0x8a, &[0x06]).unwrap();
+        // a delimiter in a boot banner whose "length" covers the real frame
+        let mut stream = b"boot \x7e\x00\x03ok ".to_vec();
+        stream.extend_from_slice(&frame);
+        let mut parser = FrameParser::new();
+        assert_eq!(parser.feed(&stream), vec![frame.clone()]);
+        assert_eq!(parser.pending(), 0);
+
+        // a length no frame can have does not hold back the frame behind it
+        let mut stream = vec![0x7e, 0xff, 0xf0, 0x12];
+        stream.extend_from_slice(&frame);
+        assert_eq!(parser.feed(&stream), vec![frame]);
+        assert_eq!(parser.pending(), 0);
+    }
+
+    #[test]
+    fn frame_parser_waits_for_frames_that_embed_frames() {
+        // RF data in AP=1 is not escaped and may look like a frame itself
+        let inner = encode_frame(0x8a, &[0x06]).unwrap();
+        let mut rx = vec![
+            0x00, 0x13, 0xa2, 0x00, 0x40, 0xab, 0xcd, 0xef, 0xff, 0xfe, 0x01,
+        ];
+        rx.extend_from_slice(&inner);
+        let outer = encode_frame(0x90, &rx).unwrap();
+
+        let mut parser = FrameParser::new();
+        assert!(parser.feed(&outer[..outer.len() - 1]).is_empty());
+        assert_eq!(parser.feed(&outer[outer.len() - 1..]), vec![outer]);
+        assert_eq!(parser.pending(), 0);
+    }
+
+    #[test]
+    fn corrupted_frames_fail_checksum() {
+        let mut frame = encode_frame(0x88, b"\x01NI\x00LOCAL").unwrap();
+        assert!(verify_checksum(&frame).is_ok());
+        frame[8] ^= 0x20;
+        match parse_frame(&frame) {
+            Err(Error::ChecksumMismatch { expected, actual }) => {
+                assert_eq!(expected, actual.wrapping_sub(0x20));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn escaping_covers_reserved_bytes() {
+        let frame = encode_frame(0x08, &[0x7e, 0x7d, 0x11, 0x13, 0x41]).unwrap();
+        let escaped = escape_frame(&frame);
+        assert_eq!(escaped[0], 0x7e);
+        assert_eq!(
+            &escaped[4..13],
+            &[0x7d, 0x5e, 0x7d, 0x5d, 0x7d, 0x31, 0x7d, 0x33, 0x41]
+        );
+        assert!(!escaped[1..].contains(&0x7e));
+        let plain = encode_frame(0x08, b"NI").unwrap();
+        assert_eq!(escape_frame(&plain), plain);
+    }
+
+    #[test]
+    fn frames_decode_from_captured_bytes() {
+        let captured: &[u8] = &[0x7e, 0x00, 0x02, 0x8a, 0x06, 0x6f];
+        let status = ModemStatus::try_from(captured).unwrap();
+        assert_eq!(status.state, ModemState::CoordinatorStarted);
+        assert!(matches!(
+            XBeeFrame::try_from(captured),
+            Ok(XBeeFrame::ModemStatus(_))
+        ));
+
+        assert!(TransmitStatus::try_from(captured).is_err());
+        assert!(ModemStatus::try_from(&captured[..5]).is_err());
+    }
+
+    #[test]
+    fn received_frames_encode_canonically() {
+        let canonical = vec![
+            encode_frame(0x8b, &[0x01, 0xff, 0xfe, 0x00, 0x21, 0x00]).unwrap(),
+            encode_frame(0x8a, &[0x0b]).unwrap(),
+            encode_frame(0x88, b"\x07NI\x00LOCAL").unwrap(),
+            encode_frame(0x90, b"\x00\x13\xa2\x00\x00\x00\x00\x02\xff\xfe\x01ping").unwrap(),
+            encode_frame(
+                0xbc,
+                b"\x03\x00\x13\xa2\x00\x00\x00\x00\x02\x00\x02\x00data",
+            )
+            .unwrap(),
+            encode_frame(0xb9, b"\x05\x00\x00\x06configset=1").unwrap(),
+        ];
+        for frame in canonical {
+            let decoded: Box<dyn RecieveApiFrame> = match parse_frame(&frame).unwrap() {
+                XBeeFrame::TransmitStatus(f) => Box::new(f),
+                XBeeFrame::ModemStatus(f) => Box::new(f),
+                XBeeFrame::AtCommandResponse(f) => Box::new(f),
+                XBeeFrame::ReceivePacket(f) => Box::new(f),
+                XBeeFrame::FileSystemResponse(f) => Box::new(f),
+                XBeeFrame::DeviceRequest(f) => Box::new(f),
+                other => panic!("unexpected {:?}", other),
+            };
+            assert_eq!(decoded.encode().unwrap(), frame);
+        }
+
+        let mut body = 0x0013a200_00000002_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0x12, 0x34, 0x01, 0x02, 0x00, 0x0e, 0x00, 0x0f]);
+        let record = RouteRecordIndicator::from_frame(encode_frame(0xa1, &body).unwrap()).unwrap();
+        assert_eq!(record.encode().unwrap(), encode_frame(0xa1, &body).unwrap());
+
+        // the 16-bit source address is not kept and comes back as unknown
+        body.truncate(11);
+        body.extend_from_slice(b"hi");
+        let packet = ReceivePacket::from_frame(encode_frame(0x90, &body).unwrap()).unwrap();
+        body[8..10].copy_from_slice(&[0xff, 0xfe]);
+        assert_eq!(packet.encode().unwrap(), encode_frame(0x90, &body).unwrap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn frames_round_trip_through_json() {
+        let frame = encode_frame(0x88, b"\x07NI\x00LOCAL").unwrap();
+        let response = AtCommandResponse::from_frame(frame.clone()).unwrap();
+        let json = serde_json::to_string(&response).unwrap();
+        assert!(!json.contains("payload"));
+
+        let restored: AtCommandResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.command_data, response.command_data);
+        assert_eq!(restored.encode().unwrap(), frame);
+
+        let parsed = parse_frame(&encode_frame(0x8a, &[0x0b]).unwrap()).unwrap();
+        let json = serde_json::to_string(&parsed).unwrap();
+        assert!(matches!(
+            serde_json::from_str::<XBeeFrame>(&json).unwrap(),
+            XBeeFrame::ModemStatus(ModemStatus {
+                state: ModemState::NetworkWokeUp,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn received_data_shares_the_frame_buffer() {
+        let frame =
+            encode_frame(0x90, b"\x00\x13\xa2\x00\x00\x00\x00\x02\xff\xfe\x01ping").unwrap();
+        let packet = ReceivePacket::from_frame(frame).unwrap();
+        let raw = packet.payload().unwrap();
+        assert_eq!(&packet.data[..], b"ping");
+        assert_eq!(packet.data.as_ptr(), raw[15..].as_ptr());
+        assert_eq!(raw.as_ptr(), packet.payload().unwrap().as_ptr());
+    }
+
+    #[test]
+    fn explicit_frame_ids_replace_allocated_ones() {
+        let frame = AtCommandFrame("NI", None).gen_with_frame_id(0x33).unwrap();
+        assert_eq!(&frame[..], &xbee_frame!(at 0x33, "NI")[..]);
+
+        let silent = TransmitRequestFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            dest_addr_16: None,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"hi",
+            fire_and_forget: false,
+        }
+        .gen_with_frame_id(0)
+        .unwrap();
+        assert_eq!(silent[4], 0);
+        assert!(verify_checksum(&silent).is_ok());
+    }
+
+    #[test]
+    fn transmit_request_carries_16_bit_address() {
+        let frame = |dest_addr_16| {
+            TransmitRequestFrame {
+                dest_addr: Addr64(0),
+                dest_addr_16,
+                broadcast_radius: 0,
+                options: None,
+                payload: b"hi",
+                fire_and_forget: true,
+            }
+            .gen()
+            .unwrap()
+        };
+        assert_eq!(&frame(None)[13..15], &[0xff, 0xfe]);
+        assert_eq!(
+            &frame(Some(Addr16(constants::COORDINATOR_16BIT)))[13..15],
+            &[0x00, 0x00]
+        );
+        assert_eq!(&frame(Some(Addr16(0x1234)))[13..15], &[0x12, 0x34]);
+    }
+
+    #[test]
+    fn transmit_status_is_typed() {
+        let frame = encode_frame(0x8b, &[0x01, 0xff, 0xfe, 0x02, 0x25, 0x02]).unwrap();
+        let status = TransmitStatus::from_frame(frame).unwrap();
+        assert_eq!(status.delivery(), DeliveryStatus::RouteNotFound);
+        assert_eq!(status.discovery(), DiscoveryStatus::Route);
+        assert!(!status.is_success());
+
+        for value in 0..=0xff {
+            assert_eq!(DeliveryStatus::from_value(value).value(), value);
+            assert_eq!(DiscoveryStatus::from_value(value).value(), value);
+        }
+        assert_eq!(
+            DeliveryStatus::from_value(0x31),
+            DeliveryStatus::ResourceError(0x31)
+        );
+    }
+
+    #[test]
+    fn at_command_data_requires_ok_status() {
+        let ok = encode_frame(0x88, &[0x01, b'N', b'I', 0x00, b'A']).unwrap();
+        let ok = AtCommandResponse::from_frame(ok).unwrap();
+        assert!(ok.is_ok());
+        assert_eq!(&ok.command_data().unwrap()[..], b"A");
+
+        let set = encode_frame(0x88, &[0x01, b'N', b'I', 0x00]).unwrap();
+        assert!(AtCommandResponse::from_frame(set)
+            .unwrap()
+            .command_data()
+            .unwrap()
+            .is_empty());
+
+        let bad = encode_frame(0x88, &[0x01, b'C', b'H', 0x03, 0xff]).unwrap();
+        let bad = AtCommandResponse::from_frame(bad).unwrap();
+        assert_eq!(bad.status(), AtCommandStatus::InvalidParameter);
+        match bad.command_data() {
+            Err(Error::AtCommandStatus { command, status }) => {
+                assert_eq!(command, "CH");
+                assert_eq!(status, AtCommandStatus::InvalidParameter);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let mut remote = vec![0x01];
+        remote.extend_from_slice(&0x0013a200_40abcdefu64.to_be_bytes());
+        remote.extend_from_slice(&[0xff, 0xfe, b'I', b'D', 0x04]);
+        let remote =
+            RemoteAtCommandResponse::from_frame(encode_frame(0x97, &remote).unwrap()).unwrap();
+        assert_eq!(remote.status(), AtCommandStatus::TxFailure);
+        assert!(remote.command_data().is_err());
+    }
+
+    #[test]
+    fn at_command_catalog() {
+        for cmd in AtCmd::ALL {
+            assert_eq!(AtCmd::from_name(cmd.name()), Some(*cmd));
+            assert!(validate_at_command(cmd.name(), None).is_ok());
+        }
+        assert_eq!("ni".parse::<AtCmd>().unwrap(), AtCmd::Ni);
+        assert!("XX".parse::<AtCmd>().is_err());
+
+        let query = AtCmd::Sh.query().unwrap().gen_with_frame_id(1).unwrap();
+        assert_eq!(
+            &query[..],
+            &AtCommandFrame("SH", None).gen_with_frame_id(1).unwrap()[..]
+        );
+        assert!(AtCmd::Sh.set(&[0x00]).is_err());
+        assert!(AtCmd::Ky.query().is_err());
+        assert!(AtCmd::Ky.set(&[0xaa; 16]).is_ok());
+        assert!(AtCmd::Ch.set(&[0x0c, 0x00]).is_err());
+        assert!(AtCmd::Ni.set(b"NODE\r").is_err());
+        assert!(AtCmd::Ni.exec(None).is_err());
+        assert!(AtCmd::Nd.exec(Some(b"NODE")).is_ok());
+        assert!(AtCmd::Wr.exec(Some(&[0x01])).is_err());
+    }
+
+    #[test]
+    fn param_values() {
+        assert_eq!(ParamValue::decode_u64(&[0x82]).unwrap(), 0x82);
+        assert_eq!(ParamValue::decode_u64(&[0x01, 0x2c]).unwrap(), 300);
+        assert_eq!(ParamValue::decode_u64(&[]).unwrap(), 0);
+        assert_eq!(
+            ParamValue::decode_u16(&[0x00, 0x00, 0x7f, 0xff]).unwrap(),
+            0x7fff
+        );
+        assert!(ParamValue::decode_u16(&[0x01, 0x00, 0x00]).is_err());
+        assert!(ParamValue::decode_u64(&[0x01; 9]).is_err());
+        assert_eq!(ParamValue::decode_string(b"NODE").unwrap(), "NODE");
+        assert!(ParamValue::decode_string(&[0xff]).is_err());
+
+        assert_eq!(ParamValue::from(0x7fffu16).encode(), vec![0x7f, 0xff]);
+        assert_eq!(ParamValue::from("NODE").encode(), b"NODE".to_vec());
+        assert_eq!(
+            AtCmd::Sp.decode(&[0x01, 0x2c]).unwrap(),
+            ParamValue::U32(300)
+        );
+        assert_eq!(AtCmd::Ni.decode(b"NODE").unwrap().as_str(), Some("NODE"));
+        assert_eq!(AtCmd::Ch.decode(&[0x0c]).unwrap().as_u64(), Some(0x0c));
+    }
+
+    #[test]
+    fn command_mode_param_values() {
+        assert_eq!(ParamValue::from(0x7fffu16).encode_command_mode(), b"7FFF");
+        assert_eq!(ParamValue::from(0u8).encode_command_mode(), b"0");
+        assert_eq!(ParamValue::from("NODE").encode_command_mode(), b"NODE");
+        assert_eq!(
+            ParamValue::from(vec![0x0a, 0xff]).encode_command_mode(),
+            b"0AFF"
+        );
+
+        assert_eq!(
+            ParamValue::decode_command_mode(AtParam::Uint(2), b"7FFF").unwrap(),
+            ParamValue::U16(0x7fff)
+        );
+        assert_eq!(
+            ParamValue::decode_command_mode(AtParam::Uint(4), b"13A200").unwrap(),
+            ParamValue::U32(0x0013a200)
+        );
+        assert!(ParamValue::decode_command_mode(AtParam::Uint(1), b"100").is_err());
+        assert!(ParamValue::decode_command_mode(AtParam::Uint(1), b"OK").is_err());
+        assert_eq!(
+            ParamValue::decode_command_mode(AtParam::Text(20), b"NODE").unwrap(),
+            ParamValue::Text("NODE".to_string())
+        );
+        assert_eq!(
+            ParamValue::decode_command_mode(AtParam::Bytes(16), b"0aFF").unwrap(),
+            ParamValue::Bytes(vec![0x0a, 0xff])
+        );
+    }
+
+    #[test]
+    fn transmit_request_builder() {
+        let frame = TransmitRequestFrame::builder()
+            .dest(Addr64(0x0013a200_40abcdef))
+            .payload(b"ping")
+            .broadcast_radius(3)
+            .disable_ack()
+            .build()
+            .unwrap();
+        assert_eq!(frame.dest_addr, Addr64(0x0013a200_40abcdef));
+        assert_eq!(frame.broadcast_radius, 3);
+        let packet = frame.gen_with_frame_id(1).unwrap();
+        assert_eq!(packet[16], 0xc1);
+        assert_eq!(&packet[17..21], b"ping");
+
+        let plain = TransmitRequestFrame::builder().broadcast().build().unwrap();
+        assert!(plain.options.is_none());
+
+        assert!(TransmitRequestFrame::builder()
+            .payload(b"x")
+            .build()
+            .is_err());
+        assert!(TransmitRequestFrame::builder()
+            .broadcast()
+            .trace_route()
+            .build()
+            .is_err());
+        assert!(TransmitRequestFrame::builder()
+            .dest(0x1234u64)
+            .trace_route()
+            .mode(MessagingMode::PointToPoint)
+            .build()
+            .is_err());
+        let huge = vec![0; 0xffff - TX_REQUEST_OVERHEAD + 1];
+        let frame = TransmitRequestFrame::builder()
+            .dest(0x1234u64)
+            .payload(&huge)
+            .build()
+            .unwrap();
+        assert!(frame.gen().is_err());
+        match TransmitRequestFrame::builder()
+            .dest(0x1234u64)
+            .payload(&[0; 74])
+            .max_payload(73)
+            .build()
+        {
+            Err(Error::PayloadTooLarge { max: 73, got: 74 }) => {}
+            other => panic!("unexpected {:?}", other.map(|frame| frame.payload.len())),
+        }
+    }
+
+    #[test]
+    fn remote_at_command_builder() {
+        let frame = RemoteAtCommandFrame::builder()
+            .dest(0x0013a200_40abcdefu64)
+            .command("ID")
+            .param(&[0x7f, 0xff])
+            .disable_ack()
+            .extended_timeout()
+            .build()
+            .unwrap();
+        let packet = frame.gen_with_frame_id(1).unwrap();
+        assert_eq!(packet[15], 0x43);
+        assert_eq!(&packet[16..20], b"ID\x7f\xff");
+
+        let queued = RemoteAtCommandFrame::builder()
+            .broadcast()
+            .command("CH")
+            .queue_changes()
+            .build()
+            .unwrap();
+        assert_eq!(queued.options.compile(), 0x00);
+
+        assert!(RemoteAtCommandFrame::builder()
+            .command("ID")
+            .build()
+            .is_err());
+        assert!(RemoteAtCommandFrame::builder()
+            .dest(0x1234u64)
+            .command("IDX")
+            .build()
+            .is_err());
+        assert!(RemoteAtCommandFrame::builder()
+            .broadcast()
+            .command("ID")
+            .secure_session()
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn decode_errors_locate_the_field() {
+        let err = TransmitStatus::from_frame(BytesMut::from(&[0x7e, 0x00, 0x05, 0x8b, 0x01][..]))
+            .unwrap_err();
+        match err {
+            Error::Decode(err) => {
+                assert_eq!(err.frame, FrameId::TransmitStatus);
+                assert_eq!((err.field, err.offset), ("discovery status", 9));
+                assert_eq!(err.kind, DecodeErrorKind::Truncated { len: 5 });
+            }
+            other => panic!("unexpected {}", other),
+        }
+
+        let mut body = 0x0013a200_40abcdef_u64.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0xc2, 0xff, 0xfe]);
+        body.extend_from_slice(&0x0013a200_40abcdef_u64.to_be_bytes());
+        body.extend_from_slice(b"SENSOR\0");
+        body.extend_from_slice(&[0xff, 0xfe, 0x01, 0x01, 0xc1, 0x05, 0x10, 0x1e]);
+        let frame = encode_frame(0x95, &body).unwrap();
+
+        // the only null byte after the remote address is the checksum
+        let mut unterminated = frame[..25].to_vec();
+        unterminated.push(0x00);
+        match NodeIdentificationIndicator::from_frame(BytesMut::from(&unterminated[..])) {
+            Err(Error::Decode(err)) => assert_eq!((err.field, err.offset), ("node identifier", 25)),
+            other => panic!("unexpected {:?}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn owned_frames_match_borrowed_frames() {
+        fn assert_send<T: Send + 'static>(_: &T) {}
+
+        let payload = vec![0x7e, 0x00, 0x11];
+        let borrowed = TransmitRequestFrame::builder()
+            .dest(0x0013a200_40abcdef_u64)
+            .payload(&payload)
+            .disable_ack()
+            .build()
+            .unwrap();
+        let owned = OwnedTransmitRequest::from(borrowed.clone());
+        assert_eq!(owned.as_frame(), borrowed);
+        drop(payload);
+        assert_send(&owned);
+        assert_eq!(owned.clone(), owned);
+        assert_eq!(
+            owned.gen_with_frame_id(1).unwrap(),
+            owned.as_frame().gen_with_frame_id(1).unwrap()
+        );
+
+        let remote = OwnedRemoteAtCommand::from(
+            RemoteAtCommandFrame::builder()
+                .dest(0x1234u64)
+                .command("NI")
+                .param(b"NODE")
+                .build()
+                .unwrap(),
+        );
+        assert_send(&remote);
+        assert_eq!(remote.as_frame().cmd_param, Some(&b"NODE"[..]));
+        assert_eq!(remote.gen_with_frame_id(1).unwrap()[16..22], b"NINODE"[..]);
+
+        let queued = OwnedAtCommand::from(AtCommandQueueFrame::new("CH", Some(&[0x0c])).unwrap());
+        assert_eq!(queued.id(), FrameId::AtCommandQueue);
+        assert_eq!(
+            queued.gen_with_frame_id(1).unwrap(),
+            AtCommandQueueFrame("CH", Some(&[0x0c]))
+                .gen_with_frame_id(1)
+                .unwrap()
+        );
+        assert!(OwnedAtCommand::from(AtCommandFrame("NID", None))
+            .gen()
+            .is_err());
+    }
+
+    #[test]
+    fn const_frames_match_runtime_frames() {
+        const NI_QUERY: [u8; 8] = xbee_frame!(at 0x01, "NI");
+        let runtime = encode_frame(0x08, &[0x01, b'N', b'I']).unwrap();
+        assert_eq!(&NI_QUERY[..], &runtime[..]);
+
+        let broadcast = xbee_frame!(broadcast 0x00, [b'h', b'i']);
+        let mut body = vec![0x00];
+        body.extend_from_slice(&0xffffu64.to_be_bytes());
+        body.extend_from_slice(&[0xff, 0xfe, 0x00, 0x00, b'h', b'i']);
+        assert_eq!(&broadcast[..], &encode_frame(0x10, &body).unwrap()[..]);
+    }
+
+    #[test]
+    fn frame_ids_skip_zero_when_wrapping() {
+        let mut ids = FrameIdAllocator::new();
+        for expected in 1..=255u8 {
+            assert_eq!(ids.next_id(), expected);
+        }
+        assert_eq!(ids.next_id(), 1);
+    }
+
+    #[test]
+    fn frame_ids_skip_in_flight() {
+        let mut ids = FrameIdAllocator::new();
+        assert_eq!(ids.allocate(), 1);
+        ids.reserve(2);
+        assert_eq!(ids.next_id(), 3);
+        ids.release(1);
+        for _ in 3..255 {
+            ids.next_id();
         }
+        assert_eq!(ids.next_id(), 1);
+        assert_eq!(ids.next_id(), 3);
+    }
+
+    #[test]
+    fn frame_ids_end_when_every_id_is_in_flight() {
+        let mut ids = FrameIdAllocator::new();
+        for id in 1..=255u8 {
+            ids.reserve(id);
+        }
+        assert_eq!(ids.next_id(), 255);
+        assert_eq!(ids.next_id(), 255);
+        ids.release(7);
+        assert_eq!(ids.next_id(), 7);
+    }
+    #[test]
+    fn generated_frames_keep_their_id_reserved() {
+        let packet = AtCommandFrame("NI", None).gen().unwrap();
+        assert!(FRAME_ID_ALLOCATOR.lock().unwrap().is_in_flight(packet[4]));
+        FRAME_ID_ALLOCATOR.lock().unwrap().release(packet[4]);
     }
 }
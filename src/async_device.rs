@@ -0,0 +1,403 @@
+//!
+//! Async device
+//!
+//! `AsyncDigiMeshDevice` talks to a radio in API mode without blocking the
+//! runtime's worker threads. It works on any `futures::io` stream, so it runs
+//! on tokio, async-std or smol alike; the `tokio` feature adds a serial port
+//! opener and adapters for tokio streams. It covers the common
+//! request/response traffic; `DigiMeshDevice` remains the full featured,
+//! blocking interface.
+//!
+
+use crate::api::{self, TransmitApiFrame};
+use crate::device::{
+    packet_dest, packet_payload_len, parse_discovery_response, response_type, Error, Result,
+    TimeoutPolicy,
+};
+use crate::network::NodeTable;
+use bytes::BytesMut;
+use futures_timer::Delay;
+use futures_util::future::{self, Either};
+use futures_util::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::collections::{HashSet, VecDeque};
+use std::future::Future;
+use std::sync::PoisonError;
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio")]
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+/// Frames kept for `recv` while waiting for responses; older ones are dropped
+static UNSOLICITED_BACKLOG: usize = 64;
+/// How long `discover_nodes` waits for answers unless told otherwise
+static DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Radio attached to a stream implementing the `futures::io` traits, e.g. an
+/// async-std or smol serial port. With the `tokio` feature, `open` and
+/// `from_tokio` wrap tokio streams to fit.
+pub struct AsyncDigiMeshDevice<P> {
+    port: P,
+    api_mode: api::ApiMode,
+    timeout_policy: TimeoutPolicy,
+    parser: api::FrameParser,
+    /// The last byte read was an escape, so the next one is to be unescaped
+    escape_pending: bool,
+    /// Frames read while waiting for a different one, oldest first
+    unsolicited: VecDeque<BytesMut>,
+    node_table: NodeTable,
+    /// 64-bit address of the local radio, once known
+    addr_64: Option<api::Addr64>,
+    /// NP once queried, `Some(None)` if the radio does not report it
+    max_payload: Option<Option<usize>>,
+}
+
+impl<P> std::fmt::Debug for AsyncDigiMeshDevice<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncDigiMeshDevice")
+            .field("api_mode", &self.api_mode)
+            .field("addr_64", &self.addr_64.map(|addr| addr.to_string()))
+            .finish()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncDigiMeshDevice<Compat<tokio_serial::SerialStream>> {
+    /// Opens `port` at `baud_rate`, 8N1 without flow control. Must be called
+    /// from within a tokio runtime.
+    pub fn open(port: &str, baud_rate: u32) -> Result<Self> {
+        use tokio_serial::SerialPortBuilderExt;
+
+        let stream = tokio_serial::new(port, baud_rate)
+            .open_native_async()
+            .map_err(std::io::Error::from)?;
+        Ok(Self::from_tokio(stream))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T> AsyncDigiMeshDevice<Compat<T>>
+where
+    T: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    /// Uses a stream implementing tokio's `AsyncRead` and `AsyncWrite`
+    pub fn from_tokio(port: T) -> Self {
+        Self::with_port(port.compat())
+    }
+}
+
+impl<P: AsyncRead + AsyncWrite + Unpin> AsyncDigiMeshDevice<P> {
+    pub fn with_port(port: P) -> Self {
+        Self {
+            port,
+            api_mode: api::ApiMode::Unescaped,
+            timeout_policy: TimeoutPolicy::default(),
+            parser: api::FrameParser::new(),
+            escape_pending: false,
+            unsolicited: VecDeque::new(),
+            node_table: NodeTable::new(),
+            addr_64: None,
+            max_payload: None,
+        }
+    }
+
+    /// Framing the radio uses, which must match its AP setting
+    pub fn api_mode(mut self, mode: api::ApiMode) -> Self {
+        self.api_mode = mode;
+        self
+    }
+
+    pub fn timeout_policy(mut self, policy: TimeoutPolicy) -> Self {
+        self.timeout_policy = policy;
+        self
+    }
+
+    /// Nodes found by `discover_nodes`
+    pub fn nodes(&self) -> &NodeTable {
+        &self.node_table
+    }
+
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+
+    /// Largest RF payload the radio accepts (NP), queried once and cached
+    pub async fn max_payload(&mut self) -> Result<usize> {
+        if self.max_payload.is_none() {
+            self.max_payload = match self.at_query("NP").await {
+                Ok(np) => Some(Some(api::ParamValue::decode_u16(&np)? as usize)),
+                Err(Error::AtCommandFailed { .. }) => Some(None),
+                Err(err) => return Err(err),
+            };
+        }
+        self.max_payload
+            .flatten()
+            .ok_or_else(|| Error::InvalidMode("Radio does not report NP".to_string()))
+    }
+
+    /// Sends `frame` and waits for the radio's response to it. Frames with
+    /// id 0, which the radio does not answer, return `None` once written.
+    /// Data frames with a payload larger than NP are rejected without being
+    /// sent. Other frames arriving in the meantime are kept for `recv`.
+    pub async fn send_frame<T: TransmitApiFrame>(
+        &mut self,
+        frame: T,
+    ) -> Result<Option<api::XBeeFrame>> {
+        let (sent, packet, _reserved) = generate(frame)?;
+        self.check_payload(sent, &packet).await?;
+        self.send_packet(sent, packet, None).await
+    }
+
+    /// Like `send_frame`, but waits up to `timeout` for the response instead
+    /// of what the timeout policy gives for the frame
+    pub async fn send_frame_with_timeout<T: TransmitApiFrame>(
+        &mut self,
+        frame: T,
+        timeout: Duration,
+    ) -> Result<Option<api::XBeeFrame>> {
+        let (sent, packet, _reserved) = generate(frame)?;
+        self.check_payload(sent, &packet).await?;
+        self.send_packet(sent, packet, Some(timeout)).await
+    }
+
+    /// Rejects data frames whose payload is larger than NP. Radios that cannot
+    /// report NP are not checked.
+    async fn check_payload(&mut self, sent: api::FrameId, packet: &[u8]) -> Result<()> {
+        let got = match packet_payload_len(sent, packet) {
+            Some(len) => len,
+            None => return Ok(()),
+        };
+        match self.max_payload().await {
+            Ok(max) if got > max => Err(Error::PayloadTooLarge { max, got }),
+            _ => Ok(()),
+        }
+    }
+
+    async fn send_packet(
+        &mut self,
+        sent: api::FrameId,
+        packet: BytesMut,
+        timeout: Option<Duration>,
+    ) -> Result<Option<api::XBeeFrame>> {
+        let frame_id = packet[4];
+        let expected = match response_type(sent) {
+            Some(expected) if frame_id != 0 => expected,
+            _ => {
+                self.write_frame(&packet).await?;
+                return Ok(None);
+            }
+        };
+
+        let timeout = timeout.unwrap_or_else(|| {
+            self.timeout_policy
+                .timeout_for(sent, packet_dest(sent, &packet))
+        });
+        let response = self
+            .transact(&packet, expected, Instant::now() + timeout)
+            .await?;
+        Ok(Some(api::parse_frame(&response)?))
+    }
+
+    async fn transact(
+        &mut self,
+        packet: &[u8],
+        expected: api::FrameId,
+        deadline: Instant,
+    ) -> Result<BytesMut> {
+        self.write_frame(packet).await?;
+        loop {
+            let frame = self.read_frame_before(deadline, "response").await?;
+            if frame.len() > 5 && frame[3] == expected.id() && frame[4] == packet[4] {
+                return Ok(frame);
+            }
+            self.keep(frame);
+        }
+    }
+
+    /// Waits for RF data from a remote node, delivered as a Receive Packet
+    /// when AO=0
+    pub async fn recv(&mut self) -> Result<api::ReceivePacket> {
+        let wanted = api::FrameId::ReceivePacket.id();
+        if let Some(pos) = self.unsolicited.iter().position(|f| f[3] == wanted) {
+            if let Some(frame) = self.unsolicited.remove(pos) {
+                return Ok(api::ReceivePacket::from_frame(frame)?);
+            }
+        }
+        loop {
+            let frame = self.read_frame().await?;
+            if frame[3] == wanted {
+                return Ok(api::ReceivePacket::from_frame(frame)?);
+            }
+            self.keep(frame);
+        }
+    }
+
+    /// Like `recv`, failing with `api::Error::Timeout` if no data arrives in time
+    pub async fn recv_timeout(&mut self, timeout: Duration) -> Result<api::ReceivePacket> {
+        within(Instant::now() + timeout, "Receive Packet", self.recv()).await
+    }
+
+    /// Runs a network discovery (ND) and adds every node that answers within
+    /// `timeout` (15 seconds if `None`) to `nodes`, returning how many did
+    pub async fn discover_nodes(&mut self, timeout: Option<Duration>) -> Result<usize> {
+        let options = self
+            .at_query("NO")
+            .await
+            .ok()
+            .and_then(|no| api::ParamValue::decode_u8(&no).ok());
+        if self.addr_64.is_none() {
+            let high = api::ParamValue::decode_u32(&self.at_query("SH").await?)?;
+            let low = api::ParamValue::decode_u32(&self.at_query("SL").await?)?;
+            self.addr_64 = Some(api::Addr64(((high as u64) << 32) | low as u64));
+        }
+
+        let (_, discover_cmd, _reserved) = generate(api::AtCommandFrame("ND", None))?;
+        self.write_frame(&discover_cmd).await?;
+        let deadline = Instant::now() + timeout.unwrap_or(DEFAULT_DISCOVERY_TIMEOUT);
+        let mut found: HashSet<api::Addr64> = HashSet::new();
+        loop {
+            let frame = match self.read_frame_before(deadline, "discovery").await {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => break,
+                Err(err) => return Err(err),
+            };
+            if frame[3] != api::FrameId::AtCommandResponse.id() {
+                self.keep(frame);
+                continue;
+            }
+            let response = api::AtCommandResponse::from_frame(frame.clone())?;
+            if response.at_command != b"ND" || !response.is_ok() {
+                self.keep(frame);
+                continue;
+            }
+            let node = response
+                .command_data
+                .as_ref()
+                .and_then(|data| parse_discovery_response(data, options));
+            if let Some(node) = node {
+                if Some(node.addr_64bit) != self.addr_64 {
+                    found.insert(node.addr_64bit);
+                    self.node_table.merge(node);
+                }
+            }
+        }
+
+        if found.is_empty() {
+            return Err(Error::DiscoveryError);
+        }
+        Ok(found.len())
+    }
+
+    /// Command data of a local AT query
+    async fn at_query(&mut self, cmd: &str) -> Result<bytes::Bytes> {
+        let (sent, packet, _reserved) = generate(api::AtCommandFrame::new(cmd, None)?)?;
+        match self.send_packet(sent, packet, None).await? {
+            Some(api::XBeeFrame::AtCommandResponse(response)) => {
+                response.command_data().map_err(|_| Error::AtCommandFailed {
+                    cmd: cmd.to_string(),
+                    status: response.command_status,
+                })
+            }
+            _ => Err(Error::ApiError(api::Error::DerefError)),
+        }
+    }
+
+    /// Sets `frame` aside for `recv`
+    fn keep(&mut self, frame: BytesMut) {
+        if self.unsolicited.len() == UNSOLICITED_BACKLOG {
+            self.unsolicited.pop_front();
+        }
+        self.unsolicited.push_back(frame);
+    }
+
+    async fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        match self.api_mode {
+            api::ApiMode::Unescaped => self.port.write_all(frame).await?,
+            api::ApiMode::Escaped => self.port.write_all(&api::escape_frame(frame)).await?,
+        }
+        self.port.flush().await?;
+        Ok(())
+    }
+
+    async fn read_frame_before(
+        &mut self,
+        deadline: Instant,
+        operation: &'static str,
+    ) -> Result<BytesMut> {
+        within(deadline, operation, self.read_frame()).await
+    }
+
+    /// Reads until a complete frame with a valid checksum arrives. Frames
+    /// with a bad checksum are dropped by the parser.
+    async fn read_frame(&mut self) -> Result<BytesMut> {
+        let mut chunk = [0u8; 256];
+        loop {
+            if let Some(frame) = self.parser.next_frame() {
+                return Ok(frame);
+            }
+            let read = self.port.read(&mut chunk).await?;
+            if read == 0 {
+                return Err(Error::IOError(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "serial port closed",
+                )));
+            }
+            match self.api_mode {
+                api::ApiMode::Unescaped => self.parser.push(&chunk[..read]),
+                api::ApiMode::Escaped => {
+                    let unescaped = self.unescape(&chunk[..read]);
+                    self.parser.push(&unescaped);
+                }
+            }
+        }
+    }
+
+    fn unescape(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(data.len());
+        for byte in data {
+            if self.escape_pending {
+                out.push(byte ^ 0x20);
+                self.escape_pending = false;
+            } else if *byte == api::ESCAPE {
+                self.escape_pending = true;
+            } else {
+                out.push(*byte);
+            }
+        }
+        out
+    }
+}
+
+/// Runs `task` until `deadline`, failing with a timeout for `operation` after
+/// that. The timer runs on its own thread, so no particular runtime is needed.
+async fn within<T>(
+    deadline: Instant,
+    operation: &'static str,
+    task: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    let delay = Delay::new(deadline.saturating_duration_since(Instant::now()));
+    futures_util::pin_mut!(task);
+    match future::select(task, delay).await {
+        Either::Left((result, _)) => result,
+        Either::Right(_) => Err(Error::ApiError(api::Error::Timeout { operation })),
+    }
+}
+
+/// Frame id reserved in `api::FRAME_ID_ALLOCATOR` until dropped, so it is
+/// freed on errors and when the future waiting for the response is cancelled
+struct ReservedFrameId(u8);
+
+impl Drop for ReservedFrameId {
+    fn drop(&mut self) {
+        api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release(self.0);
+    }
+}
+
+/// Encodes `frame`, consuming it so it is not held across an await. The
+/// frame id it was given stays reserved until the returned guard is dropped.
+fn generate<T: TransmitApiFrame>(frame: T) -> Result<(api::FrameId, BytesMut, ReservedFrameId)> {
+    let packet = frame.gen()?;
+    let reserved = ReservedFrameId(packet[4]);
+    Ok((frame.id(), packet, reserved))
+}
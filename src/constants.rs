@@ -0,0 +1,19 @@
+//!
+//! Reserved addresses
+//!
+//! Addresses with a special meaning to every DigiMesh, Zigbee and 802.15.4
+//! radio. Constants rather than statics so they can be matched on.
+//!
+
+/// 64-bit address that reaches every node of the network
+pub const BROADCAST: u64 = 0x0000_0000_0000_ffff;
+
+/// 64-bit address of the coordinator of a Zigbee network
+pub const COORDINATOR: u64 = 0x0000_0000_0000_0000;
+
+/// 16-bit address to use when it is unknown or for broadcasts; the radio then
+/// resolves the 64-bit address itself
+pub const UNKNOWN_16BIT: u16 = 0xfffe;
+
+/// 16-bit address of the coordinator of a Zigbee network
+pub const COORDINATOR_16BIT: u16 = 0x0000;
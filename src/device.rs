@@ -1,104 +1,718 @@
-use crate::api::{self, AtCommand, AtCommands, RecieveApiFrame, TransmitApiFrame};
-use bytes::{BufMut, BytesMut};
+use crate::api::{self, Addr64, AtCommand, AtCommands, ParamValue, TransmitApiFrame};
+use crate::firmware::{self, Protocol};
+use crate::network::{Network, NodeTable};
+use bytes::{BufMut, Bytes, BytesMut};
 use serialport::*;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
+use std::sync::PoisonError;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use thiserror::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Error)]
 pub enum Error {
-    SerialError(serialport::Error),
-    IOError(std::io::Error),
-    DecodeError(std::str::Utf8Error),
+    #[error(transparent)]
+    SerialError(#[from] serialport::Error),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    DecodeError(#[from] std::str::Utf8Error),
+    #[error(transparent)]
     ApiError(api::Error),
+    #[error("{0}")]
     InvalidMode(String),
+    #[error("{0}")]
+    LoopbackError(String),
+    #[error("AT command {cmd} failed with status {status}")]
+    AtCommandFailed { cmd: String, status: u8 },
+    #[error("Remote device {addr} is unreachable")]
+    RemoteUnreachable { addr: Addr64 },
+    #[error("Could not complete discovery mode")]
     DiscoveryError,
+    #[error("Payload of {got} bytes exceeds the maximum of {max} bytes")]
+    PayloadTooLarge { max: usize, got: usize },
+    #[error("Delivery failed after {attempts} attempts: {status:?}")]
+    DeliveryFailed {
+        status: api::DeliveryStatus,
+        attempts: u32,
+    },
 }
 
-impl From<serialport::Error> for Error {
-    fn from(err: serialport::Error) -> Self {
-        Error::SerialError(err)
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl From<api::Error> for Error {
+    /// Payload limits are reported the same way whether the frame or the radio
+    /// caught them
+    fn from(err: api::Error) -> Self {
+        match err {
+            api::Error::PayloadTooLarge { max, got } => Error::PayloadTooLarge { max, got },
+            err => Error::ApiError(err),
+        }
     }
 }
 
-impl From<std::io::Error> for Error {
-    fn from(err: std::io::Error) -> Self {
-        Error::IOError(err)
+/// Used when the discovery timeout cannot be read from the device
+static DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(15);
+/// Extra time on top of NT for responses to propagate back through the mesh
+static DISCOVERY_MARGIN: Duration = Duration::from_secs(2);
+/// NO bit that appends the RSSI of the last hop to ND responses
+static ND_OPTION_APPEND_RSSI: u8 = 0x04;
+/// Idle time after which the radio drops out of command mode (CT at its default)
+static COMMAND_MODE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest wait for a reply in command mode, on top of GT for the escape sequence
+static COMMAND_REPLY_TIMEOUT: Duration = Duration::from_millis(2000);
+/// Modem statuses kept until collected; older ones are dropped beyond this
+static MODEM_STATUS_BACKLOG: usize = 32;
+/// Unsolicited frames kept for the `recv_*` methods; older ones are dropped
+static UNSOLICITED_BACKLOG: usize = 64;
+/// Serial read timeout unless the builder is given another
+static DEFAULT_SERIAL_TIMEOUT: Duration = Duration::from_secs(20);
+/// How long `recv` waits at a time before starting over
+static RECV_WAIT: Duration = Duration::from_secs(60);
+
+/// Result of a keepalive query against the local radio
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthEvent {
+    /// Radio answered as expected
+    Alive,
+    /// Radio stopped answering AT frames
+    Unresponsive,
+    /// Radio answers again after being unresponsive, most likely because it rebooted.
+    /// `reinitialized` is set when the cached device state was reloaded.
+    Recovered { reinitialized: bool },
+}
+
+struct Keepalive {
+    interval: Duration,
+    last: Instant,
+    reinitialize: bool,
+}
+
+/// Decides how long to wait for the response to a frame, based on where it is
+/// going and how the network is configured. The defaults assume a DigiMesh
+/// network with factory settings; `DigiMeshDevice::load_timeout_policy` reads
+/// the actual values from the radio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeoutPolicy {
+    /// Wait for responses generated by the local radio
+    pub local: Duration,
+    /// Fixed part of the wait for anything that goes over the air
+    pub base: Duration,
+    /// Added for every hop a frame may travel
+    pub per_hop: Duration,
+    /// Maximum number of hops (NH)
+    pub hops: u8,
+    /// Times a broadcast is sent by every node (MT + 1)
+    pub broadcast_transmissions: u8,
+    /// Sleep period of the network, if it sleeps. Added to over the air waits
+    /// since the destination may only wake up after that long.
+    pub sleep_period: Option<Duration>,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            local: Duration::from_millis(100),
+            base: Duration::from_millis(500),
+            per_hop: Duration::from_millis(200),
+            hops: 7,
+            broadcast_transmissions: 4,
+            sleep_period: None,
+        }
     }
 }
 
-impl From<std::str::Utf8Error> for Error {
-    fn from(err: std::str::Utf8Error) -> Self {
-        Error::DecodeError(err)
+impl TimeoutPolicy {
+    /// Time to wait for the response to a `frame` sent to `dest`, where `dest` is
+    /// `None` for frames handled by the local radio
+    pub fn timeout_for(&self, frame: api::FrameId, dest: Option<Addr64>) -> Duration {
+        let dest = match (frame, dest) {
+            // 802.15.4 does not route, only MAC retries to a neighbour
+            (api::FrameId::TxRequest64, _) | (api::FrameId::TxRequest16, _) => return self.base,
+            // answered by the cellular modem once it has handled the request
+            (api::FrameId::SocketCreate, _)
+            | (api::FrameId::SocketConnect, _)
+            | (api::FrameId::SocketClose, _)
+            | (api::FrameId::SocketSend, _)
+            | (api::FrameId::TxIpv4, _)
+            | (api::FrameId::TxSms, _) => return self.base,
+            // flash operations take longer than answering an AT command
+            (api::FrameId::FileSystemRequest, _) => return self.base,
+            (api::FrameId::AtCommand, _) | (_, None) => return self.local,
+            (_, Some(dest)) => dest,
+        };
+        let mut hops = self.per_hop * self.hops as u32;
+        if dest == Addr64::BROADCAST {
+            hops *= self.broadcast_transmissions.max(1) as u32;
+        }
+        self.base + hops + self.sleep_period.unwrap_or_default()
     }
 }
 
-impl From<api::Error> for Error {
-    fn from(err: api::Error) -> Self {
-        Error::ApiError(err)
+/// How the escape sequence that enters command mode is sent. The defaults
+/// match the factory settings; `DigiMeshDevice::load_escape_sequence` reads
+/// the actual values from the radio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeSequence {
+    /// Silence required before and after the command characters (GT)
+    pub guard_time: Duration,
+    /// Character sent three times in a row (CC)
+    pub command_char: u8,
+}
+
+impl Default for EscapeSequence {
+    fn default() -> Self {
+        Self {
+            guard_time: Duration::from_millis(1000),
+            command_char: b'+',
+        }
     }
 }
 
-impl std::fmt::Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+/// Retransmission of frames whose Transmit Status reports a transient failure,
+/// such as a missing MAC or network ACK (see `TransmitStatus::is_retryable`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Retransmissions after the first attempt
+    pub retries: u32,
+    /// Wait before the first retransmission, doubled for every one after it
+    pub backoff: Duration,
+    /// Longest wait between two transmissions
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Wait before retransmission number `retry`, counting from 0
+    fn backoff(&self, retry: u32) -> Duration {
+        self.backoff
+            .checked_mul(1 << retry.min(31))
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+/// Command data of an AT Command Response, or the error its status stands for
+fn at_response_data(cmd: &str, response: &dyn api::RecieveApiFrame) -> Result<Bytes> {
+    let response = response
+        .downcast_ref::<api::AtCommandResponse>()
+        .ok_or(Error::ApiError(api::Error::DerefError))?;
+    response.command_data().map_err(|_| Error::AtCommandFailed {
+        cmd: cmd.to_string(),
+        status: response.command_status,
+    })
+}
+
+/// Frees `frame_id` for reuse once nothing waits for its response any more
+fn release_frame_id(frame_id: u8) {
+    api::FRAME_ID_ALLOCATOR
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .release(frame_id);
+}
+
+/// Destination of a frame that goes over the air
+pub(crate) fn packet_dest(frame: api::FrameId, packet: &[u8]) -> Option<Addr64> {
+    match frame {
+        api::FrameId::TransmitRequest
+        | api::FrameId::ExplicitAddressing
+        | api::FrameId::RemoteAtCommand
+        | api::FrameId::RemoteFileSystemRequest
+            if packet.len() >= 13 =>
+        {
+            ParamValue::decode_u64(&packet[5..13]).ok().map(Addr64)
+        }
+        _ => None,
+    }
+}
+
+/// Whether frames of type `kind` answer a request and carry its frame id
+fn is_response(kind: u8) -> bool {
+    [
+        api::FrameId::TransmitStatus,
+        api::FrameId::AtCommandResponse,
+        api::FrameId::RemoteAtCommandResponse,
+        api::FrameId::TxStatus,
+        api::FrameId::SocketCreateResponse,
+        api::FrameId::SocketConnectResponse,
+        api::FrameId::SocketCloseResponse,
+        api::FrameId::FileSystemResponse,
+        api::FrameId::RemoteFileSystemResponse,
+        api::FrameId::DeviceResponseStatus,
+        api::FrameId::RegisterJoiningDeviceStatus,
+    ]
+    .iter()
+    .any(|response| response.id() == kind)
+}
+
+/// Whether frames of type `kind` are sent over the air, and so count against
+/// the rate limit
+fn is_rf_transmit(kind: u8) -> bool {
+    [
+        api::FrameId::TransmitRequest,
+        api::FrameId::ExplicitAddressing,
+        api::FrameId::RemoteAtCommand,
+        api::FrameId::RemoteFileSystemRequest,
+        api::FrameId::TxRequest64,
+        api::FrameId::TxRequest16,
+    ]
+    .iter()
+    .any(|transmit| transmit.id() == kind)
+}
+
+/// Frame type the radio answers a `sent` frame with, `None` when it sends no
+/// response
+pub(crate) fn response_type(sent: api::FrameId) -> Option<api::FrameId> {
+    match sent {
+        api::FrameId::TransmitRequest | api::FrameId::ExplicitAddressing => {
+            Some(api::FrameId::TransmitStatus)
+        }
+        api::FrameId::AtCommand | api::FrameId::AtCommandQueue => {
+            Some(api::FrameId::AtCommandResponse)
+        }
+        api::FrameId::RemoteAtCommand => Some(api::FrameId::RemoteAtCommandResponse),
+        api::FrameId::TxRequest64
+        | api::FrameId::TxRequest16
+        | api::FrameId::SocketSend
+        | api::FrameId::TxIpv4
+        | api::FrameId::TxSms => Some(api::FrameId::TxStatus),
+        api::FrameId::SocketCreate => Some(api::FrameId::SocketCreateResponse),
+        api::FrameId::SocketConnect => Some(api::FrameId::SocketConnectResponse),
+        api::FrameId::SocketClose => Some(api::FrameId::SocketCloseResponse),
+        api::FrameId::FileSystemRequest => Some(api::FrameId::FileSystemResponse),
+        api::FrameId::RemoteFileSystemRequest => Some(api::FrameId::RemoteFileSystemResponse),
+        api::FrameId::DeviceResponse => Some(api::FrameId::DeviceResponseStatus),
+        api::FrameId::RegisterJoiningDevice => Some(api::FrameId::RegisterJoiningDeviceStatus),
+        _ => None,
+    }
+}
+
+/// Length of the RF payload carried by a data frame
+pub(crate) fn packet_payload_len(frame: api::FrameId, packet: &[u8]) -> Option<usize> {
+    // delimiter, length, frame specific header and checksum
+    let overhead = match frame {
+        api::FrameId::TransmitRequest => 18,
+        api::FrameId::ExplicitAddressing => 24,
+        _ => return None,
+    };
+    packet.len().checked_sub(overhead)
+}
+
+/// Pace at which frames that go over the air are written to the radio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimit {
+    FramesPerSecond(u32),
+    BytesPerSecond(u32),
+    /// Keep the share of time spent transmitting at or below `percent`, with
+    /// airtime estimated from the RF data rate in bits per second. Meant for
+    /// sub-GHz modules operating under duty cycle regulations.
+    DutyCycle {
+        percent: u8,
+        air_rate_bps: u32,
+    },
+}
+
+impl RateLimit {
+    /// How long to wait after writing a frame of `len` bytes
+    fn interval(&self, len: usize) -> Duration {
         match *self {
-            Error::SerialError(ref err) => write!(f, "{}", err),
-            Error::IOError(ref err) => write!(f, "{}", err),
-            Error::DecodeError(ref err) => write!(f, "{}", err),
-            Error::InvalidMode(ref err) => write!(f, "{}", err),
-            Error::ApiError(ref err) => write!(f, "{}", err),
-            Error::DiscoveryError => write!(f, "Could not complete discovery mode"),
+            RateLimit::FramesPerSecond(rate) => Duration::from_secs(1) / rate.max(1),
+            RateLimit::BytesPerSecond(rate) => {
+                Duration::from_secs_f64(len as f64 / rate.max(1) as f64)
+            }
+            RateLimit::DutyCycle {
+                percent,
+                air_rate_bps,
+            } => {
+                let airtime = (len * 8) as f64 / air_rate_bps.max(1) as f64;
+                Duration::from_secs_f64(airtime * 100.0 / percent.clamp(1, 100) as f64)
+            }
         }
     }
 }
 
-impl std::error::Error for Error {}
+struct Throttle {
+    limit: RateLimit,
+    next_send: Instant,
+}
+
+type Handler<T> = Box<dyn FnMut(&T) + Send>;
 
-pub type Result<T> = std::result::Result<T, Error>;
+/// Callbacks for frames that arrive while nobody is waiting for them
+#[derive(Default)]
+struct Handlers {
+    data: Option<Handler<api::ReceivePacket>>,
+    modem_status: Option<Handler<api::ModemStatus>>,
+    node_identification: Option<Handler<api::NodeIdentificationIndicator>>,
+}
+
+/// How a radio talks to its host, see `DigiMeshDevice::detect_mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperatingMode {
+    /// AP=0: data is passed through as is and AT commands need command mode
+    Transparent,
+    Api(api::ApiMode),
+}
+
+/// When `DigiMeshDevice::transparent_recv` returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadUntil {
+    /// Through the first occurrence of this byte, e.g. `b'\n'` for lines
+    Delimiter(u8),
+    /// Once this many bytes arrived
+    Len(usize),
+}
+
+/// Order in which queued frames leave the device, highest first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Large transfers that can wait
+    Bulk = 0,
+    /// Regular application data
+    Telemetry = 1,
+    /// AT commands, acknowledgements and anything else that must not be delayed
+    Control = 2,
+}
+
+/// Generated frames waiting to be written, one FIFO per priority
+#[derive(Debug, Default)]
+struct TxQueue {
+    queues: [VecDeque<BytesMut>; 3],
+}
+
+impl TxQueue {
+    fn push(&mut self, priority: Priority, packet: BytesMut) {
+        self.queues[priority as usize].push_back(packet);
+    }
+
+    fn pop(&mut self) -> Option<BytesMut> {
+        self.queues
+            .iter_mut()
+            .rev()
+            .find_map(|queue| queue.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        self.queues.iter().map(|queue| queue.len()).sum()
+    }
+}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RemoteDigiMeshDevice {
-    pub addr_64bit: u64,
-    pub node_id: String,
+    pub addr_64bit: Addr64,
+    /// Raw NI bytes as reported by the node, which are not guaranteed to be UTF-8
+    pub node_id: Vec<u8>,
     pub firmware_version: Option<u16>,
     pub hardware_version: Option<u16>,
+    /// Signal strength of the last hop in -dBm, when the node reported it
+    pub rssi: Option<u8>,
+}
+
+impl std::fmt::Display for RemoteDigiMeshDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.node_id_lossy(), self.addr_64bit)
+    }
+}
+
+impl RemoteDigiMeshDevice {
+    /// Node identifier with any invalid UTF-8 replaced
+    pub fn node_id_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.node_id)
+    }
+}
+
+/// Kind of radio, as told by the upper byte of HV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModuleType {
+    XBeePro900Hp,
+    XBee3,
+    Other(u8),
+}
+
+impl ModuleType {
+    pub fn from_hardware_version(hardware_version: u16) -> Self {
+        match hardware_version >> 8 {
+            _ if firmware::is_xbee3(hardware_version) => ModuleType::XBee3,
+            0x23 => ModuleType::XBeePro900Hp,
+            other => ModuleType::Other(other as u8),
+        }
+    }
+}
+
+/// Identity and capabilities of the local radio, see `DigiMeshDevice::info`
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceInfo {
+    pub addr_64: Addr64,
+    /// Raw NI bytes, which are not guaranteed to be UTF-8
+    pub node_id: Vec<u8>,
+    pub firmware: u16,
+    pub hardware: u16,
+    /// Only known for XBee3, where it follows from the firmware
+    pub protocol: Option<Protocol>,
+    pub module_type: ModuleType,
+    /// Largest RF payload in the current configuration (NP), `None` if the
+    /// firmware does not report it
+    pub max_payload: Option<usize>,
 }
 
 pub struct DigiMeshDevice {
+    #[deprecated(note = "use `DigiMeshDevice::info()` instead")]
     pub addr_64bit: Option<u64>,
-    pub node_id: Option<String>,
+    /// Raw NI bytes, which are not guaranteed to be UTF-8
+    #[deprecated(note = "use `DigiMeshDevice::info()` instead")]
+    pub node_id: Option<Vec<u8>>,
+    #[deprecated(note = "use `DigiMeshDevice::info()` instead")]
     pub firmware_version: Option<u16>,
+    #[deprecated(note = "use `DigiMeshDevice::info()` instead")]
     pub hardware_version: Option<u16>,
+    #[deprecated(note = "use `DigiMeshDevice::nodes()` instead")]
     pub nodes: Option<Vec<RemoteDigiMeshDevice>>,
+    node_table: NodeTable,
+    /// Last known AO setting, `None` until it is queried or set
+    api_options: Option<api::ApiOptions>,
+    /// `None` until first needed, and again after anything that may change it
+    info: Option<DeviceInfo>,
+    /// Transmit statuses recieved while waiting for a different frame id
+    pending_statuses: HashMap<u8, api::TransmitStatus>,
+    /// Frames written by `transmit` or `send_queued` while a retry policy is
+    /// set, with the number of retransmissions so far
+    retransmits: HashMap<u8, (BytesMut, u32)>,
+    /// Other responses to frames in flight, recieved while waiting for a
+    /// different frame id
+    pending_responses: HashMap<u8, BytesMut>,
+    /// Data and indications that arrived while waiting for something else,
+    /// oldest first
+    unsolicited: VecDeque<BytesMut>,
+    /// Unsolicited modem statuses, oldest first
+    modem_statuses: VecDeque<api::ModemStatus>,
+    keepalive: Option<Keepalive>,
+    handlers: Handlers,
+    tx_queue: TxQueue,
+    throttle: Option<Throttle>,
+    /// When the radio leaves command mode unless another command is sent,
+    /// `None` when it is not in command mode
+    command_mode_expires: Option<Instant>,
+    /// Last time anything was written to the radio, for the `+++` guard time
+    last_write: Option<Instant>,
+    timeout_policy: TimeoutPolicy,
+    retry_policy: Option<RetryPolicy>,
+    escape_sequence: EscapeSequence,
+    api_mode: api::ApiMode,
+    /// Switch a radio found in transparent mode by `init` to API mode
+    provision_api_mode: bool,
+    /// Whether the radio answered the last health check
+    responsive: bool,
+    /// Bytes discarded while looking for a start delimiter
+    skipped_bytes: u64,
+    /// Bytes read behind a start delimiter that turned out to be noise, scanned
+    /// again before anything new is read
+    resync_buf: BytesMut,
+    /// Switched to transparent mode by `enter_transparent_mode`
+    transparent: bool,
+    /// Transparent mode data read but not returned by `transparent_recv` yet
+    transparent_rx: BytesMut,
     serial: Box<dyn SerialPort>,
+    /// Timeout the port had before the device took it over, restored on drop
+    original_timeout: Duration,
     rx_buf: BytesMut,
     tx_buf: BytesMut,
 }
 
 impl std::fmt::Debug for DigiMeshDevice {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let info = self.info.as_ref();
         f.debug_struct("DigiMeshDevice")
-            .field("addr_64bit", &format!("{:x?}", self.addr_64bit))
-            .field("node_id", &format!("{:?}", self.node_id))
-            .field("firmware_version", &format!("{:x?}", self.firmware_version))
-            .field("hardware_version", &format!("{:x?}", self.hardware_version))
+            .field("addr_64bit", &info.map(|i| i.addr_64.to_string()))
+            .field(
+                "node_id",
+                &info.map(|i| String::from_utf8_lossy(&i.node_id)),
+            )
+            .field(
+                "firmware_version",
+                &format!("{:x?}", info.map(|i| i.firmware)),
+            )
+            .field(
+                "hardware_version",
+                &format!("{:x?}", info.map(|i| i.hardware)),
+            )
             .finish()
     }
 }
 
-impl DigiMeshDevice {
-    pub fn new<'a>(port: &'a str, baud: u32) -> Result<Self> {
-        let settings = SerialPortSettings {
-            baud_rate: baud,
-            data_bits: DataBits::Eight,
-            flow_control: FlowControl::None,
-            parity: Parity::None,
-            stop_bits: StopBits::One,
-            timeout: Duration::from_millis(20000),
-        };
+impl Drop for DigiMeshDevice {
+    /// Takes the radio out of command mode and writes out anything still
+    /// buffered, so it is left ready for the next user of the port. Errors are
+    /// ignored since there is nobody left to report them to.
+    fn drop(&mut self) {
+        if let Some(expires) = self.command_mode_expires {
+            if Instant::now() < expires {
+                let _ = self.command_mode(false);
+            }
+        }
+        let _ = self.serial.flush();
+        let _ = self.serial.set_timeout(self.original_timeout);
+    }
+}
+
+impl std::fmt::Display for DigiMeshDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.info {
+            Some(ref info) => write!(
+                f,
+                "{} ({})",
+                String::from_utf8_lossy(&info.node_id),
+                info.addr_64
+            ),
+            None => write!(f, "<unknown> (<unknown address>)"),
+        }
+    }
+}
+
+/// Opens a `DigiMeshDevice` with non-default connect behaviour
+pub struct DigiMeshDeviceBuilder<'a> {
+    port: &'a str,
+    baud: u32,
+    provision_api_mode: bool,
+    keepalive: Option<Duration>,
+    reinitialize_on_recovery: bool,
+    rate_limit: Option<RateLimit>,
+    hardware_flow_control: bool,
+    timeout_policy: TimeoutPolicy,
+    retry_policy: Option<RetryPolicy>,
+    escape_sequence: EscapeSequence,
+    serial_timeout: Duration,
+    api_mode: api::ApiMode,
+}
+
+impl<'a> DigiMeshDeviceBuilder<'a> {
+    pub fn new(port: &'a str, baud: u32) -> Self {
+        Self {
+            port,
+            baud,
+            provision_api_mode: false,
+            keepalive: None,
+            reinitialize_on_recovery: false,
+            rate_limit: None,
+            hardware_flow_control: false,
+            timeout_policy: TimeoutPolicy::default(),
+            retry_policy: None,
+            escape_sequence: EscapeSequence::default(),
+            serial_timeout: DEFAULT_SERIAL_TIMEOUT,
+            api_mode: api::ApiMode::Unescaped,
+        }
+    }
+
+    /// Switch a radio found in transparent mode when connecting to the AP of
+    /// `api_mode` through command mode, written to flash and verified again.
+    /// Without it, connecting to a radio in transparent mode fails.
+    pub fn provision_api_mode(mut self, enable: bool) -> Self {
+        self.provision_api_mode = enable;
+        self
+    }
+
+    /// Query VR on the local radio every `interval`, see `DigiMeshDevice::poll_keepalive`
+    pub fn keepalive(mut self, interval: Duration) -> Self {
+        self.keepalive = Some(interval);
+        self
+    }
+
+    /// Reload the cached device state when the radio answers again after
+    /// being unresponsive
+    pub fn reinitialize_on_recovery(mut self, enable: bool) -> Self {
+        self.reinitialize_on_recovery = enable;
+        self
+    }
+
+    /// Pace frames sent over the air according to `limit`. Local AT commands
+    /// and other frames the radio handles itself are written right away.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Only write while the radio asserts CTS, which keeps its serial buffer from
+    /// overflowing. Requires CTS to be wired and enabled on the radio (D7=1).
+    pub fn hardware_flow_control(mut self, enable: bool) -> Self {
+        self.hardware_flow_control = enable;
+        self
+    }
+
+    /// Framing the radio is expected to use (AP), tried first when connecting.
+    /// Defaults to `ApiMode::Unescaped`; the device switches to the other
+    /// framing if that is what the radio runs.
+    pub fn api_mode(mut self, mode: api::ApiMode) -> Self {
+        self.api_mode = mode;
+        self
+    }
+
+    /// Response timeouts to use instead of `TimeoutPolicy::default()`
+    pub fn timeout_policy(mut self, policy: TimeoutPolicy) -> Self {
+        self.timeout_policy = policy;
+        self
+    }
+
+    /// Retransmit frames that fail to be delivered, see `RetryPolicy`. Off by
+    /// default.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(policy);
+        self
+    }
+
+    /// Guard time and command character of the radio, if they differ from the
+    /// factory settings. Used when command mode is entered, including while
+    /// connecting to a radio in transparent mode.
+    pub fn escape_sequence(mut self, escape: EscapeSequence) -> Self {
+        self.escape_sequence = escape;
+        self
+    }
+
+    /// Read timeout of the serial port for reads that have no timeout of
+    /// their own, such as `recv_raw`. Defaults to 20 seconds.
+    pub fn serial_timeout(mut self, timeout: Duration) -> Self {
+        self.serial_timeout = timeout;
+        self
+    }
+
+    pub fn build(self) -> Result<DigiMeshDevice> {
+        let serial = serialport::open_with_settings(self.port, &self.settings())?;
+        self.build_with_port(serial)
+    }
 
-        let mut device = Self {
-            serial: serialport::open_with_settings(port, &settings)?,
+    /// Like `build`, but talks to the radio through an already opened port, such
+    /// as one from `sim::SimNetwork`. The port name given to the builder is unused.
+    pub fn build_with_port(self, serial: Box<dyn SerialPort>) -> Result<DigiMeshDevice> {
+        let mut device = self.open_with_port(serial)?;
+        device.init()?;
+        Ok(device)
+    }
+
+    /// Opens the port without talking to the radio, so it works while the radio
+    /// sleeps or is busy. Call `DigiMeshDevice::init` before relying on the API
+    /// mode or the radio's identity.
+    pub fn open(self) -> Result<DigiMeshDevice> {
+        let serial = serialport::open_with_settings(self.port, &self.settings())?;
+        self.open_with_port(serial)
+    }
+
+    /// Like `open`, but with an already opened port
+    #[allow(deprecated)]
+    pub fn open_with_port(self, mut serial: Box<dyn SerialPort>) -> Result<DigiMeshDevice> {
+        let original_timeout = serial.timeout();
+        serial.set_all(&self.settings())?;
+        let device = DigiMeshDevice {
+            serial,
+            original_timeout,
             rx_buf: BytesMut::with_capacity(128),
             tx_buf: BytesMut::with_capacity(128),
             addr_64bit: None,
@@ -106,232 +720,2359 @@ impl DigiMeshDevice {
             firmware_version: None,
             hardware_version: None,
             nodes: None,
+            node_table: NodeTable::new(),
+            pending_statuses: HashMap::new(),
+            retransmits: HashMap::new(),
+            pending_responses: HashMap::new(),
+            unsolicited: VecDeque::new(),
+            modem_statuses: VecDeque::new(),
+            api_options: None,
+            info: None,
+            keepalive: self.keepalive.map(|interval| Keepalive {
+                interval,
+                last: Instant::now(),
+                reinitialize: self.reinitialize_on_recovery,
+            }),
+            responsive: true,
+            skipped_bytes: 0,
+            resync_buf: BytesMut::new(),
+            transparent: false,
+            transparent_rx: BytesMut::new(),
+            handlers: Handlers::default(),
+            tx_queue: TxQueue::default(),
+            command_mode_expires: None,
+            last_write: None,
+            throttle: self.rate_limit.map(|limit| Throttle {
+                limit,
+                next_send: Instant::now(),
+            }),
+            timeout_policy: self.timeout_policy,
+            retry_policy: self.retry_policy,
+            escape_sequence: self.escape_sequence,
+            api_mode: self.api_mode,
+            provision_api_mode: self.provision_api_mode,
         };
-        let addr = device.get_64bit_addr()?;
-        let node_id = device.get_node_id()?;
-        let hw_version = device.get_hardware_version()?;
-        let fw_version = device.get_firmware_version()?;
-
-        device.addr_64bit = Some(addr);
-        device.node_id = Some(node_id);
-        device.hardware_version = Some(hw_version);
-        device.firmware_version = Some(fw_version);
-
         Ok(device)
     }
 
-    pub fn get_firmware_version(&mut self) -> Result<u16> {
-        if let None = self.firmware_version {
-            let fw = self.send_frame(api::AtCommandFrame("VR", None))?;
-            let fw = fw
-                .downcast_ref::<api::AtCommandResponse>()
-                .ok_or(Error::ApiError(api::Error::DerefError))?
-                .command_data
-                .as_ref()
-                .unwrap();
-            return Ok(u16::from_be_bytes(<[u8; 2]>::try_from(&fw[..]).unwrap()));
+    fn settings(&self) -> SerialPortSettings {
+        SerialPortSettings {
+            baud_rate: self.baud,
+            data_bits: DataBits::Eight,
+            flow_control: if self.hardware_flow_control {
+                FlowControl::Hardware
+            } else {
+                FlowControl::None
+            },
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            timeout: self.serial_timeout,
         }
-        Ok(self.firmware_version.unwrap())
     }
+}
 
-    pub fn get_hardware_version(&mut self) -> Result<u16> {
-        if let None = self.hardware_version {
-            let fw = self.send_frame(api::AtCommandFrame("HV", None))?;
-            let fw = fw
-                .downcast_ref::<api::AtCommandResponse>()
-                .ok_or(Error::ApiError(api::Error::DerefError))?
-                .command_data
-                .as_ref()
-                .unwrap();
-            return Ok(u16::from_be_bytes(<[u8; 2]>::try_from(&fw[..]).unwrap()));
+impl DigiMeshDevice {
+    pub fn new(port: &str, baud: u32) -> Result<Self> {
+        DigiMeshDeviceBuilder::new(port, baud).build()
+    }
+
+    pub fn builder(port: &str, baud: u32) -> DigiMeshDeviceBuilder<'_> {
+        DigiMeshDeviceBuilder::new(port, baud)
+    }
+
+    /// Opens the port without querying the radio, unlike `new`. The address,
+    /// node identifier and versions stay unknown until `init` or `info`.
+    pub fn open(port: &str, baud: u32) -> Result<Self> {
+        DigiMeshDeviceBuilder::new(port, baud).open()
+    }
+
+    /// Detects the API mode of the radio, switching it out of transparent mode
+    /// if the builder asked for that, and reads its identity. `new` and `build`
+    /// do this while connecting; after `open` it can be retried until the radio
+    /// answers.
+    pub fn init(&mut self) -> Result<()> {
+        match self.detect_mode()? {
+            OperatingMode::Api(_) => {}
+            OperatingMode::Transparent if self.provision_api_mode => {
+                self.switch_to_api_mode(true)?;
+                self.transparent = false;
+            }
+            OperatingMode::Transparent => {
+                return Err(Error::InvalidMode(
+                    "Radio is in transparent mode (AP=0); enable provision_api_mode to switch it to API mode"
+                        .to_string(),
+                ))
+            }
         }
-        Ok(self.hardware_version.unwrap())
+        self.info()?;
+        Ok(())
     }
 
-    pub fn get_node_id(&mut self) -> Result<String> {
-        if let None = self.node_id {
-            // get node_id
-            let node_id = self.send_frame(api::AtCommandFrame("NI", None))?;
-            let node_id = node_id
-                .downcast_ref::<api::AtCommandResponse>()
-                .ok_or(Error::ApiError(api::Error::DerefError))?
-                .command_data
-                .as_ref()
-                .unwrap();
-            let node_id = std::str::from_utf8(&node_id[..])?;
+    /// Reads the address, node identifier, versions and NP of the local radio
+    /// again, replacing what was cached
+    pub fn refresh_info(&mut self) -> Result<&DeviceInfo> {
+        self.info = None;
+        self.info()
+    }
 
-            return Ok(String::from(node_id));
+    /// Identity and capabilities of the local radio. Read from the radio on first
+    /// use and cached afterwards.
+    pub fn info(&mut self) -> Result<&DeviceInfo> {
+        if self.info.is_none() {
+            let info = self.query_info()?;
+            self.set_info(info);
         }
-        Ok(self.node_id.clone().unwrap())
+        Ok(self.info.as_ref().unwrap())
     }
 
-    pub fn get_64bit_addr(&mut self) -> Result<u64> {
-        if let None = self.addr_64bit {
-            // get 64bit addr of device
-            let sh = self.send_frame(api::AtCommandFrame("SH", None))?;
-            let sl = self.send_frame(api::AtCommandFrame("SL", None))?;
+    fn query_info(&mut self) -> Result<DeviceInfo> {
+        let upper = ParamValue::decode_u64(&self.at_query("SH")?)?;
+        let lower = ParamValue::decode_u64(&self.at_query("SL")?)?;
+        let node_id = self.at_query("NI")?.to_vec();
+        let firmware = ParamValue::decode_u16(&self.at_query("VR")?)?;
+        let hardware = ParamValue::decode_u16(&self.at_query("HV")?)?;
+        let max_payload = self.query_max_payload()?;
 
-            let sh = sh
-                .downcast_ref::<api::AtCommandResponse>()
-                .ok_or(Error::ApiError(api::Error::DerefError))?;
-            let sl = sl
-                .downcast_ref::<api::AtCommandResponse>()
-                .ok_or(Error::ApiError(api::Error::DerefError))?;
-            let upper = sh.command_data.as_ref().unwrap();
-            let lower = sl.command_data.as_ref().unwrap();
-            let upper = u32::from_be_bytes(<[u8; 4]>::try_from(&upper[..]).unwrap()); // messy but works
-            let lower = u32::from_be_bytes(<[u8; 4]>::try_from(&lower[..]).unwrap());
+        let module_type = ModuleType::from_hardware_version(hardware);
+        let protocol = match module_type {
+            ModuleType::XBee3 => Protocol::from_firmware_version(firmware),
+            _ => None,
+        };
+        Ok(DeviceInfo {
+            addr_64: Addr64((upper << 32) | lower),
+            node_id,
+            firmware,
+            hardware,
+            protocol,
+            module_type,
+            max_payload,
+        })
+    }
 
-            let addr_64bit: u64 = ((upper as u64) << 32) | (lower as u64);
-            return Ok(addr_64bit);
+    /// NP, or `None` if the firmware does not report it
+    fn query_max_payload(&mut self) -> Result<Option<usize>> {
+        match self.at_query("NP") {
+            Ok(np) => Ok(Some(ParamValue::decode_u16(&np)? as usize)),
+            Err(Error::AtCommandFailed { .. }) => Ok(None),
+            Err(err) => Err(err),
         }
-        Ok(self.addr_64bit.unwrap())
     }
 
-    pub fn send<'a>(&mut self, data: &'a [u8]) -> Result<usize> {
-        Ok(self.serial.write(data)?)
+    /// Caches `info`, keeping the deprecated public fields in step
+    #[allow(deprecated)]
+    fn set_info(&mut self, info: DeviceInfo) {
+        self.addr_64bit = Some(info.addr_64.0);
+        self.node_id = Some(info.node_id.clone());
+        self.firmware_version = Some(info.firmware);
+        self.hardware_version = Some(info.hardware);
+        self.info = Some(info);
     }
 
-    pub fn discover_nodes(&mut self, timeout: Option<std::time::Duration>) -> Result<()> {
-        let discover_cmd = api::AtCommandFrame("ND", None).gen()?;
-        self.serial.write(&discover_cmd[..])?;
-        let old_timeout = self.serial.timeout();
+    /// Drops everything cached about the local radio and reads it again,
+    /// e.g. after the radio rebooted
+    pub fn reinitialize(&mut self) -> Result<()> {
+        self.info = None;
+        self.api_options = None;
+        self.pending_statuses.clear();
+        self.retransmits.clear();
+        self.pending_responses.clear();
+        self.info()?;
+        Ok(())
+    }
 
-        match timeout {
-            Some(t) => self.serial.set_timeout(t)?,
-            None => self
-                .serial
-                .set_timeout(std::time::Duration::from_secs(15))?,
+    pub fn timeout_policy(&self) -> &TimeoutPolicy {
+        &self.timeout_policy
+    }
+
+    /// Framing used with the radio, as detected when connecting
+    pub fn api_mode(&self) -> api::ApiMode {
+        self.api_mode
+    }
+
+    pub fn set_timeout_policy(&mut self, policy: TimeoutPolicy) {
+        self.timeout_policy = policy;
+    }
+
+    pub fn retry_policy(&self) -> Option<&RetryPolicy> {
+        self.retry_policy.as_ref()
+    }
+
+    /// Retransmits frames that fail to be delivered according to `policy`, or
+    /// stops retransmitting when it is `None`
+    pub fn set_retry_policy(&mut self, policy: Option<RetryPolicy>) {
+        self.retry_policy = policy;
+        if policy.is_none() {
+            self.retransmits.clear();
         }
+    }
 
-        let mut api_responses: Vec<api::AtCommandResponse> = Vec::new();
-        let mut remote_devices: Vec<RemoteDigiMeshDevice> = Vec::new();
-        let mut break_loop = false;
-        loop {
-            if break_loop == true {
-                break;
+    /// Updates the timeout policy with the hop count (NH), broadcast
+    /// retransmissions (MT) and sleep settings (SM, SP) of the local radio
+    pub fn load_timeout_policy(&mut self) -> Result<()> {
+        let hops = ParamValue::decode_u8(&self.at_query("NH")?)?;
+        let mt = ParamValue::decode_u8(&self.at_query("MT")?)?;
+        let sleep_mode = ParamValue::decode_u8(&self.at_query("SM")?)?;
+        let sleep_period = if sleep_mode != 0 {
+            // SP is in units of 10 ms
+            let sp = ParamValue::decode_u64(&self.at_query("SP")?)?;
+            Some(Duration::from_millis(sp * 10))
+        } else {
+            None
+        };
+
+        self.timeout_policy.hops = hops;
+        self.timeout_policy.broadcast_transmissions = mt.saturating_add(1);
+        self.timeout_policy.sleep_period = sleep_period;
+        Ok(())
+    }
+
+    pub fn escape_sequence(&self) -> &EscapeSequence {
+        &self.escape_sequence
+    }
+
+    pub fn set_escape_sequence(&mut self, escape: EscapeSequence) {
+        self.escape_sequence = escape;
+    }
+
+    /// Updates the escape sequence with the guard time (GT) and command
+    /// character (CC) of the local radio
+    pub fn load_escape_sequence(&mut self) -> Result<()> {
+        let gt = ParamValue::decode_u16(&self.at_query("GT")?)?;
+        let cc = ParamValue::decode_u8(&self.at_query("CC")?)?;
+        self.escape_sequence = EscapeSequence {
+            guard_time: Duration::from_millis(gt as u64),
+            command_char: cc,
+        };
+        Ok(())
+    }
+
+    /// Keeps the escape sequence in step with GT or CC changed through this
+    /// device
+    fn note_setting(&mut self, cmd: &str, value: &[u8]) {
+        if cmd.eq_ignore_ascii_case("GT") {
+            if let Ok(gt) = ParamValue::decode_u16(value) {
+                self.escape_sequence.guard_time = Duration::from_millis(gt as u64);
+            }
+        } else if cmd.eq_ignore_ascii_case("CC") {
+            if let Ok(cc) = ParamValue::decode_u8(value) {
+                self.escape_sequence.command_char = cc;
             }
-            println!("Iteration...");
-            let response = api::AtCommandResponse::recieve(self.serial.try_clone()?);
+        }
+    }
 
-            match response {
-                Ok(resp) => api_responses.push(resp),
-                Err(_) => {
-                    break_loop = true;
-                }
+    /// Runs `check_health` if keepalive is enabled and its interval has passed.
+    /// Meant to be called from the application's main loop.
+    pub fn poll_keepalive(&mut self) -> Result<Option<HealthEvent>> {
+        match self.keepalive {
+            Some(ref keepalive) if keepalive.last.elapsed() >= keepalive.interval => {
+                self.check_health().map(Some)
             }
+            _ => Ok(None),
         }
-        self.serial.set_timeout(old_timeout)?;
+    }
 
-        if api_responses.len() > 0 {
-            println!("{:?}", api_responses);
+    /// Queries VR on the local radio to find out whether it still responds
+    pub fn check_health(&mut self) -> Result<HealthEvent> {
+        let alive = self.try_at_query("VR")?.is_some();
+        let reinitialize = match self.keepalive {
+            Some(ref mut keepalive) => {
+                keepalive.last = Instant::now();
+                keepalive.reinitialize
+            }
+            None => false,
+        };
 
-            for rd in api_responses.iter() {
-                let ref buf = &rd.command_data.as_ref().unwrap();
-                let addr = u64::from_be_bytes(<[u8; 8]>::try_from(&buf[2..10]).unwrap());
-                let mut end_idx = 10;
-                for i in 10..buf.len() - 1 {
-                    if buf[i] == 0 {
-                        break;
-                    }
-                    end_idx += 1;
+        let event = match (self.responsive, alive) {
+            (_, false) => HealthEvent::Unresponsive,
+            (true, true) => HealthEvent::Alive,
+            (false, true) => {
+                if reinitialize {
+                    self.reinitialize()?;
+                }
+                HealthEvent::Recovered {
+                    reinitialized: reinitialize,
                 }
-                let node_id = std::str::from_utf8(&buf[10..end_idx])?;
-                let d = RemoteDigiMeshDevice {
-                    addr_64bit: addr,
-                    node_id: String::from(node_id),
-                    firmware_version: None,
-                    hardware_version: None,
-                };
-
-                remote_devices.push(d);
             }
-            self.nodes = Some(remote_devices);
-            return Ok(());
-        }
-        Err(Error::DiscoveryError)
+        };
+        self.responsive = alive;
+        Ok(event)
     }
 
-    pub fn send_frame<T: api::TransmitApiFrame>(
-        &mut self,
-        frame: T,
-    ) -> Result<Box<dyn api::RecieveApiFrame>> {
-        let packet = frame.gen()?; // creats bytes mut
-        self.serial.write(&packet[..])?;
-        let response: Box<dyn api::RecieveApiFrame>;
-
-        let old_timeout = self.serial.timeout();
-        if frame.id() == api::FrameId::TransmitRequest {
-            response = Box::new(api::TransmitStatus::recieve(self.serial.try_clone()?)?);
-        } else if frame.id() == api::FrameId::AtCommand {
-            self.serial
-                .set_timeout(std::time::Duration::from_millis(100))?;
-            response = Box::new(api::AtCommandResponse::recieve(self.serial.try_clone()?)?);
-        } else if frame.id() == api::FrameId::RemoteAtCommand {
-            self.serial
-                .set_timeout(std::time::Duration::from_millis(3000))?;
-            response = Box::new(api::RemoteAtCommandResponse::recieve(
-                self.serial.try_clone()?,
-            )?);
-        } else {
-            response = Box::new(api::NullRecieve::recieve(self.serial.try_clone()?)?);
+    /// Like `at_query`, but `None` if the radio did not answer the API frame
+    fn try_at_query(&mut self, cmd: &str) -> Result<Option<Bytes>> {
+        match self.at_query(cmd) {
+            Ok(data) => Ok(Some(data)),
+            Err(Error::ApiError(_)) => Ok(None),
+            Err(e) => Err(e),
         }
+    }
 
-        self.serial.set_timeout(old_timeout)?;
-        Ok(response)
+    /// Reads AP over an API frame, `None` if the radio did not answer one
+    fn query_api_mode(&mut self) -> Result<Option<u64>> {
+        Ok(self
+            .try_at_query("AP")?
+            .map(|ap| ParamValue::decode_u64(&ap))
+            .transpose()?)
     }
 
-    /// send an AT command and returns the result
-    pub fn atcmd<'a>(&mut self, atcmd: &'a AtCommand) -> Result<()> {
-        self.tx_buf.clear();
-        self.rx_buf.clear();
+    /// Probes how the radio talks: API frames in the configured framing, then
+    /// in the other one, then command mode, which only a radio in transparent
+    /// mode is expected to need. The device adapts its framing to the AP the
+    /// radio reports, and is marked as transparent if it reports AP=0.
+    pub fn detect_mode(&mut self) -> Result<OperatingMode> {
+        let configured = self.api_mode;
+        let other = match configured {
+            api::ApiMode::Unescaped => api::ApiMode::Escaped,
+            api::ApiMode::Escaped => api::ApiMode::Unescaped,
+        };
+        for mode in [configured, other].iter() {
+            self.api_mode = *mode;
+            if let Some(ap) = self.query_api_mode()? {
+                return self.adopt_mode(ap);
+            }
+        }
+        self.api_mode = configured;
 
-        if atcmd.command != "+++" {
-            self.tx_buf.put(&b"AT"[..]);
-            self.tx_buf.put(atcmd.command.as_bytes());
+        self.serial.clear(ClearBuffer::Input)?;
+        self.resync_buf.clear();
+        let ap = self.command_mode(true).and_then(|_| {
+            self.atcmd(&AtCommands::AtCmd(("AP", None)).create())?;
+            let reply = String::from_utf8_lossy(&self.rx_buf).trim().to_string();
+            u64::from_str_radix(&reply, 16)
+                .map_err(|_| Error::InvalidMode(format!("Radio answered ATAP with {:?}", reply)))
+        });
+        let ap = match ap {
+            Ok(ap) => ap,
+            Err(Error::ApiError(api::Error::Timeout { .. })) => return Err(Error::InvalidMode(
+                "Radio answers neither API frames nor command mode; check the port and baud rate"
+                    .to_string(),
+            )),
+            Err(err) => return Err(err),
+        };
+        self.command_mode(false)?;
+        self.adopt_mode(ap)
+    }
 
-            if let Some(data) = &atcmd.parameter {
-                self.tx_buf.put(&data[..]);
+    /// Takes on the framing of a radio reporting `ap`
+    fn adopt_mode(&mut self, ap: u64) -> Result<OperatingMode> {
+        if ap == 0 {
+            self.transparent = true;
+            return Ok(OperatingMode::Transparent);
+        }
+        match api::ApiMode::from_value(ap as u8).filter(|_| ap <= 2) {
+            Some(mode) => {
+                self.api_mode = mode;
+                self.transparent = false;
+                Ok(OperatingMode::Api(mode))
             }
-            self.tx_buf.put_u8(0x0d);
-        } else {
-            self.tx_buf.put(atcmd.command.as_bytes());
+            None => Err(Error::InvalidMode(format!(
+                "Radio reports AP={}, which is not supported",
+                ap
+            ))),
         }
+    }
 
-        self.serial.write(&self.tx_buf[..])?;
-        let mut buf: [u8; 1] = [0; 1];
-        let mut cr_counter = 0;
-        loop {
-            if buf[0] == b'\r' {
-                cr_counter += 1;
-                if cr_counter == atcmd.rcr_len {
-                    break;
-                }
-            }
-            self.serial.read_exact(&mut buf)?;
-            self.rx_buf.put_u8(buf[0]);
+    /// Sets AP to the configured API mode through command mode, writing it to
+    /// flash if `save` is set, and checks the radio answers API frames after
+    fn switch_to_api_mode(&mut self, save: bool) -> Result<()> {
+        let ap = [b'0' + self.api_mode.value()];
+        self.serial.clear(ClearBuffer::Input)?;
+        self.resync_buf.clear();
+        self.command_mode(true)?;
+        self.atcmd(&AtCommands::AtCmd(("AP", Some(&ap[..]))).create())?;
+        self.expect_ok("ATAP")?;
+        if save {
+            self.atcmd(&AtCommands::AtCmd(("WR", None)).create())?;
+            self.expect_ok("ATWR")?;
         }
+        self.command_mode(false)?;
+        self.expect_ok("ATCN")?;
 
-        if self.rx_buf.len() < 1 {
-            return Err(Error::IOError(std::io::Error::new(
-                std::io::ErrorKind::UnexpectedEof,
-                "RX buf empty",
-            )));
+        match self.query_api_mode()? {
+            Some(1) | Some(2) => Ok(()),
+            _ => Err(Error::InvalidMode(format!(
+                "Radio did not enter API mode after setting AP={}",
+                self.api_mode.value()
+            ))),
         }
-        Ok(())
     }
 
-    pub fn command_mode(&mut self, mode: bool) -> Result<()> {
-        match mode {
-            true => {
-                thread::sleep(Duration::from_millis(1000));
-                self.atcmd(&AtCommands::CmdMode(true).create())?;
-                thread::sleep(Duration::from_millis(1000));
+    /// Checks the reply to the last command mode command left in `rx_buf`
+    fn expect_ok(&self, cmd: &str) -> Result<()> {
+        if self.rx_buf.starts_with(b"OK") {
+            return Ok(());
+        }
+        Err(Error::InvalidMode(format!(
+            "Radio answered {} with {:?} in command mode",
+            cmd,
+            String::from_utf8_lossy(&self.rx_buf)
+        )))
+    }
+
+    /// Reads a local AT parameter, see `at_command`
+    fn at_query(&mut self, cmd: &str) -> Result<Bytes> {
+        self.at_command(cmd, None)
+    }
+
+    /// Sends a local AT command and returns its command data, failing with
+    /// `Error::AtCommandFailed` unless the radio answered OK
+    pub(crate) fn at_command(&mut self, cmd: &str, param: Option<&[u8]>) -> Result<Bytes> {
+        let response = self.send_frame(api::AtCommandFrame(cmd, param))?;
+        let data = at_response_data(cmd, &*response)?;
+        if let Some(param) = param {
+            self.note_setting(cmd, param);
+            if cmd.eq_ignore_ascii_case("EE") && self.info.is_some() {
+                // encryption changes the maximum payload
+                let max_payload = self.query_max_payload()?;
+                if let Some(info) = self.info.as_mut() {
+                    info.max_payload = max_payload;
+                }
+            }
+        }
+        Ok(data)
+    }
+
+    /// Queues every setting with AT Command Queue frames, then applies them
+    /// together with AC, so the radio never runs with only some of them.
+    /// If a setting is rejected the ones before it stay queued until the next
+    /// AC or WR.
+    pub fn apply_settings(&mut self, settings: &[(&str, &[u8])]) -> Result<()> {
+        for (cmd, value) in settings {
+            let response = self.send_frame(api::AtCommandQueueFrame::new(cmd, Some(value))?)?;
+            at_response_data(cmd, &*response)?;
+        }
+        self.at_command("AC", None)?;
+        // any of the settings may change what was cached
+        self.info = None;
+        self.api_options = None;
+        Ok(())
+    }
+
+    pub fn get_firmware_version(&mut self) -> Result<u16> {
+        Ok(self.info()?.firmware)
+    }
+
+    pub fn get_hardware_version(&mut self) -> Result<u16> {
+        Ok(self.info()?.hardware)
+    }
+
+    /// Node identifier with any invalid UTF-8 replaced
+    pub fn get_node_id(&mut self) -> Result<String> {
+        let node_id = self.get_node_id_bytes()?;
+        Ok(String::from_utf8_lossy(&node_id).into_owned())
+    }
+
+    pub fn get_node_id_bytes(&mut self) -> Result<Vec<u8>> {
+        Ok(self.info()?.node_id.clone())
+    }
+
+    pub fn get_64bit_addr(&mut self) -> Result<Addr64> {
+        Ok(self.info()?.addr_64)
+    }
+
+    /// Returns a handle for operations that span every node on the network
+    pub fn network(&mut self) -> Network<'_> {
+        Network::new(self)
+    }
+
+    /// Writes `data` as is, without the escaping of API mode 2
+    pub fn send(&mut self, data: &[u8]) -> Result<usize> {
+        self.write_paced(data)?;
+        Ok(data.len())
+    }
+
+    /// Switches the radio to transparent mode (AP=0) until `enter_api_mode` is
+    /// called. AP is not written to flash, so the radio is back in API mode
+    /// after a reset. API frames cannot be used in the meantime.
+    pub fn enter_transparent_mode(&mut self) -> Result<()> {
+        if self.transparent {
+            return Ok(());
+        }
+        self.at_command("AP", Some(&[0]))?;
+        self.transparent = true;
+        Ok(())
+    }
+
+    /// Switches a radio in transparent mode back to the configured API mode
+    /// through command mode. Data recieved but not read yet is discarded.
+    pub fn enter_api_mode(&mut self) -> Result<()> {
+        self.transparent_rx.clear();
+        self.switch_to_api_mode(false)?;
+        self.transparent = false;
+        Ok(())
+    }
+
+    /// Whether the radio was switched to transparent mode by
+    /// `enter_transparent_mode`
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    /// Writes `data` to a radio in transparent mode, which sends it to the
+    /// node set by DH and DL
+    pub fn transparent_send(&mut self, data: &[u8]) -> Result<usize> {
+        self.require_transparent()?;
+        self.write_paced(data)?;
+        Ok(data.len())
+    }
+
+    /// Reads data recieved by a radio in transparent mode until `until` is
+    /// met, waiting up to `timeout`. When it times out the bytes read so far
+    /// are kept for the next call.
+    pub fn transparent_recv(&mut self, until: ReadUntil, timeout: Duration) -> Result<Vec<u8>> {
+        self.require_transparent()?;
+        let deadline = Instant::now() + timeout;
+        let old_timeout = self.serial.timeout();
+        let mut chunk = [0u8; 256];
+        let result = loop {
+            let end = match until {
+                ReadUntil::Delimiter(byte) => self
+                    .transparent_rx
+                    .iter()
+                    .position(|b| *b == byte)
+                    .map(|pos| pos + 1),
+                ReadUntil::Len(len) if self.transparent_rx.len() >= len => Some(len),
+                ReadUntil::Len(_) => None,
+            };
+            if let Some(end) = end {
+                break Ok(self.transparent_rx.split_to(end).to_vec());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_millis(0) {
+                break Err(Error::ApiError(api::Error::Timeout {
+                    operation: "transparent data",
+                }));
+            }
+            if let Err(err) = self.serial.set_timeout(remaining) {
+                break Err(err.into());
+            }
+            match self.serial.read(&mut chunk) {
+                Ok(read) => self.transparent_rx.extend_from_slice(&chunk[..read]),
+                Err(err) if err.kind() == std::io::ErrorKind::TimedOut => {}
+                Err(err) => break Err(err.into()),
+            }
+        };
+        self.serial.set_timeout(old_timeout)?;
+        result
+    }
+
+    fn require_transparent(&self) -> Result<()> {
+        if self.transparent {
+            Ok(())
+        } else {
+            Err(Error::InvalidMode(
+                "Radio is not in transparent mode".to_string(),
+            ))
+        }
+    }
+
+    /// Writes a complete frame, escaped if the radio runs in API mode 2. Only
+    /// frames sent over the air are held back by the rate limit.
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        let escaped;
+        let data = match self.api_mode {
+            api::ApiMode::Unescaped => frame,
+            api::ApiMode::Escaped => {
+                escaped = api::escape_frame(frame);
+                &escaped[..]
+            }
+        };
+        match frame.get(3) {
+            Some(kind) if is_rf_transmit(*kind) => self.write_paced(data),
+            _ => self.write_now(data),
+        }
+    }
+
+    /// Writes to the radio, waiting first if the rate limit requires it
+    fn write_paced(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(ref throttle) = self.throttle {
+            let wait = throttle.next_send.saturating_duration_since(Instant::now());
+            if wait > Duration::from_millis(0) {
+                thread::sleep(wait);
+            }
+        }
+        self.write_now(data)?;
+        if let Some(ref mut throttle) = self.throttle {
+            throttle.next_send = Instant::now() + throttle.limit.interval(data.len());
+        }
+        Ok(())
+    }
+
+    fn write_now(&mut self, data: &[u8]) -> Result<()> {
+        self.serial.write_all(data)?;
+        self.last_write = Some(Instant::now());
+        Ok(())
+    }
+
+    /// API Options (AO) of the local device, which decide whether recieved data
+    /// arrives as Receive Packet or Explicit Rx Indicator frames. Queried once
+    /// and cached.
+    pub fn get_api_options(&mut self) -> Result<api::ApiOptions> {
+        if let Some(options) = self.api_options {
+            return Ok(options);
+        }
+        let ao = self.at_query("AO")?;
+        let value = ParamValue::decode_u8(&ao)?;
+        let options = api::ApiOptions::from_value(value).ok_or_else(|| {
+            Error::InvalidMode(format!("Unsupported API options value 0x{:02x}", value))
+        })?;
+        self.api_options = Some(options);
+        Ok(options)
+    }
+
+    /// Changes the API Options (AO) of the local device and applies them
+    /// immediately. The setting is not written to flash.
+    pub fn set_api_options(&mut self, options: api::ApiOptions) -> Result<()> {
+        self.at_command("AO", Some(&[options.value()]))?;
+        self.api_options = Some(options);
+        Ok(())
+    }
+
+    /// Largest RF payload the radio accepts in its current configuration (NP).
+    /// Enabling encryption lowers it, so it is read again when EE is changed
+    /// through this device.
+    pub fn max_payload(&mut self) -> Result<usize> {
+        self.info()?
+            .max_payload
+            .ok_or_else(|| Error::InvalidMode("Radio does not report NP".to_string()))
+    }
+
+    /// Starts a Transmit Request whose payload is checked against NP when it
+    /// is built
+    pub fn transmit_request<'a>(&mut self) -> Result<api::TransmitRequestBuilder<'a>> {
+        let builder = api::TransmitRequestFrame::builder();
+        match self.max_payload() {
+            Ok(max) => Ok(builder.max_payload(max)),
+            Err(Error::InvalidMode(_)) => Ok(builder),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Rejects data frames whose payload is larger than NP. Radios that cannot
+    /// report NP are not checked.
+    fn check_payload(&mut self, frame: api::FrameId, packet: &[u8]) -> Result<()> {
+        let got = match packet_payload_len(frame, packet) {
+            Some(len) => len,
+            None => return Ok(()),
+        };
+        match self.max_payload() {
+            Ok(max) if got > max => Err(Error::PayloadTooLarge { max, got }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Frame type recieved RF data is expected to arrive in
+    pub fn expected_rx_frame(&mut self) -> Result<api::FrameId> {
+        Ok(self.get_api_options()?.rx_frame())
+    }
+
+    /// Nodes found by `discover_nodes`, keyed by address
+    pub fn nodes(&self) -> &NodeTable {
+        &self.node_table
+    }
+
+    pub fn nodes_mut(&mut self) -> &mut NodeTable {
+        &mut self.node_table
+    }
+
+    /// How long to wait for discovery responses. Uses N? (the discovery
+    /// timeout including network propagation) where the firmware supports it,
+    /// otherwise NT plus a margin for propagation.
+    pub fn discovery_timeout(&mut self) -> Result<Duration> {
+        if let Ok(timeout) = self.at_query("N?") {
+            return Ok(Duration::from_millis(ParamValue::decode_u64(&timeout)?));
+        }
+        let nt = self.at_query("NT")?; // units of 100ms
+        Ok(Duration::from_millis(ParamValue::decode_u64(&nt)? * 100) + DISCOVERY_MARGIN)
+    }
+
+    /// Runs a network discovery (ND) and merges every responding node into
+    /// `nodes()`. A node that answers more than once is only recorded once, with
+    /// the freshest NI and RSSI kept. Returns the number of unique nodes that
+    /// answered.
+    ///
+    /// Without an explicit `timeout` the wait is derived from the device's
+    /// discovery settings (see `discovery_timeout`). The NO option bits are
+    /// honored when parsing responses, and the local device's own response
+    /// (NO bit 1) is not recorded as a remote node.
+    #[allow(deprecated)]
+    pub fn discover_nodes(&mut self, timeout: Option<std::time::Duration>) -> Result<usize> {
+        let timeout = match timeout {
+            Some(t) => t,
+            None => self
+                .discovery_timeout()
+                .unwrap_or(DEFAULT_DISCOVERY_TIMEOUT),
+        };
+        let options = self
+            .at_query("NO")
+            .ok()
+            .and_then(|no| ParamValue::decode_u8(&no).ok());
+
+        let discover_cmd = api::AtCommandFrame("ND", None).gen()?;
+        if let Err(err) = self.write_frame(&discover_cmd[..]) {
+            release_frame_id(discover_cmd[4]);
+            return Err(err);
+        }
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + timeout;
+
+        let mut found: HashSet<Addr64> = HashSet::new();
+        let result = loop {
+            let frame = match self.read_frame_before(deadline) {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => break Ok(()),
+                Err(err) => break Err(err),
+            };
+            let response = match api::AtCommandResponse::from_frame(frame.clone()) {
+                Ok(response) => response,
+                Err(_) => {
+                    // not a discovery response
+                    self.stash(frame);
+                    continue;
+                }
+            };
+            if response.at_command != b"ND" || !response.is_ok() {
+                self.stash(frame);
+                continue;
+            }
+
+            if let Some(node) = response
+                .command_data
+                .as_ref()
+                .and_then(|data| parse_discovery_response(data, options))
+            {
+                if Some(node.addr_64bit) == self.info.as_ref().map(|i| i.addr_64) {
+                    continue;
+                }
+                found.insert(node.addr_64bit);
+                self.node_table.merge(node);
+            }
+        };
+        release_frame_id(discover_cmd[4]);
+        self.serial.set_timeout(old_timeout)?;
+        result?;
+
+        if found.is_empty() {
+            return Err(Error::DiscoveryError);
+        }
+        self.nodes = Some(self.node_table.iter().cloned().collect());
+        Ok(found.len())
+    }
+
+    /// Reads the next API frame, giving up with a timeout once `deadline` passes.
+    /// Modem statuses are set aside for `take_modem_statuses` rather than
+    /// returned, and frames with a registered handler go to it. The serial
+    /// timeout is left changed; callers restore it.
+    fn read_frame_before(&mut self, deadline: Instant) -> Result<BytesMut> {
+        self.read_frame_for(deadline, None)
+    }
+
+    /// Like `read_frame_before`, but frames of type `wanted` are always
+    /// returned, even if a handler is registered for them
+    fn read_frame_for(
+        &mut self,
+        deadline: Instant,
+        wanted: Option<api::FrameId>,
+    ) -> Result<BytesMut> {
+        loop {
+            let frame = self.read_any_frame_before(deadline)?;
+            if wanted.is_some() && frame.get(3).copied() == wanted.map(|wanted| wanted.id()) {
+                return Ok(frame);
+            }
+            let frame = match self.dispatch(frame) {
+                Some(frame) => frame,
+                None => continue,
+            };
+            match api::ModemStatus::from_frame(frame.clone()) {
+                Ok(status) => {
+                    if self.modem_statuses.len() == MODEM_STATUS_BACKLOG {
+                        self.modem_statuses.pop_front();
+                    }
+                    self.modem_statuses.push_back(status);
+                }
+                Err(_) => return Ok(frame),
+            }
+        }
+    }
+
+    /// Passes `frame` to its handler, handing it back if there is none or it
+    /// does not parse
+    fn dispatch(&mut self, frame: BytesMut) -> Option<BytesMut> {
+        let kind = *frame.get(3)?;
+        if kind == api::FrameId::ReceivePacket.id() {
+            if let Some(handler) = self.handlers.data.as_mut() {
+                if let Ok(packet) = api::ReceivePacket::from_frame(frame.clone()) {
+                    handler(&packet);
+                    return None;
+                }
+            }
+        } else if kind == api::FrameId::ModemStatus.id() {
+            if let Some(handler) = self.handlers.modem_status.as_mut() {
+                if let Ok(status) = api::ModemStatus::from_frame(frame.clone()) {
+                    handler(&status);
+                    return None;
+                }
+            }
+        } else if kind == api::FrameId::NodeIdentificationIndicator.id()
+            && self.handlers.node_identification.is_some()
+        {
+            if let Ok(indicator) = api::NodeIdentificationIndicator::from_frame(frame.clone()) {
+                self.note_identification(&indicator);
+                if let Some(handler) = self.handlers.node_identification.as_mut() {
+                    handler(&indicator);
+                }
+                return None;
+            }
+        }
+        Some(frame)
+    }
+
+    /// Calls `handler` with every Receive Packet that arrives while no
+    /// `recv_packet` is waiting for one, replacing any earlier handler. Frames
+    /// are only read while the device is in use, so an application that does
+    /// nothing else should call `dispatch_events` in a loop.
+    pub fn on_data<F>(&mut self, handler: F)
+    where
+        F: FnMut(&api::ReceivePacket) + Send + 'static,
+    {
+        self.handlers.data = Some(Box::new(handler));
+    }
+
+    /// Calls `handler` with every unsolicited modem status instead of keeping
+    /// it for `take_modem_statuses`
+    pub fn on_modem_status<F>(&mut self, handler: F)
+    where
+        F: FnMut(&api::ModemStatus) + Send + 'static,
+    {
+        self.handlers.modem_status = Some(Box::new(handler));
+    }
+
+    /// Calls `handler` whenever a remote node identifies itself, after adding
+    /// the node to the node table
+    pub fn on_node_identification<F>(&mut self, handler: F)
+    where
+        F: FnMut(&api::NodeIdentificationIndicator) + Send + 'static,
+    {
+        self.handlers.node_identification = Some(Box::new(handler));
+    }
+
+    /// Removes every handler registered with the `on_*` methods
+    pub fn clear_handlers(&mut self) {
+        self.handlers = Handlers::default();
+    }
+
+    /// Reads frames for `timeout`, passing them to the registered handlers.
+    /// Frames without a handler are kept as they would be while waiting for a
+    /// response.
+    pub fn dispatch_events(&mut self, timeout: Duration) -> Result<()> {
+        // frames kept during earlier calls come first, if they have a handler now
+        let kept = std::mem::take(&mut self.unsolicited);
+        for frame in kept {
+            if let Some(frame) = self.dispatch(frame) {
+                self.unsolicited.push_back(frame);
+            }
+        }
+
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            match self.read_frame_before(deadline) {
+                Ok(frame) => self.stash(frame),
+                Err(Error::ApiError(api::Error::Timeout { .. })) => break Ok(()),
+                Err(err) => break Err(err),
+            }
+        };
+        self.serial.set_timeout(old_timeout)?;
+        result
+    }
+
+    fn read_any_frame_before(&mut self, deadline: Instant) -> Result<BytesMut> {
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::ApiError(api::Error::Timeout {
+                operation: "API frame",
+            }));
+        }
+        self.serial.set_timeout(deadline - now)?;
+        self.read_resync()
+    }
+
+    /// Reads the next frame, skipping anything that does not belong to one
+    fn read_resync(&mut self) -> Result<BytesMut> {
+        let (frame, skipped) =
+            api::read_frame_skipping(&mut *self.serial, self.api_mode, &mut self.resync_buf)?;
+        self.skipped_bytes += skipped as u64;
+        Ok(frame)
+    }
+
+    /// Total number of bytes discarded so far because they did not belong to a
+    /// frame, e.g. output of a radio booting or noise on the serial line
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped_bytes
+    }
+
+    /// Modem statuses the radio sent since the last call, oldest first. They
+    /// arrive unsolicited, e.g. after a reset, and are collected while reading
+    /// responses to other frames.
+    pub fn take_modem_statuses(&mut self) -> Vec<api::ModemStatus> {
+        self.modem_statuses.drain(..).collect()
+    }
+
+    /// Data and indications that arrived while a call waited for something
+    /// else and were not collected by a `recv_*` method yet, oldest first.
+    /// Frames that fail to parse are left out.
+    pub fn take_unsolicited(&mut self) -> Vec<api::XBeeFrame> {
+        self.unsolicited
+            .drain(..)
+            .filter_map(|frame| api::parse_frame(&frame).ok())
+            .collect()
+    }
+
+    /// Waits for the next modem status, returning collected ones first
+    pub fn wait_for_modem_status(&mut self, timeout: Duration) -> Result<api::ModemStatus> {
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            if let Some(status) = self.modem_statuses.pop_front() {
+                break Ok(status);
+            }
+            match self.read_any_frame_before(deadline) {
+                Ok(frame) => match api::ModemStatus::from_frame(frame.clone()) {
+                    Ok(status) => break Ok(status),
+                    Err(_) => {
+                        if let Some(frame) = self.dispatch(frame) {
+                            self.stash(frame);
+                        }
+                    }
+                },
+                Err(Error::ApiError(api::Error::Timeout { .. })) => {
+                    break Err(Error::ApiError(api::Error::Timeout {
+                        operation: "Modem Status",
+                    }))
+                }
+                Err(err) => break Err(err),
+            }
+        };
+        self.serial.set_timeout(old_timeout)?;
+        result
+    }
+
+    /// Keeps a frame nobody was waiting for: Transmit Statuses for
+    /// `wait_for_transmit_status`, other responses to frames in flight for
+    /// `wait_for_response` and anything that is not a response for the
+    /// `recv_*` methods. Late responses to frames no longer in flight are dropped.
+    fn stash(&mut self, frame: BytesMut) {
+        let kind = match frame.get(3) {
+            Some(kind) => *kind,
+            None => return,
+        };
+        if kind == api::FrameId::TransmitStatus.id() {
+            if let Ok(status) = api::TransmitStatus::from_frame(frame) {
+                api::FRAME_ID_ALLOCATOR
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .release(status.frame_id);
+                self.pending_statuses.insert(status.frame_id, status);
+            }
+        } else if is_response(kind) {
+            let in_flight = frame.len() > 5
+                && api::FRAME_ID_ALLOCATOR
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .is_in_flight(frame[4]);
+            if in_flight {
+                self.pending_responses.insert(frame[4], frame);
+            }
+        } else {
+            if self.unsolicited.len() == UNSOLICITED_BACKLOG {
+                self.unsolicited.pop_front();
+            }
+            self.unsolicited.push_back(frame);
+        }
+    }
+
+    /// Waits for the response to a frame sent with `transmit`, whatever its
+    /// type. Responses to other frames in flight that arrive in the meantime
+    /// are kept for later calls.
+    pub fn wait_for_response(&mut self, frame_id: u8, timeout: Duration) -> Result<api::XBeeFrame> {
+        if let Some(status) = self.pending_statuses.remove(&frame_id) {
+            self.retransmits.remove(&frame_id);
+            return Ok(api::XBeeFrame::TransmitStatus(status));
+        }
+        let frame = match self.pending_responses.remove(&frame_id) {
+            Some(frame) => Ok(frame),
+            None => {
+                let old_timeout = self.serial.timeout();
+                let deadline = Instant::now() + timeout;
+                let result = loop {
+                    let frame = match self.read_frame_before(deadline) {
+                        Ok(frame) => frame,
+                        Err(Error::ApiError(api::Error::Timeout { .. })) => {
+                            break Err(Error::ApiError(api::Error::Timeout {
+                                operation: "response",
+                            }))
+                        }
+                        Err(err) => break Err(err),
+                    };
+                    if frame.len() > 5 && is_response(frame[3]) && frame[4] == frame_id {
+                        break Ok(frame);
+                    }
+                    self.stash(frame);
+                };
+                self.serial.set_timeout(old_timeout)?;
+                result
+            }
+        };
+        api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release(frame_id);
+        self.retransmits.remove(&frame_id);
+        Ok(api::parse_frame(&frame?)?)
+    }
+
+    /// Sends an arbitrary frame type. `body` is everything following the frame
+    /// type byte (including the frame id, if the type has one); the delimiter,
+    /// length and checksum are added automatically.
+    pub fn send_raw(&mut self, frame_type: u8, body: &[u8]) -> Result<()> {
+        let packet = api::encode_frame(frame_type, body)?;
+        self.write_frame(&packet[..])?;
+        Ok(())
+    }
+
+    /// Reads the next frame of any type, returning its frame type and the
+    /// frame data between the type byte and the checksum
+    pub fn recv_raw(&mut self) -> Result<(u8, BytesMut)> {
+        let mut frame = self.read_resync()?;
+        if frame.len() < 5 {
+            return Err(Error::ApiError(api::Error::FrameError(
+                "Frame has no frame type".to_string(),
+            )));
+        }
+        let frame_type = frame[3];
+        if frame_type == api::FrameId::TransmitStatus.id() && frame.len() > 5 {
+            // the caller has the status, so nothing will retransmit the frame
+            release_frame_id(frame[4]);
+            self.retransmits.remove(&frame[4]);
+        }
+        frame.truncate(frame.len() - 1);
+        Ok((frame_type, frame.split_off(4)))
+    }
+
+    /// Writes `frame` without waiting for its Transmit Status and returns the
+    /// frame id it was sent with. Several frames can be sent back to back this
+    /// way and their statuses collected afterwards with `wait_for_transmit_status`.
+    /// Fire and forget frames are sent with id 0, which gets no status.
+    pub fn transmit<T: api::TransmitApiFrame>(&mut self, frame: T) -> Result<u8> {
+        let packet = frame.gen()?;
+        let frame_id = packet[4];
+        if let Err(err) = self.check_payload(frame.id(), &packet) {
+            release_frame_id(frame_id);
+            return Err(err);
+        }
+        self.forget_frame_id(frame_id);
+        if let Err(err) = self.write_frame(&packet[..]) {
+            release_frame_id(frame_id);
+            return Err(err);
+        }
+        self.keep_for_retry(packet);
+        Ok(frame_id)
+    }
+
+    /// Generates `frame` and queues it for `send_queued`, returning its frame id.
+    /// Higher priority frames are written first; frames of equal priority keep
+    /// their order.
+    pub fn enqueue<T: api::TransmitApiFrame>(
+        &mut self,
+        frame: T,
+        priority: Priority,
+    ) -> Result<u8> {
+        let packet = frame.gen()?;
+        let frame_id = packet[4];
+        if let Err(err) = self.check_payload(frame.id(), &packet) {
+            release_frame_id(frame_id);
+            return Err(err);
+        }
+        self.forget_frame_id(frame_id);
+        self.tx_queue.push(priority, packet);
+        Ok(frame_id)
+    }
+
+    /// Writes the highest priority queued frame, returning its frame id or
+    /// `None` if the queue is empty. Statuses are collected the same way as for
+    /// `transmit`.
+    pub fn send_queued(&mut self) -> Result<Option<u8>> {
+        match self.tx_queue.pop() {
+            Some(packet) => {
+                let frame_id = packet[4];
+                if let Err(err) = self.write_frame(&packet[..]) {
+                    release_frame_id(frame_id);
+                    return Err(err);
+                }
+                self.keep_for_retry(packet);
+                Ok(Some(frame_id))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Writes every queued frame in priority order, returning how many were sent
+    pub fn flush_queue(&mut self) -> Result<usize> {
+        let mut sent = 0;
+        while self.send_queued()?.is_some() {
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    pub fn queued_len(&self) -> usize {
+        self.tx_queue.len()
+    }
+
+    /// Drops the status, response and retransmit kept for an earlier frame with
+    /// `frame_id`, which are stale now that the id is used again
+    fn forget_frame_id(&mut self, frame_id: u8) {
+        self.pending_statuses.remove(&frame_id);
+        self.pending_responses.remove(&frame_id);
+        self.retransmits.remove(&frame_id);
+    }
+
+    /// Remembers a frame that was just written so `wait_for_transmit_status`
+    /// can retransmit it
+    fn keep_for_retry(&mut self, packet: BytesMut) {
+        // frame id 0 gets no Transmit Status to react to
+        if self.retry_policy.is_some() && packet[4] != 0 {
+            self.retransmits.insert(packet[4], (packet, 0));
+        }
+    }
+
+    /// Waits for the Transmit Status of a frame previously sent with `transmit`.
+    /// Statuses for other frame ids that arrive in the meantime are kept so they
+    /// can be collected by later calls.
+    ///
+    /// With a retry policy set, the frame is retransmitted while its status
+    /// reports a transient failure, waiting up to `timeout` for each status.
+    /// `Error::DeliveryFailed` is returned once the retries are used up.
+    ///
+    /// Frame id 0 is rejected straight away, since the radio sends no status
+    /// for it.
+    pub fn wait_for_transmit_status(
+        &mut self,
+        frame_id: u8,
+        timeout: Duration,
+    ) -> Result<api::TransmitStatus> {
+        if frame_id == 0 {
+            return Err(Error::ApiError(api::Error::FrameError(
+                "Frame id 0 gets no Transmit Status".to_string(),
+            )));
+        }
+        loop {
+            let status = self.read_transmit_status(frame_id, timeout)?;
+            let kept = self.retransmits.remove(&frame_id);
+            let (policy, (packet, retry)) = match (self.retry_policy, kept) {
+                (Some(policy), Some(kept)) if status.is_retryable() => (policy, kept),
+                _ => return Ok(status),
+            };
+            if retry >= policy.retries {
+                return Err(Error::DeliveryFailed {
+                    status: status.delivery(),
+                    attempts: retry + 1,
+                });
+            }
+            thread::sleep(policy.backoff(retry));
+            api::FRAME_ID_ALLOCATOR
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .reserve(frame_id);
+            if let Err(err) = self.write_frame(&packet[..]) {
+                api::FRAME_ID_ALLOCATOR
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .release(frame_id);
+                return Err(err);
+            }
+            self.retransmits.insert(frame_id, (packet, retry + 1));
+        }
+    }
+
+    fn read_transmit_status(
+        &mut self,
+        frame_id: u8,
+        timeout: Duration,
+    ) -> Result<api::TransmitStatus> {
+        if let Some(status) = self.pending_statuses.remove(&frame_id) {
+            return Ok(status);
+        }
+
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            let frame = match self.read_frame_before(deadline) {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => {
+                    // the status is lost, don't keep the frame id or the packet around
+                    api::FRAME_ID_ALLOCATOR
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .release(frame_id);
+                    self.retransmits.remove(&frame_id);
+                    break Err(Error::ApiError(api::Error::Timeout {
+                        operation: "Transmit Status",
+                    }));
+                }
+                Err(err) => break Err(err),
+            };
+            let status = match api::TransmitStatus::from_frame(frame.clone()) {
+                Ok(status) => status,
+                Err(_) => {
+                    self.stash(frame);
+                    continue;
+                }
+            };
+            api::FRAME_ID_ALLOCATOR
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .release(status.frame_id);
+            if status.frame_id == frame_id {
+                break Ok(status);
+            }
+            self.pending_statuses.insert(status.frame_id, status);
+        };
+        self.serial.set_timeout(old_timeout)?;
+        result
+    }
+
+    /// Waits for RF data from a remote node, delivered as a Receive Packet when
+    /// AO=0. Packets that arrived while waiting for a response to another frame
+    /// are returned first; frames that arrive in the meantime are kept.
+    pub fn recv_packet(&mut self, timeout: Duration) -> Result<api::ReceivePacket> {
+        let frame = self.recv_data(api::FrameId::ReceivePacket, timeout)?;
+        Ok(api::ReceivePacket::from_frame(frame)?)
+    }
+
+    /// Blocks until RF data from a remote node arrives, like `recv_packet`
+    /// without a timeout
+    pub fn recv(&mut self) -> Result<api::ReceivePacket> {
+        loop {
+            match self.recv_packet(RECV_WAIT) {
+                Err(Error::ApiError(api::Error::Timeout { .. })) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for RF data, failing with `api::Error::Timeout`
+    /// if none arrives. The same as `recv_packet`, named to match `recv`.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<api::ReceivePacket> {
+        self.recv_packet(timeout)
+    }
+
+    /// Waits for RF data on legacy 802.15.4 firmware from a sender using its
+    /// 64-bit address
+    pub fn recv_rx64(&mut self, timeout: Duration) -> Result<api::Rx64Packet> {
+        let frame = self.recv_data(api::FrameId::Rx64, timeout)?;
+        Ok(api::Rx64Packet::from_frame(frame)?)
+    }
+
+    /// Waits for RF data on legacy 802.15.4 firmware from a sender using a
+    /// 16-bit address (MY)
+    pub fn recv_rx16(&mut self, timeout: Duration) -> Result<api::Rx16Packet> {
+        let frame = self.recv_data(api::FrameId::Rx16, timeout)?;
+        Ok(api::Rx16Packet::from_frame(frame)?)
+    }
+
+    /// Like `recv_packet`, for radios with explicit API options (AO=1) where
+    /// data arrives with its endpoints, cluster and profile
+    pub fn recv_explicit(&mut self, timeout: Duration) -> Result<api::ExplicitRxIndicator> {
+        let frame = self.recv_data(api::FrameId::ExplicitRxIndicator, timeout)?;
+        Ok(api::ExplicitRxIndicator::from_frame(frame)?)
+    }
+
+    /// Waits for an I/O sample from a remote node configured with IR or IC
+    pub fn recv_io_sample(&mut self, timeout: Duration) -> Result<api::IoSampleRxIndicator> {
+        let frame = self.recv_data(api::FrameId::IoSampleRxIndicator, timeout)?;
+        Ok(api::IoSampleRxIndicator::from_frame(frame)?)
+    }
+
+    /// Waits for a reading from an XBee Sensor or 1-wire sensor adapter
+    pub fn recv_sensor_read(&mut self, timeout: Duration) -> Result<api::SensorReadIndicator> {
+        let frame = self.recv_data(api::FrameId::SensorReadIndicator, timeout)?;
+        Ok(api::SensorReadIndicator::from_frame(frame)?)
+    }
+
+    /// Waits for data on a socket of an XBee Cellular modem
+    pub fn recv_socket(&mut self, timeout: Duration) -> Result<api::SocketReceive> {
+        let frame = self.recv_data(api::FrameId::SocketReceive, timeout)?;
+        Ok(api::SocketReceive::from_frame(frame)?)
+    }
+
+    /// Waits for data from an IPv4 host on XBee Wi-Fi or Cellular modules
+    pub fn recv_ipv4(&mut self, timeout: Duration) -> Result<api::RxIpv4> {
+        let frame = self.recv_data(api::FrameId::RxIpv4, timeout)?;
+        Ok(api::RxIpv4::from_frame(frame)?)
+    }
+
+    /// Waits for a text message on an XBee Cellular modem
+    pub fn recv_sms(&mut self, timeout: Duration) -> Result<api::RxSms> {
+        let frame = self.recv_data(api::FrameId::RxSms, timeout)?;
+        Ok(api::RxSms::from_frame(frame)?)
+    }
+
+    /// Waits for data relayed from the Bluetooth or MicroPython interface
+    pub fn recv_relay(&mut self, timeout: Duration) -> Result<api::UserDataRelayOutput> {
+        let frame = self.recv_data(api::FrameId::UserDataRelayOutput, timeout)?;
+        Ok(api::UserDataRelayOutput::from_frame(frame)?)
+    }
+
+    /// Waits for a route record, sent when the local radio acts as a
+    /// many-to-one concentrator
+    pub fn recv_route_record(&mut self, timeout: Duration) -> Result<api::RouteRecordIndicator> {
+        let frame = self.recv_data(api::FrameId::RouteRecordIndicator, timeout)?;
+        Ok(api::RouteRecordIndicator::from_frame(frame)?)
+    }
+
+    /// Waits for a remote concentrator to send a many-to-one route request,
+    /// after which source routes to it should be refreshed
+    pub fn recv_many_to_one_request(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<api::ManyToOneRouteRequest> {
+        let frame = self.recv_data(api::FrameId::ManyToOneRouteRequest, timeout)?;
+        Ok(api::ManyToOneRouteRequest::from_frame(frame)?)
+    }
+
+    /// Waits for a join diagnostic, reported once verbose joining (VJ=1) is enabled
+    pub fn recv_extended_modem_status(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<api::ExtendedModemStatus> {
+        let frame = self.recv_data(api::FrameId::ExtendedModemStatus, timeout)?;
+        Ok(api::ExtendedModemStatus::from_frame(frame)?)
+    }
+
+    /// Waits for a Digi Remote Manager device request, to be answered with a
+    /// `DeviceResponseFrame`
+    pub fn recv_device_request(&mut self, timeout: Duration) -> Result<api::DeviceRequest> {
+        let frame = self.recv_data(api::FrameId::DeviceRequest, timeout)?;
+        Ok(api::DeviceRequest::from_frame(frame)?)
+    }
+
+    /// Waits for progress of an over-the-air firmware update
+    pub fn recv_ota_status(&mut self, timeout: Duration) -> Result<api::OtaUpdateStatus> {
+        let frame = self.recv_data(api::FrameId::OtaUpdateStatus, timeout)?;
+        Ok(api::OtaUpdateStatus::from_frame(frame)?)
+    }
+
+    /// Waits for a remote node to identify itself, e.g. after its commissioning
+    /// button was pressed, and adds it to the node table
+    pub fn recv_node_identification(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<api::NodeIdentificationIndicator> {
+        let frame = self.recv_data(api::FrameId::NodeIdentificationIndicator, timeout)?;
+        let indicator = api::NodeIdentificationIndicator::from_frame(frame)?;
+        self.note_identification(&indicator);
+        Ok(indicator)
+    }
+
+    fn note_identification(&mut self, indicator: &api::NodeIdentificationIndicator) {
+        self.node_table.merge(RemoteDigiMeshDevice {
+            addr_64bit: indicator.remote_addr,
+            node_id: indicator.node_id.clone(),
+            firmware_version: None,
+            hardware_version: None,
+            rssi: None,
+        });
+    }
+
+    /// Returns the oldest kept frame of type `wanted`, or reads frames until
+    /// one arrives, keeping the others
+    fn recv_data(&mut self, wanted: api::FrameId, timeout: Duration) -> Result<BytesMut> {
+        let kept = self
+            .unsolicited
+            .iter()
+            .position(|frame| frame.get(3) == Some(&wanted.id()));
+        if let Some(frame) = kept.and_then(|pos| self.unsolicited.remove(pos)) {
+            return Ok(frame);
+        }
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + timeout;
+        let result = loop {
+            let frame = match self.read_frame_for(deadline, Some(wanted)) {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => {
+                    break Err(Error::ApiError(api::Error::Timeout {
+                        operation: "unsolicited frame",
+                    }))
+                }
+                Err(err) => break Err(err),
+            };
+            if frame.get(3) == Some(&wanted.id()) {
+                break Ok(frame);
+            }
+            self.stash(frame);
+        };
+        self.serial.set_timeout(old_timeout)?;
+        result
+    }
+
+    /// Sends `payload` to `dest` with trace route enabled and collects the Route
+    /// Information packet each hop reports until the Transmit Status arrives
+    pub fn trace_route(
+        &mut self,
+        dest: impl Into<Addr64>,
+        payload: &[u8],
+    ) -> Result<(api::TransmitStatus, Vec<api::RouteInformation>)> {
+        let dest = dest.into();
+        let frame = api::TransmitRequestFrame::builder()
+            .dest(dest)
+            .payload(payload)
+            .trace_route()
+            .build()?;
+        let timeout = self.timeout_policy.timeout_for(frame.id(), Some(dest));
+        let frame_id = self.transmit(frame)?;
+
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + timeout;
+        let mut hops = Vec::new();
+        let result = loop {
+            let frame = match self.read_frame_before(deadline) {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => {
+                    api::FRAME_ID_ALLOCATOR
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .release(frame_id);
+                    break Err(Error::ApiError(api::Error::Timeout {
+                        operation: "Transmit Status",
+                    }));
+                }
+                Err(err) => break Err(err),
+            };
+            if let Ok(hop) = api::RouteInformation::from_frame(frame.clone()) {
+                hops.push(hop);
+                continue;
+            }
+            self.stash(frame);
+            if let Some(status) = self.pending_statuses.remove(&frame_id) {
+                break Ok(status);
+            }
+        };
+        // trace route frames are not retried
+        self.retransmits.remove(&frame_id);
+        self.serial.set_timeout(old_timeout)?;
+        Ok((result?, hops))
+    }
+
+    /// Sends `cmd` to each of `dests` as a Remote AT Command, keeping at most
+    /// `max_in_flight` requests outstanding. Every request gets `timeout` to be
+    /// answered; nodes that miss it are left out of the returned map. Writes,
+    /// where `param` is given, are applied right away.
+    pub fn remote_at_many(
+        &mut self,
+        dests: &[Addr64],
+        cmd: &str,
+        param: Option<&[u8]>,
+        max_in_flight: usize,
+        timeout: Duration,
+    ) -> Result<HashMap<Addr64, api::RemoteAtCommandResponse>> {
+        let max_in_flight = max_in_flight.max(1);
+        let mut queue = dests.iter();
+        let mut in_flight: HashMap<u8, (Addr64, Instant)> = HashMap::new();
+        let mut responses = HashMap::new();
+
+        let old_timeout = self.serial.timeout();
+        let result = loop {
+            let mut sent = Ok(());
+            while in_flight.len() < max_in_flight {
+                let dest = match queue.next() {
+                    Some(dest) => *dest,
+                    None => break,
+                };
+                match self.transmit(api::RemoteAtCommandFrame {
+                    dest_addr: dest,
+                    options: api::RemoteCommandOptions {
+                        apply_changes: param.is_some(),
+                        ..api::RemoteCommandOptions::default()
+                    },
+                    atcmd: cmd,
+                    cmd_param: param,
+                    fire_and_forget: false,
+                }) {
+                    Ok(frame_id) => {
+                        in_flight.insert(frame_id, (dest, Instant::now() + timeout));
+                    }
+                    Err(err) => {
+                        sent = Err(err);
+                        break;
+                    }
+                }
+            }
+            if let Err(err) = sent {
+                break Err(err);
+            }
+            let deadline = match in_flight.values().map(|(_, deadline)| *deadline).min() {
+                Some(deadline) => deadline,
+                None => break Ok(()),
+            };
+
+            let frame = match self.read_frame_before(deadline) {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => {
+                    let now = Instant::now();
+                    in_flight.retain(|frame_id, (_, deadline)| {
+                        let pending = *deadline > now;
+                        if !pending {
+                            api::FRAME_ID_ALLOCATOR
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .release(*frame_id);
+                        }
+                        pending
+                    });
+                    continue;
+                }
+                Err(err) => break Err(err),
+            };
+            let response = match api::RemoteAtCommandResponse::from_frame(frame.clone()) {
+                Ok(response) => response,
+                Err(_) => {
+                    self.stash(frame);
+                    continue;
+                }
+            };
+            if let Some((dest, _)) = in_flight.remove(&response.frame_id) {
+                api::FRAME_ID_ALLOCATOR
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .release(response.frame_id);
+                responses.insert(dest, response);
+            }
+        };
+        let mut allocator = api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        for frame_id in in_flight.keys() {
+            allocator.release(*frame_id);
+        }
+        drop(allocator);
+        self.serial.set_timeout(old_timeout)?;
+        result?;
+        Ok(responses)
+    }
+
+    /// Sends a Remote AT Command and collects every response that arrives within
+    /// the timeout policy's window, paired with the address of the node that sent
+    /// it. Meant for commands sent to `Addr64::BROADCAST`, where `send_frame`
+    /// only returns the first responder.
+    pub fn collect_remote_at(
+        &mut self,
+        frame: api::RemoteAtCommandFrame,
+    ) -> Result<Vec<(Addr64, api::RemoteAtCommandResponse)>> {
+        let packet = frame.gen()?;
+        let frame_id = packet[4];
+        if frame_id == 0 {
+            self.write_frame(&packet[..])?;
+            return Ok(Vec::new());
+        }
+        self.forget_frame_id(frame_id);
+        let window = self
+            .timeout_policy
+            .timeout_for(frame.id(), Some(frame.dest_addr));
+
+        let old_timeout = self.serial.timeout();
+        let deadline = Instant::now() + window;
+        let mut responses = Vec::new();
+        let result = self.write_frame(&packet[..]).and_then(|_| loop {
+            let frame = match self.read_frame_before(deadline) {
+                Ok(frame) => frame,
+                Err(Error::ApiError(api::Error::Timeout { .. })) => break Ok(()),
+                Err(err) => break Err(err),
+            };
+            match api::RemoteAtCommandResponse::from_frame(frame.clone()) {
+                Ok(response) if response.frame_id == frame_id => {
+                    responses.push((response.dest_addr, response))
+                }
+                _ => self.stash(frame),
+            }
+        });
+        api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release(frame_id);
+        self.serial.set_timeout(old_timeout)?;
+        result?;
+        Ok(responses)
+    }
+
+    /// Writes `frame` and waits for its response. With a retry policy set, a
+    /// frame whose Transmit Status reports a transient failure is sent again,
+    /// and `Error::DeliveryFailed` is returned once the retries are used up.
+    pub fn send_frame<T: api::TransmitApiFrame>(
+        &mut self,
+        frame: T,
+    ) -> Result<Box<dyn api::RecieveApiFrame>> {
+        let packet = frame.gen()?; // creats bytes mut
+        self.send_packet(&frame, packet, None)
+    }
+
+    /// Like `send_frame`, but waits up to `timeout` for the response instead
+    /// of what the timeout policy gives for the frame
+    pub fn send_frame_with_timeout<T: api::TransmitApiFrame>(
+        &mut self,
+        frame: T,
+        timeout: Duration,
+    ) -> Result<Box<dyn api::RecieveApiFrame>> {
+        let packet = frame.gen()?;
+        self.send_packet(&frame, packet, Some(timeout))
+    }
+
+    /// Like `send_frame`, but sends the frame with `frame_id` rather than an
+    /// allocated one. With id 0 the radio sends no response and `NullRecieve`
+    /// is returned right after writing the frame.
+    pub fn send_frame_with_id<T: api::TransmitApiFrame>(
+        &mut self,
+        frame: T,
+        frame_id: u8,
+    ) -> Result<Box<dyn api::RecieveApiFrame>> {
+        let in_flight = api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_in_flight(frame_id);
+        if in_flight {
+            return Err(Error::ApiError(api::Error::FrameError(format!(
+                "Frame id {} is already awaiting a response",
+                frame_id
+            ))));
+        }
+        let packet = frame.gen_with_frame_id(frame_id)?;
+        self.send_packet(&frame, packet, None)
+    }
+
+    fn send_packet<T: api::TransmitApiFrame>(
+        &mut self,
+        frame: &T,
+        packet: BytesMut,
+        timeout: Option<Duration>,
+    ) -> Result<Box<dyn api::RecieveApiFrame>> {
+        let frame_id = packet[4];
+        if let Err(err) = self.check_payload(frame.id(), &packet) {
+            release_frame_id(frame_id);
+            return Err(err);
+        }
+        if frame_id == 0 {
+            // frame id 0 suppresses the response, so there is nothing to wait for
+            self.write_frame(&packet[..])?;
+            return Ok(Box::new(api::NullRecieve));
+        }
+
+        api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .reserve(frame_id);
+        self.forget_frame_id(frame_id);
+        let mut retry = 0;
+        let response = loop {
+            let response = self.transact(frame, &packet, timeout);
+            let failed = response
+                .as_ref()
+                .ok()
+                .and_then(|response| response.downcast_ref::<api::TransmitStatus>())
+                .filter(|status| status.is_retryable())
+                .map(|status| status.delivery());
+            let (policy, status) = match (self.retry_policy, failed) {
+                (Some(policy), Some(status)) => (policy, status),
+                _ => break response,
+            };
+            if retry >= policy.retries {
+                break Err(Error::DeliveryFailed {
+                    status,
+                    attempts: retry + 1,
+                });
+            }
+            thread::sleep(policy.backoff(retry));
+            retry += 1;
+        };
+        api::FRAME_ID_ALLOCATOR
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release(frame_id);
+        response
+    }
+
+    fn transact<T: api::TransmitApiFrame>(
+        &mut self,
+        frame: &T,
+        packet: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<Box<dyn api::RecieveApiFrame>> {
+        self.write_frame(packet)?;
+
+        let old_timeout = self.serial.timeout();
+        let timeout = timeout.unwrap_or_else(|| {
+            self.timeout_policy
+                .timeout_for(frame.id(), packet_dest(frame.id(), packet))
+        });
+        let response = self.read_response(frame.id(), packet[4], Instant::now() + timeout);
+        self.serial.set_timeout(old_timeout)?;
+        let response = response?;
+
+        // 0x74: the radio rejected the payload as too large
+        if let Some(status) = response.downcast_ref::<api::TransmitStatus>() {
+            if status.deliver_status == 0x74 {
+                if let (Some(got), Ok(max)) =
+                    (packet_payload_len(frame.id(), packet), self.max_payload())
+                {
+                    return Err(Error::PayloadTooLarge { max, got });
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    /// Reads frames until the response to the frame with `frame_id` arrives,
+    /// unless it already did while waiting for another frame. Frames that do
+    /// not match are kept (see `stash`), except late answers to requests that
+    /// already timed out.
+    fn read_response(
+        &mut self,
+        sent: api::FrameId,
+        frame_id: u8,
+        deadline: Instant,
+    ) -> Result<Box<dyn api::RecieveApiFrame>> {
+        let expected = match response_type(sent) {
+            Some(kind) => kind.id(),
+            None => return Ok(Box::new(api::NullRecieve)),
+        };
+        let frame = match self.pending_responses.remove(&frame_id) {
+            Some(frame) if frame[3] == expected => frame,
+            _ => loop {
+                let frame = self.read_frame_before(deadline)?;
+                if frame.len() > 5 && frame[3] == expected && frame[4] == frame_id {
+                    break frame;
+                }
+                self.stash(frame);
+            },
+        };
+
+        let response: Box<dyn api::RecieveApiFrame> = match sent {
+            api::FrameId::TransmitRequest | api::FrameId::ExplicitAddressing => {
+                Box::new(api::TransmitStatus::from_frame(frame)?)
+            }
+            api::FrameId::AtCommand | api::FrameId::AtCommandQueue => {
+                Box::new(api::AtCommandResponse::from_frame(frame)?)
+            }
+            api::FrameId::SocketCreate
+            | api::FrameId::SocketConnect
+            | api::FrameId::SocketClose => Box::new(api::SocketResponse::from_frame(frame)?),
+            api::FrameId::FileSystemRequest | api::FrameId::RemoteFileSystemRequest => {
+                Box::new(api::FileSystemResponse::from_frame(frame)?)
+            }
+            api::FrameId::DeviceResponse => Box::new(api::DeviceResponseStatus::from_frame(frame)?),
+            api::FrameId::RegisterJoiningDevice => {
+                Box::new(api::RegisterJoiningDeviceStatus::from_frame(frame)?)
+            }
+            api::FrameId::RemoteAtCommand => {
+                let remote = api::RemoteAtCommandResponse::from_frame(frame)?;
+                if remote.status() == api::AtCommandStatus::TxFailure {
+                    return Err(Error::RemoteUnreachable {
+                        addr: remote.dest_addr,
+                    });
+                }
+                Box::new(remote)
+            }
+            api::FrameId::TxRequest64
+            | api::FrameId::TxRequest16
+            | api::FrameId::SocketSend
+            | api::FrameId::TxIpv4
+            | api::FrameId::TxSms => Box::new(api::TxStatus::from_frame(frame)?),
+            _ => Box::new(api::NullRecieve),
+        };
+        Ok(response)
+    }
+
+    /// Sends `payload` to the loopback cluster of `dest` using explicit addressing and
+    /// waits for the echo, returning the round trip time. The local device must
+    /// be configured with AO=1 so the echo is reported as an Explicit Rx Indicator.
+    pub fn loopback(&mut self, dest: impl Into<Addr64>, payload: &[u8]) -> Result<Duration> {
+        let dest = dest.into();
+        if self.expected_rx_frame()? != api::FrameId::ExplicitRxIndicator {
+            return Err(Error::InvalidMode(
+                "Loopback requires explicit API options (AO=1), see set_api_options".to_string(),
+            ));
+        }
+
+        let frame = api::ExplicitAddressingFrame {
+            dest_addr: dest,
+            source_endpoint: api::DIGI_DATA_ENDPOINT,
+            dest_endpoint: api::DIGI_DATA_ENDPOINT,
+            cluster_id: api::LOOPBACK_CLUSTER_ID,
+            profile_id: api::DIGI_PROFILE_ID,
+            broadcast_radius: 0,
+            options: None,
+            payload,
+            fire_and_forget: false,
+        };
+
+        let start = Instant::now();
+        let status = self.send_frame(frame)?;
+        let status = status
+            .downcast_ref::<api::TransmitStatus>()
+            .ok_or(Error::ApiError(api::Error::DerefError))?;
+        if !status.is_success() {
+            return Err(Error::LoopbackError(format!(
+                "Loopback transmission failed: {}",
+                status.guidance()
+            )));
+        }
+
+        let echo = self.recv_explicit(Duration::from_millis(3000))?;
+        let rtt = start.elapsed();
+
+        if echo.source_addr != dest
+            || echo.cluster_id != api::LOOPBACK_CLUSTER_ID
+            || echo.data[..] != payload[..]
+        {
+            return Err(Error::LoopbackError(
+                "Echoed payload does not match what was sent".to_string(),
+            ));
+        }
+        Ok(rtt)
+    }
+
+    /// Puts the radio to sleep right away (SI). Only has an effect when a
+    /// sleep mode (SM) is configured.
+    pub fn sleep_now(&mut self) -> Result<()> {
+        self.at_command("SI", None)?;
+        Ok(())
+    }
+
+    /// Drives the SLEEP_RQ pin through DTR, for adapters that wire the two
+    /// together. With SM=1 the radio sleeps while the pin is asserted.
+    pub fn set_sleep_pin(&mut self, asleep: bool) -> Result<()> {
+        self.serial.write_data_terminal_ready(asleep)?;
+        Ok(())
+    }
+
+    /// Releases the sleep pin and waits until the radio is awake, which is when
+    /// it asserts CTS or starts sending data (e.g. a Modem Status frame). Pending
+    /// data is left in the serial buffer for the caller.
+    pub fn wake_and_wait(&mut self, timeout: Duration) -> Result<()> {
+        self.set_sleep_pin(false)?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            if self.serial.read_clear_to_send()? || self.serial.bytes_to_read()? > 0 {
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::ApiError(api::Error::Timeout {
+                    operation: "radio to wake up",
+                }));
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// send an AT command and returns the result
+    pub fn atcmd(&mut self, atcmd: &AtCommand) -> Result<()> {
+        self.tx_buf.clear();
+        self.rx_buf.clear();
+
+        if atcmd.command != "+++" {
+            api::validate_at_command(atcmd.command, *atcmd.parameter)?;
+            self.tx_buf.put(&b"AT"[..]);
+            self.tx_buf.put(atcmd.command.as_bytes());
+
+            if let Some(data) = &atcmd.parameter {
+                self.tx_buf.put(&data[..]);
+            }
+            self.tx_buf.put_u8(0x0d);
+        } else {
+            self.tx_buf.put(atcmd.command.as_bytes());
+        }
+
+        // discovery replies keep arriving for up to NT, everything else is immediate
+        let reply_timeout = if atcmd.rcr_len > 1 {
+            DEFAULT_DISCOVERY_TIMEOUT
+        } else {
+            COMMAND_REPLY_TIMEOUT
+        };
+        let old_timeout = self.serial.timeout();
+        self.serial.set_timeout(reply_timeout)?;
+        let result = self.exchange_command(atcmd.rcr_len);
+        self.serial.set_timeout(old_timeout)?;
+        result?;
+
+        if self.rx_buf.is_empty() {
+            return Err(Error::IOError(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "RX buf empty",
+            )));
+        }
+        if self.command_mode_expires.is_some() {
+            self.command_mode_expires = Some(Instant::now() + COMMAND_MODE_TIMEOUT);
+        }
+        Ok(())
+    }
+
+    /// Writes `tx_buf` and reads the reply into `rx_buf` up to the `rcr_len`th
+    /// carriage return
+    fn exchange_command(&mut self, rcr_len: usize) -> Result<()> {
+        self.serial.write_all(&self.tx_buf[..])?;
+        self.last_write = Some(Instant::now());
+        let mut buf: [u8; 1] = [0; 1];
+        let mut cr_counter = 0;
+        loop {
+            if buf[0] == b'\r' {
+                cr_counter += 1;
+                if cr_counter == rcr_len {
+                    break;
+                }
+            }
+            match self.serial.read_exact(&mut buf) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::TimedOut => {
+                    return Err(Error::ApiError(api::Error::Timeout {
+                        operation: "command mode reply",
+                    }))
+                }
+                Err(err) => return Err(Error::IOError(err)),
+            }
+            self.rx_buf.put_u8(buf[0]);
+        }
+        Ok(())
+    }
+
+    /// Enters or leaves command mode. Entering is skipped when the radio is
+    /// still in command mode from an earlier call, and only waits out as much of
+    /// the guard time as has not already passed since the last write.
+    pub fn command_mode(&mut self, mode: bool) -> Result<()> {
+        match mode {
+            true => {
+                if let Some(expires) = self.command_mode_expires {
+                    if Instant::now() < expires {
+                        return Ok(());
+                    }
+                }
+                let guard_time = self.escape_sequence.guard_time;
+                if let Some(last_write) = self.last_write {
+                    let quiet = last_write.elapsed();
+                    if quiet < guard_time {
+                        thread::sleep(guard_time - quiet);
+                    }
+                }
+                self.tx_buf.clear();
+                self.rx_buf.clear();
+                self.tx_buf.put(&[self.escape_sequence.command_char; 3][..]);
+                // the radio only answers once another guard time has passed
+                let old_timeout = self.serial.timeout();
+                self.serial
+                    .set_timeout(guard_time + COMMAND_REPLY_TIMEOUT)?;
+                let result = self.exchange_command(1);
+                self.serial.set_timeout(old_timeout)?;
+                result?;
+                self.expect_ok("the escape sequence")?;
+                self.command_mode_expires = Some(Instant::now() + COMMAND_MODE_TIMEOUT);
             }
             false => {
                 self.atcmd(&AtCommands::CmdMode(false).create())?;
+                self.command_mode_expires = None;
             }
         }
         Ok(())
     }
+
+    /// Reply to the last command sent with `atcmd`, without its carriage return
+    pub fn command_reply(&self) -> &[u8] {
+        self.rx_buf.strip_suffix(b"\r").unwrap_or(&self.rx_buf[..])
+    }
+
+    /// Reads a setting in command mode, entering it first if needed, which
+    /// works whether or not the radio runs API firmware. Numbers are decoded
+    /// with the width `AtCmd` gives the command; replies to commands it does
+    /// not know are returned as text.
+    pub fn cmd_mode_get(&mut self, cmd: &str) -> Result<ParamValue> {
+        let known = api::AtCmd::from_name(cmd);
+        if let Some(known) = known {
+            known.query()?;
+        }
+        self.cmd_mode_exchange(cmd, None)?;
+        let reply = self.command_reply();
+        match known {
+            Some(known) => Ok(ParamValue::decode_command_mode(known.param(), reply)?),
+            None => Ok(ParamValue::Text(ParamValue::decode_string(reply)?)),
+        }
+    }
+
+    /// Changes a setting in command mode, entering it first if needed. The
+    /// radio applies it on `AC` or when command mode is left, and keeps it
+    /// across resets only after `WR`.
+    pub fn cmd_mode_set(&mut self, cmd: &str, value: impl Into<ParamValue>) -> Result<()> {
+        let value = value.into();
+        if let Some(known) = api::AtCmd::from_name(cmd) {
+            // the same checks as for an API frame
+            known.set(&value.encode())?;
+        }
+        self.cmd_mode_exchange(cmd, Some(&value.encode_command_mode()))?;
+        if self.command_reply() != b"OK" {
+            return Err(Error::InvalidMode(format!(
+                "Radio answered AT{} with {:?} in command mode",
+                cmd,
+                String::from_utf8_lossy(self.command_reply())
+            )));
+        }
+        self.note_setting(cmd, &value.encode());
+        Ok(())
+    }
+
+    /// Sends a command in command mode and fails if the radio answers ERROR
+    fn cmd_mode_exchange(&mut self, cmd: &str, param: Option<&[u8]>) -> Result<()> {
+        self.command_mode(true)?;
+        self.atcmd(&AtCommands::AtCmd((cmd, param)).create())?;
+        if self.command_reply() == b"ERROR" {
+            return Err(Error::AtCommandFailed {
+                cmd: cmd.to_string(),
+                status: 0x01,
+            });
+        }
+        Ok(())
+    }
+
+    /// Every frame the radio sends from now on, parsed. Iterating blocks until
+    /// the next frame arrives; modem statuses and other frames kept earlier
+    /// come first.
+    /// Registered handlers are bypassed. Iteration ends after a serial port
+    /// error, which is yielded as the last item.
+    pub fn incoming(&mut self) -> Incoming<'_> {
+        Incoming {
+            old_timeout: self.serial.timeout(),
+            device: self,
+            done: false,
+        }
+    }
+}
+
+/// Iterator over the frames a device receives, see `DigiMeshDevice::incoming`
+pub struct Incoming<'a> {
+    device: &'a mut DigiMeshDevice,
+    old_timeout: Duration,
+    done: bool,
+}
+
+impl Iterator for Incoming<'_> {
+    type Item = Result<api::XBeeFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(status) = self.device.modem_statuses.pop_front() {
+            return Some(Ok(api::XBeeFrame::ModemStatus(status)));
+        }
+        if let Some(frame) = self.device.unsolicited.pop_front() {
+            return Some(api::parse_frame(&frame).map_err(Error::from));
+        }
+        loop {
+            match self
+                .device
+                .read_any_frame_before(Instant::now() + RECV_WAIT)
+            {
+                Ok(frame) => {
+                    let parsed = match api::parse_frame(&frame) {
+                        Ok(parsed) => parsed,
+                        Err(err) => return Some(Err(err.into())),
+                    };
+                    if let api::XBeeFrame::TransmitStatus(ref status) = parsed {
+                        api::FRAME_ID_ALLOCATOR
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .release(status.frame_id);
+                        self.device.retransmits.remove(&status.frame_id);
+                    }
+                    return Some(Ok(parsed));
+                }
+                Err(Error::ApiError(api::Error::Timeout { .. })) => continue,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Incoming<'_> {
+    fn drop(&mut self) {
+        let _ = self.device.serial.set_timeout(self.old_timeout);
+    }
+}
+
+/// Parses the command data of a single ND response:
+/// MY(2) SH(4) SL(4) NI(null terminated) PARENT(2) TYPE(1) STATUS(1)
+/// PROFILE(2) MANUFACTURER(2), optionally followed by DD(4) and RSSI(1)
+/// depending on the NO setting. When NO is unknown the trailer length is
+/// used to guess whether an RSSI byte is present.
+pub(crate) fn parse_discovery_response(
+    data: &[u8],
+    options: Option<u8>,
+) -> Option<RemoteDigiMeshDevice> {
+    if data.len() < 11 {
+        return None;
+    }
+    let addr = Addr64::from_be_bytes(<[u8; 8]>::try_from(&data[2..10]).ok()?);
+    let ni_len = data[10..].iter().position(|b| *b == 0)?;
+    let trailer = &data[10 + ni_len + 1..];
+
+    let rssi = match options {
+        Some(no) if no & ND_OPTION_APPEND_RSSI != 0 => trailer.last().copied(),
+        Some(_) => None,
+        // only RSSI (9) or DD + RSSI (13) end with a signal strength byte
+        None => match trailer.len() {
+            9 | 13 => trailer.last().copied(),
+            _ => None,
+        },
+    };
+
+    Some(RemoteDigiMeshDevice {
+        addr_64bit: addr,
+        node_id: data[10..10 + ni_len].to_vec(),
+        firmware_version: None,
+        hardware_version: None,
+        rssi,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeouts_scale_with_hops_and_broadcast() {
+        let policy = TimeoutPolicy::default();
+        assert_eq!(
+            policy.timeout_for(api::FrameId::AtCommand, None),
+            Duration::from_millis(100)
+        );
+        assert_eq!(
+            policy.timeout_for(
+                api::FrameId::RemoteAtCommand,
+                Some(Addr64(0x0013a200_40abcdef))
+            ),
+            Duration::from_millis(500 + 7 * 200)
+        );
+        assert_eq!(
+            policy.timeout_for(api::FrameId::TransmitRequest, Some(Addr64::BROADCAST)),
+            Duration::from_millis(500 + 7 * 200 * 4)
+        );
+
+        let sleeping = TimeoutPolicy {
+            sleep_period: Some(Duration::from_secs(2)),
+            ..policy
+        };
+        assert_eq!(
+            sleeping.timeout_for(api::FrameId::RemoteAtCommand, Some(Addr64(1))),
+            Duration::from_millis(2000 + 500 + 7 * 200)
+        );
+    }
+
+    #[test]
+    fn payload_length_of_data_frames() {
+        let frame = api::TransmitRequestFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            dest_addr_16: None,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"hello",
+            fire_and_forget: true,
+        };
+        let packet = frame.gen().unwrap();
+        assert_eq!(
+            packet_payload_len(api::FrameId::TransmitRequest, &packet),
+            Some(5)
+        );
+
+        let frame = api::ExplicitAddressingFrame {
+            dest_addr: Addr64(0x0013a200_40abcdef),
+            source_endpoint: api::DIGI_DATA_ENDPOINT,
+            dest_endpoint: api::DIGI_DATA_ENDPOINT,
+            cluster_id: api::LOOPBACK_CLUSTER_ID,
+            profile_id: api::DIGI_PROFILE_ID,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"hello",
+            fire_and_forget: true,
+        };
+        let packet = frame.gen().unwrap();
+        assert_eq!(
+            packet_payload_len(api::FrameId::ExplicitAddressing, &packet),
+            Some(5)
+        );
+        assert_eq!(packet_payload_len(api::FrameId::AtCommand, &packet), None);
+    }
+
+    #[test]
+    fn module_type_from_hardware_version() {
+        assert_eq!(
+            ModuleType::from_hardware_version(0x2347),
+            ModuleType::XBeePro900Hp
+        );
+        assert_eq!(ModuleType::from_hardware_version(0x4247), ModuleType::XBee3);
+        assert_eq!(
+            ModuleType::from_hardware_version(0x1e44),
+            ModuleType::Other(0x1e)
+        );
+    }
+
+    #[test]
+    fn rate_limit_intervals() {
+        let about = |got: Duration, millis: u64| {
+            let want = Duration::from_millis(millis);
+            assert!(
+                got.abs_diff(want) < Duration::from_micros(1),
+                "{:?} != {:?}",
+                got,
+                want
+            );
+        };
+        assert_eq!(
+            RateLimit::FramesPerSecond(4).interval(100),
+            Duration::from_millis(250)
+        );
+        about(RateLimit::BytesPerSecond(1000).interval(50), 50);
+        // 10 bytes take 8 ms at 10 kbps, at 10% duty cycle that is 80 ms
+        let duty = RateLimit::DutyCycle {
+            percent: 10,
+            air_rate_bps: 10_000,
+        };
+        about(duty.interval(10), 80);
+    }
+
+    #[test]
+    fn rate_limit_only_paces_rf_frames() {
+        let net = crate::sim::SimNetwork::new();
+        net.add_radio(1, "LOCAL");
+        net.add_radio(2, "REMOTE");
+        net.link(1, 2);
+        let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+            .rate_limit(RateLimit::FramesPerSecond(2))
+            .build_with_port(net.port(1).unwrap())
+            .unwrap();
+
+        let started = Instant::now();
+        for _ in 0..4 {
+            device.at_query("NI").unwrap();
+        }
+        assert!(started.elapsed() < Duration::from_millis(400));
+
+        let started = Instant::now();
+        for _ in 0..2 {
+            let frame = api::TransmitRequestFrame::builder()
+                .dest(2)
+                .payload(b"paced")
+                .build()
+                .unwrap();
+            device.send_frame(frame).unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retry_backoff_doubles_up_to_the_limit() {
+        let policy = RetryPolicy {
+            retries: 5,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        let waits: Vec<u64> = (0..5)
+            .map(|retry| policy.backoff(retry).as_millis() as u64)
+            .collect();
+        assert_eq!(waits, [100, 200, 400, 500, 500]);
+        assert_eq!(policy.backoff(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn tx_queue_pops_by_priority() {
+        let mut queue = TxQueue::default();
+        queue.push(Priority::Bulk, BytesMut::from(&b"bulk"[..]));
+        queue.push(Priority::Telemetry, BytesMut::from(&b"tm1"[..]));
+        queue.push(Priority::Control, BytesMut::from(&b"ctl"[..]));
+        queue.push(Priority::Telemetry, BytesMut::from(&b"tm2"[..]));
+        assert_eq!(queue.len(), 4);
+
+        let order: Vec<BytesMut> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(
+            order,
+            vec![&b"ctl"[..], &b"tm1"[..], &b"tm2"[..], &b"bulk"[..]]
+        );
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn parses_discovery_response_with_rssi() {
+        let mut data = vec![0xff, 0xfe];
+        data.extend_from_slice(&0x0013a200_40abcdefu64.to_be_bytes());
+        data.extend_from_slice(b"NODE\0");
+        data.extend_from_slice(&[0xff, 0xfe, 0x01, 0x00, 0xc1, 0x05, 0x10, 0x1e]);
+        data.push(0x28);
+
+        let node = parse_discovery_response(&data, None).unwrap();
+        assert_eq!(node.addr_64bit, Addr64(0x0013a200_40abcdef));
+        assert_eq!(node.node_id, b"NODE");
+        assert_eq!(node.rssi, Some(0x28));
+        let node = parse_discovery_response(&data, Some(0x04)).unwrap();
+        assert_eq!(node.rssi, Some(0x28));
+
+        data.pop();
+        assert_eq!(parse_discovery_response(&data, None).unwrap().rssi, None);
+        assert_eq!(parse_discovery_response(&data, Some(0)).unwrap().rssi, None);
+        assert!(parse_discovery_response(&data[..8], None).is_none());
+    }
+    #[test]
+    #[allow(deprecated)]
+    fn changing_encryption_rereads_np() {
+        let net = crate::sim::SimNetwork::new();
+        net.add_radio(1, "LOCAL");
+        let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+            .build_with_port(net.port(1).unwrap())
+            .unwrap();
+        assert_eq!(device.max_payload().unwrap(), 0x49);
+
+        net.set_param(1, "NP", &[0x42]);
+        device.at_command("EE", Some(&[0x01])).unwrap();
+        assert_eq!(device.max_payload().unwrap(), 0x42);
+        assert_eq!(device.addr_64bit, Some(1));
+        assert_eq!(device.node_id.as_deref(), Some(&b"LOCAL"[..]));
+    }
+
+    #[test]
+    fn statuses_read_elsewhere_drop_the_kept_frame() {
+        let net = crate::sim::SimNetwork::new();
+        net.add_radio(1, "LOCAL");
+        net.add_radio(2, "REMOTE");
+        net.link(1, 2);
+        let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+            .retry_policy(RetryPolicy::default())
+            .build_with_port(net.port(1).unwrap())
+            .unwrap();
+        let frame = || {
+            api::TransmitRequestFrame::builder()
+                .dest(2)
+                .payload(b"hello")
+                .build()
+                .unwrap()
+        };
+
+        let frame_id = device.transmit(frame()).unwrap();
+        assert!(device.retransmits.contains_key(&frame_id));
+        device
+            .wait_for_response(frame_id, Duration::from_secs(2))
+            .unwrap();
+        assert!(!device.retransmits.contains_key(&frame_id));
+
+        // a frame kept for an id that is then used by `send_frame_with_id`
+        let stale = frame().gen_with_frame_id(0xfe).unwrap();
+        device.retransmits.insert(0xfe, (stale, 0));
+        device.send_frame_with_id(frame(), 0xfe).unwrap();
+        assert!(!device.retransmits.contains_key(&0xfe));
+    }
 }
@@ -0,0 +1,255 @@
+//!
+//! XBee3 protocol switching
+//!
+//! XBee3 hardware runs DigiMesh, Zigbee or 802.15.4 depending on the firmware
+//! loaded. Switching protocol means uploading a different `.gbl` image through
+//! the serial bootloader, which is entered with `AT%P` and accepts the image
+//! over XMODEM-CRC.
+//!
+
+use crate::api::{self, AtCommandFrame, AtCommandResponse};
+use crate::device::{self, DigiMeshDevice, DigiMeshDeviceBuilder};
+use serialport::*;
+use std::io::Write;
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    DeviceError(#[from] device::Error),
+    #[error(transparent)]
+    SerialError(#[from] serialport::Error),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+    #[error("{0}")]
+    Unsupported(String),
+    #[error("Firmware upload failed: {0}")]
+    TransferFailed(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The bootloader always talks at this rate, regardless of BD
+static BOOTLOADER_BAUD: u32 = 115_200;
+/// How long to wait for the bootloader menu and XMODEM handshakes
+static BOOTLOADER_TIMEOUT: Duration = Duration::from_secs(10);
+static XMODEM_BLOCK_LEN: usize = 128;
+static XMODEM_RETRIES: usize = 10;
+
+const SOH: u8 = 0x01;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CRC_REQUEST: u8 = b'C';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Zigbee,
+    Ieee802154,
+    DigiMesh,
+}
+
+impl Protocol {
+    /// Protocol of an XBee3 firmware, which is encoded in the upper nibble of VR
+    pub fn from_firmware_version(version: u16) -> Option<Self> {
+        match version >> 12 {
+            0x1 => Some(Protocol::Zigbee),
+            0x2 => Some(Protocol::Ieee802154),
+            0x3 => Some(Protocol::DigiMesh),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an HV value belongs to XBee3 hardware (through-hole, SMT or micro)
+pub fn is_xbee3(hardware_version: u16) -> bool {
+    matches!(hardware_version >> 8, 0x41..=0x43)
+}
+
+/// Protocol the local XBee3 radio is currently running
+pub fn detect_protocol(device: &mut DigiMeshDevice) -> Result<Protocol> {
+    let hv = device.get_hardware_version()?;
+    if !is_xbee3(hv) {
+        return Err(Error::Unsupported(format!(
+            "Hardware version 0x{:04x} is not XBee3, protocol cannot be switched",
+            hv
+        )));
+    }
+    let vr = device.get_firmware_version()?;
+    Protocol::from_firmware_version(vr)
+        .ok_or_else(|| Error::Unsupported(format!("Unknown XBee3 firmware version 0x{:04x}", vr)))
+}
+
+/// Uploads `image`, a `.gbl` firmware file for the target protocol, to the XBee3
+/// radio behind `device`, then reopens `port` at `baud` and checks the radio
+/// now runs `target`.
+///
+/// Flashing new firmware resets the radio to defaults, so the device is reopened
+/// with API mode provisioning enabled.
+pub fn switch_protocol(
+    mut device: DigiMeshDevice,
+    port: &str,
+    baud: u32,
+    image: &[u8],
+    target: Protocol,
+) -> Result<DigiMeshDevice> {
+    if detect_protocol(&mut device)? == target {
+        return Ok(device);
+    }
+
+    let response = device.send_frame(AtCommandFrame("%P", None))?;
+    let status = response
+        .downcast_ref::<AtCommandResponse>()
+        .ok_or(device::Error::ApiError(api::Error::DerefError))?
+        .command_status;
+    if status != 0 {
+        return Err(Error::DeviceError(device::Error::AtCommandFailed {
+            cmd: "%P".to_string(),
+            status,
+        }));
+    }
+    // the radio reboots into the bootloader, release the port before reopening it
+    drop(device);
+    thread::sleep(Duration::from_millis(500));
+
+    let settings = SerialPortSettings {
+        baud_rate: BOOTLOADER_BAUD,
+        data_bits: DataBits::Eight,
+        flow_control: FlowControl::None,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        timeout: BOOTLOADER_TIMEOUT,
+    };
+    let mut serial = serialport::open_with_settings(port, &settings)?;
+    serial.write_all(b"\r")?;
+    wait_for(&mut *serial, b"BL >")?;
+    serial.write_all(b"1")?;
+    wait_for(&mut *serial, &[CRC_REQUEST])?;
+    xmodem_send(&mut *serial, image)?;
+    wait_for(&mut *serial, b"BL >")?;
+    serial.write_all(b"2")?;
+    drop(serial);
+    thread::sleep(Duration::from_secs(2));
+
+    let mut device = DigiMeshDeviceBuilder::new(port, baud)
+        .provision_api_mode(true)
+        .build()?;
+    let running = detect_protocol(&mut device)?;
+    if running != target {
+        return Err(Error::TransferFailed(format!(
+            "radio runs {:?} after the upload, expected {:?}",
+            running, target
+        )));
+    }
+    Ok(device)
+}
+
+/// Reads from the bootloader until `pattern` shows up
+fn wait_for(serial: &mut dyn SerialPort, pattern: &[u8]) -> Result<()> {
+    let deadline = Instant::now() + BOOTLOADER_TIMEOUT;
+    let mut seen = Vec::new();
+    let mut byte = [0u8; 1];
+    while Instant::now() < deadline {
+        serial.read_exact(&mut byte)?;
+        seen.push(byte[0]);
+        if seen.ends_with(pattern) {
+            return Ok(());
+        }
+    }
+    Err(Error::TransferFailed(format!(
+        "bootloader did not send {:?}",
+        String::from_utf8_lossy(pattern)
+    )))
+}
+
+fn xmodem_send(serial: &mut dyn SerialPort, image: &[u8]) -> Result<()> {
+    for (i, chunk) in image.chunks(XMODEM_BLOCK_LEN).enumerate() {
+        // block numbers start at 1 and wrap
+        let block = xmodem_block((i + 1) as u8, chunk);
+        send_with_retry(serial, &block)?;
+    }
+    send_with_retry(serial, &[EOT])
+}
+
+fn send_with_retry(serial: &mut dyn SerialPort, packet: &[u8]) -> Result<()> {
+    let mut reply = [0u8; 1];
+    for _ in 0..XMODEM_RETRIES {
+        serial.write_all(packet)?;
+        serial.read_exact(&mut reply)?;
+        match reply[0] {
+            ACK => return Ok(()),
+            NAK => continue,
+            other => {
+                return Err(Error::TransferFailed(format!(
+                    "unexpected reply 0x{:02x} from bootloader",
+                    other
+                )))
+            }
+        }
+    }
+    Err(Error::TransferFailed(format!(
+        "block rejected {} times",
+        XMODEM_RETRIES
+    )))
+}
+
+/// Frames one XMODEM-CRC block, padding short data with 0xff
+fn xmodem_block(number: u8, data: &[u8]) -> Vec<u8> {
+    let mut block = Vec::with_capacity(XMODEM_BLOCK_LEN + 5);
+    block.push(SOH);
+    block.push(number);
+    block.push(!number);
+    block.extend_from_slice(data);
+    block.resize(XMODEM_BLOCK_LEN + 3, 0xff);
+    let crc = crc16_xmodem(&block[3..]);
+    block.extend_from_slice(&crc.to_be_bytes());
+    block
+}
+
+/// CRC-16/XMODEM (polynomial 0x1021, initial value 0)
+fn crc16_xmodem(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_from_firmware_version() {
+        assert_eq!(
+            Protocol::from_firmware_version(0x300b),
+            Some(Protocol::DigiMesh)
+        );
+        assert_eq!(
+            Protocol::from_firmware_version(0x100a),
+            Some(Protocol::Zigbee)
+        );
+        assert_eq!(
+            Protocol::from_firmware_version(0x2003),
+            Some(Protocol::Ieee802154)
+        );
+        assert_eq!(Protocol::from_firmware_version(0x8075), None);
+    }
+
+    #[test]
+    fn xmodem_blocks_are_padded_and_checksummed() {
+        assert_eq!(crc16_xmodem(b"123456789"), 0x31c3);
+
+        let block = xmodem_block(1, b"gbl");
+        assert_eq!(block.len(), 133);
+        assert_eq!(&block[..6], &[SOH, 0x01, 0xfe, b'g', b'b', b'l']);
+        assert!(block[6..131].iter().all(|b| *b == 0xff));
+        assert_eq!(&block[131..], &crc16_xmodem(&block[3..131]).to_be_bytes());
+    }
+}
@@ -1,5 +1,16 @@
+#[macro_use]
+mod macros;
+
 pub mod api;
+#[cfg(feature = "futures")]
+pub mod async_device;
+pub mod constants;
 pub mod device;
+pub mod firmware;
+pub mod network;
+pub mod ports;
+pub mod reliable;
+pub mod sim;
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,72 @@
+//!
+//! Compile time frame construction
+//!
+
+/// Builds a complete API frame (delimiter, length, body and checksum) as a
+/// fixed size `[u8; N]` array at compile time, so frequently used frames need
+/// no runtime allocation. The result can be written with `DigiMeshDevice::send`.
+///
+/// ```
+/// use rustbee::xbee_frame;
+///
+/// // AT query with frame id 1
+/// const NI_QUERY: &[u8] = &xbee_frame!(at 0x01, "NI");
+/// assert_eq!(NI_QUERY, &[0x7e, 0x00, 0x04, 0x08, 0x01, 0x4e, 0x49, 0x5f]);
+///
+/// // AT command with a parameter
+/// const SET_ID: &[u8] = &xbee_frame!(at 0x01, "ID", [0x7f, 0xff]);
+///
+/// // Transmit Request to every node, frame id 0 so no status is sent back
+/// const HELLO: &[u8] = &xbee_frame!(broadcast 0x00, [b'h', b'i']);
+///
+/// // Any frame type followed by its body
+/// const RESET: &[u8] = &xbee_frame!(0x08, [0x01, b'F', b'R']);
+/// ```
+#[macro_export]
+macro_rules! xbee_frame {
+    (at $id:expr, $cmd:literal $(, [$($param:expr),* $(,)?])?) => {{
+        const CMD: &[u8] = $cmd.as_bytes();
+        const _: () = assert!(CMD.len() == 2, "AT command must be two characters");
+        $crate::xbee_frame!(0x08, [$id, CMD[0], CMD[1] $($(, $param)*)?])
+    }};
+    (broadcast $id:expr, [$($payload:expr),* $(,)?]) => {
+        $crate::xbee_frame!(
+            0x10,
+            [
+                $id, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, // broadcast address
+                0xff, 0xfe, // unknown 16-bit address
+                0x00, 0x00 // broadcast radius, options
+                $(, $payload)*
+            ]
+        )
+    };
+    ($frame_type:expr, [$($body:expr),* $(,)?]) => {{
+        const DATA: &[u8] = &[$frame_type $(, $body)*];
+        const FRAME: [u8; DATA.len() + 4] = $crate::api::encode_frame_const(DATA);
+        FRAME
+    }};
+}
+
+/// Checks an AT command code at compile time and yields it as an
+/// `api::AtCode`, so a typo such as `"NID"` fails the build instead of being
+/// sent to a radio that ignores it.
+///
+/// ```
+/// use rustbee::at;
+/// use rustbee::api::AtCode;
+///
+/// const NODE_ID: AtCode = at!("NI");
+/// let frame = at!("ID").set(&[0x7f, 0xff]).unwrap();
+/// assert_eq!(frame.0, "ID");
+/// ```
+///
+/// ```compile_fail
+/// let _ = rustbee::at!("NID");
+/// ```
+#[macro_export]
+macro_rules! at {
+    ($code:literal) => {{
+        const CODE: $crate::api::AtCode = $crate::api::AtCode::new($code);
+        CODE
+    }};
+}
@@ -0,0 +1,400 @@
+//!
+//! Network wide operations
+//!
+//! Helpers that act on every node reachable from the local device rather
+//! than on a single radio.
+//!
+
+use crate::api::{self, Addr64, RemoteAtCommandFrame, RemoteCommandOptions};
+use crate::device::{DigiMeshDevice, RemoteDigiMeshDevice, Result};
+use std::collections::btree_map::{self, BTreeMap};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+/// Requests `remote_at_all` keeps outstanding at once
+static REMOTE_AT_MAX_IN_FLIGHT: usize = 4;
+
+/// Answer of a single node to `Network::remote_at_all`
+#[derive(Debug)]
+pub enum RemoteAtOutcome {
+    /// The node answered, possibly with a non-zero command status
+    Response(api::RemoteAtCommandResponse),
+    /// Nothing came back before the timeout
+    NoResponse,
+}
+
+impl RemoteAtOutcome {
+    /// Whether the node answered with status OK
+    pub fn is_ok(&self) -> bool {
+        match self {
+            RemoteAtOutcome::Response(response) => response.is_ok(),
+            RemoteAtOutcome::NoResponse => false,
+        }
+    }
+
+    /// Command data of a successful answer
+    pub fn data(&self) -> Option<&[u8]> {
+        match self {
+            RemoteAtOutcome::Response(response) if response.is_ok() => {
+                response.command_data.as_ref().map(|data| &data[..])
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Result of `Network::broadcast_and_verify`
+#[derive(Debug, Clone, Default)]
+pub struct QuorumReport {
+    /// Nodes that report the new value
+    pub applied: Vec<Addr64>,
+    /// Nodes that answered with a different value, along with that value
+    pub mismatched: Vec<(Addr64, Vec<u8>)>,
+    /// Nodes that did not answer the verification query or answered with an error
+    pub unacknowledged: Vec<Addr64>,
+}
+
+impl QuorumReport {
+    /// Whether every known node applied the change
+    pub fn is_complete(&self) -> bool {
+        self.mismatched.is_empty() && self.unacknowledged.is_empty()
+    }
+}
+
+pub struct Network<'a> {
+    device: &'a mut DigiMeshDevice,
+}
+
+impl<'a> Network<'a> {
+    pub fn new(device: &'a mut DigiMeshDevice) -> Self {
+        Self { device }
+    }
+
+    /// Queues every `(command, value)` pair on all remote nodes without applying
+    /// them, then broadcasts `AC` so the changes take effect at the same time.
+    /// This keeps nodes from splitting off the network halfway through a
+    /// multi-parameter change such as `ID` + `CH`.
+    ///
+    /// Only remote nodes are changed; the local device is left untouched.
+    pub fn stage_and_apply(&mut self, params: &[(&str, &[u8])]) -> Result<()> {
+        for (cmd, value) in params {
+            self.device.send_frame(RemoteAtCommandFrame {
+                dest_addr: Addr64::BROADCAST,
+                options: RemoteCommandOptions::default(),
+                atcmd: cmd,
+                cmd_param: Some(value),
+                fire_and_forget: true,
+            })?;
+        }
+
+        self.device.send_frame(RemoteAtCommandFrame {
+            dest_addr: Addr64::BROADCAST,
+            options: RemoteCommandOptions {
+                apply_changes: true,
+                ..RemoteCommandOptions::default()
+            },
+            atcmd: "AC",
+            cmd_param: None,
+            fire_and_forget: true,
+        })?;
+        Ok(())
+    }
+
+    /// Broadcasts `cmd` = `value` to all nodes, then queries every known node
+    /// to confirm it took the new value, so a change that only reached part of
+    /// the network does not go unnoticed.
+    ///
+    /// With `include_local` the local device is changed as well before
+    /// verifying. This is required for parameters that decide who can talk to
+    /// whom, such as `ID` or `CH`, since the nodes are otherwise unreachable
+    /// once they applied the change.
+    pub fn broadcast_and_verify(
+        &mut self,
+        cmd: &str,
+        value: &[u8],
+        include_local: bool,
+    ) -> Result<QuorumReport> {
+        self.device.send_frame(RemoteAtCommandFrame {
+            dest_addr: Addr64::BROADCAST,
+            options: RemoteCommandOptions {
+                apply_changes: true,
+                ..RemoteCommandOptions::default()
+            },
+            atcmd: cmd,
+            cmd_param: Some(value),
+            fire_and_forget: true,
+        })?;
+        if include_local {
+            self.device.at_command(cmd, Some(value))?;
+        }
+
+        let mut report = QuorumReport::default();
+        for (addr, outcome) in self.remote_at_all(cmd, None)? {
+            match outcome.data() {
+                Some(data) if same_value(data, value) => report.applied.push(addr),
+                Some(data) => report.mismatched.push((addr, data.to_vec())),
+                None if outcome.is_ok() && value.is_empty() => report.applied.push(addr),
+                None => report.unacknowledged.push(addr),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Sends `cmd` to every known node and collects the answers. Every node in
+    /// the node table gets an entry; nodes that did not answer in time, including
+    /// ones whose answer arrives late, are reported as `NoResponse`.
+    pub fn remote_at_all(
+        &mut self,
+        cmd: &str,
+        param: Option<&[u8]>,
+    ) -> Result<BTreeMap<Addr64, RemoteAtOutcome>> {
+        let addrs: Vec<Addr64> = self.device.nodes().iter().map(|n| n.addr_64bit).collect();
+        // every request gets the wait of the slowest destination
+        let policy = self.device.timeout_policy();
+        let timeout = addrs
+            .iter()
+            .map(|addr| policy.timeout_for(api::FrameId::RemoteAtCommand, Some(*addr)))
+            .max()
+            .unwrap_or_else(|| policy.timeout_for(api::FrameId::RemoteAtCommand, None));
+        let mut responses =
+            self.device
+                .remote_at_many(&addrs, cmd, param, REMOTE_AT_MAX_IN_FLIGHT, timeout)?;
+        Ok(addrs
+            .into_iter()
+            .map(|addr| {
+                let outcome = match responses.remove(&addr) {
+                    Some(response) => RemoteAtOutcome::Response(response),
+                    None => RemoteAtOutcome::NoResponse,
+                };
+                (addr, outcome)
+            })
+            .collect())
+    }
+
+    /// Fills in the firmware (VR) and hardware (HV) versions of known nodes that
+    /// lack them, querying up to `max_in_flight` nodes at a time. A node that does
+    /// not answer within `timeout` keeps `None`. Returns how many nodes were updated.
+    pub fn enrich_nodes(&mut self, max_in_flight: usize, timeout: Duration) -> Result<usize> {
+        let mut updated =
+            self.enrich_version("VR", |n| &mut n.firmware_version, max_in_flight, timeout)?;
+        updated.extend(self.enrich_version(
+            "HV",
+            |n| &mut n.hardware_version,
+            max_in_flight,
+            timeout,
+        )?);
+        Ok(updated.len())
+    }
+
+    /// Queries `cmd` on every node whose `field` is unset and stores the answer,
+    /// returning the addresses that were filled in
+    fn enrich_version(
+        &mut self,
+        cmd: &str,
+        field: fn(&mut RemoteDigiMeshDevice) -> &mut Option<u16>,
+        max_in_flight: usize,
+        timeout: Duration,
+    ) -> Result<BTreeSet<Addr64>> {
+        let missing: Vec<Addr64> = self
+            .device
+            .nodes_mut()
+            .iter_mut()
+            .filter_map(|node| match field(node) {
+                None => Some(node.addr_64bit),
+                Some(_) => None,
+            })
+            .collect();
+        let responses = self
+            .device
+            .remote_at_many(&missing, cmd, None, max_in_flight, timeout)?;
+
+        let mut updated = BTreeSet::new();
+        for (addr, response) in responses {
+            if !response.is_ok() {
+                continue;
+            }
+            let version = match response
+                .command_data
+                .as_ref()
+                .and_then(|data| api::ParamValue::decode_u16(data).ok())
+            {
+                Some(version) => version,
+                None => continue,
+            };
+            if let Some(node) = self.device.nodes_mut().get_by_addr_mut(addr) {
+                *field(node) = Some(version);
+                updated.insert(addr);
+            }
+        }
+        Ok(updated)
+    }
+}
+
+/// Remote nodes known to the local device, keyed by 64-bit address
+#[derive(Debug, Clone, Default)]
+pub struct NodeTable {
+    nodes: BTreeMap<Addr64, RemoteDigiMeshDevice>,
+}
+
+impl NodeTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `node`, replacing any entry with the same address. Returns the
+    /// entry that was replaced.
+    pub fn insert(&mut self, node: RemoteDigiMeshDevice) -> Option<RemoteDigiMeshDevice> {
+        self.nodes.insert(node.addr_64bit, node)
+    }
+
+    /// Merges `node` into the table. A known node keeps its entry, taking the
+    /// NI from the fresher record along with any RSSI or version information it
+    /// carries. Returns true if the node was not known before.
+    pub fn merge(&mut self, node: RemoteDigiMeshDevice) -> bool {
+        match self.nodes.get_mut(&node.addr_64bit) {
+            Some(known) => {
+                known.node_id = node.node_id;
+                if node.rssi.is_some() {
+                    known.rssi = node.rssi;
+                }
+                if node.firmware_version.is_some() {
+                    known.firmware_version = node.firmware_version;
+                }
+                if node.hardware_version.is_some() {
+                    known.hardware_version = node.hardware_version;
+                }
+                false
+            }
+            None => {
+                self.nodes.insert(node.addr_64bit, node);
+                true
+            }
+        }
+    }
+
+    pub fn remove(&mut self, addr: impl Into<Addr64>) -> Option<RemoteDigiMeshDevice> {
+        self.nodes.remove(&addr.into())
+    }
+
+    pub fn get_by_addr(&self, addr: impl Into<Addr64>) -> Option<&RemoteDigiMeshDevice> {
+        self.nodes.get(&addr.into())
+    }
+
+    pub fn get_by_addr_mut(
+        &mut self,
+        addr: impl Into<Addr64>,
+    ) -> Option<&mut RemoteDigiMeshDevice> {
+        self.nodes.get_mut(&addr.into())
+    }
+
+    /// First node whose NI matches `ni` exactly
+    pub fn get_by_ni<N: AsRef<[u8]>>(&self, ni: N) -> Option<&RemoteDigiMeshDevice> {
+        self.nodes
+            .values()
+            .find(|node| node.node_id[..] == *ni.as_ref())
+    }
+
+    pub fn contains(&self, addr: impl Into<Addr64>) -> bool {
+        self.nodes.contains_key(&addr.into())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.nodes.clear()
+    }
+
+    pub fn iter(&self) -> btree_map::Values<'_, Addr64, RemoteDigiMeshDevice> {
+        self.nodes.values()
+    }
+
+    pub fn iter_mut(&mut self) -> btree_map::ValuesMut<'_, Addr64, RemoteDigiMeshDevice> {
+        self.nodes.values_mut()
+    }
+}
+
+impl<'a> IntoIterator for &'a NodeTable {
+    type Item = &'a RemoteDigiMeshDevice;
+    type IntoIter = btree_map::Values<'a, Addr64, RemoteDigiMeshDevice>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Compares an AT parameter as read back against the value it was set to,
+/// ignoring the leading zeros numeric parameters are padded with
+fn same_value(read: &[u8], written: &[u8]) -> bool {
+    let trim = |v: &[u8]| -> usize { v.iter().take_while(|b| **b == 0).count() };
+    read[trim(read)..] == written[trim(written)..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(addr: u64, ni: &str) -> RemoteDigiMeshDevice {
+        RemoteDigiMeshDevice {
+            addr_64bit: Addr64(addr),
+            node_id: ni.as_bytes().to_vec(),
+            firmware_version: None,
+            hardware_version: None,
+            rssi: None,
+        }
+    }
+
+    #[test]
+    fn node_table_lookup_and_update() {
+        let mut table = NodeTable::new();
+        assert!(table.insert(node(2, "B")).is_none());
+        assert!(table.insert(node(1, "A")).is_none());
+        assert_eq!(table.len(), 2);
+
+        assert_eq!(table.get_by_ni("B").unwrap().addr_64bit, Addr64(2));
+        assert!(table.get_by_ni("C").is_none());
+
+        let old = table.insert(node(2, "B2")).unwrap();
+        assert_eq!(old.node_id, b"B");
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get_by_addr(2).unwrap().node_id, b"B2");
+
+        let addrs: Vec<Addr64> = table.iter().map(|n| n.addr_64bit).collect();
+        assert_eq!(addrs, vec![Addr64(1), Addr64(2)]);
+    }
+
+    #[test]
+    fn read_back_values_ignore_padding() {
+        assert!(same_value(&[0x7f, 0xff], b"\x7f\xff"));
+        assert!(same_value(&[0x00, 0x0c], &[0x0c]));
+        assert!(same_value(b"NODE", b"NODE"));
+        assert!(!same_value(&[0x7f, 0xfe], &[0x7f, 0xff]));
+        assert!(!same_value(b"NODE", b"NODE2"));
+    }
+
+    #[test]
+    fn node_table_merge_keeps_freshest() {
+        let mut table = NodeTable::new();
+        let mut first = node(1, "OLD");
+        first.rssi = Some(40);
+        first.firmware_version = Some(0x9002);
+        assert!(table.merge(first));
+
+        assert!(!table.merge(node(1, "NEW")));
+        let merged = table.get_by_addr(1).unwrap();
+        assert_eq!(merged.node_id, b"NEW");
+        assert_eq!(merged.rssi, Some(40));
+        assert_eq!(merged.firmware_version, Some(0x9002));
+
+        let mut again = node(1, "NEW");
+        again.rssi = Some(55);
+        table.merge(again);
+        assert_eq!(table.get_by_addr(1).unwrap().rssi, Some(55));
+        assert_eq!(table.len(), 1);
+    }
+}
@@ -0,0 +1,155 @@
+//!
+//! Serial port discovery
+//!
+//! Lists the serial ports of the host together with their USB identity, so
+//! hosts with several radios attached can tell them apart by serial number
+//! rather than by the order the OS enumerated them in.
+//!
+
+use serialport::{SerialPortInfo, SerialPortType};
+
+static FTDI_VID: u16 = 0x0403;
+/// FT231X, used on Digi's XBee USB interface boards
+static FTDI_FT231X_PID: u16 = 0x6015;
+static SILABS_VID: u16 = 0x10c4;
+static SILABS_CP210X_PID: u16 = 0xea60;
+
+/// How likely it is that a radio sits behind a port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Likelihood {
+    Unlikely,
+    /// USB-serial bridge that is also common on other hardware
+    Possible,
+    /// Adapter identifies as Digi/XBee or is the bridge Digi ships
+    Likely,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortCandidate {
+    pub port_name: String,
+    pub vid: Option<u16>,
+    pub pid: Option<u16>,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub likelihood: Likelihood,
+}
+
+impl PortCandidate {
+    pub fn from_port_info(info: &SerialPortInfo) -> Self {
+        let usb = match info.port_type {
+            SerialPortType::UsbPort(ref usb) => Some(usb),
+            _ => None,
+        };
+        let likelihood = match usb {
+            Some(usb) => {
+                let names_xbee = [&usb.manufacturer, &usb.product]
+                    .iter()
+                    .copied()
+                    .flatten()
+                    .map(|name| name.to_lowercase())
+                    .any(|name| name.contains("digi") || name.contains("xbee"));
+                if names_xbee || (usb.vid == FTDI_VID && usb.pid == FTDI_FT231X_PID) {
+                    Likelihood::Likely
+                } else if usb.vid == FTDI_VID
+                    || (usb.vid == SILABS_VID && usb.pid == SILABS_CP210X_PID)
+                {
+                    Likelihood::Possible
+                } else {
+                    Likelihood::Unlikely
+                }
+            }
+            None => Likelihood::Unlikely,
+        };
+
+        Self {
+            port_name: info.port_name.clone(),
+            vid: usb.map(|usb| usb.vid),
+            pid: usb.map(|usb| usb.pid),
+            serial_number: usb.and_then(|usb| usb.serial_number.clone()),
+            manufacturer: usb.and_then(|usb| usb.manufacturer.clone()),
+            product: usb.and_then(|usb| usb.product.clone()),
+            likelihood,
+        }
+    }
+}
+
+/// Every serial port of the host, most likely radios first. Ports of equal
+/// likelihood are ordered by USB serial number, then by name, so the order is
+/// stable across reboots and replugging.
+pub fn list_xbee_candidates() -> serialport::Result<Vec<PortCandidate>> {
+    let mut candidates: Vec<PortCandidate> = serialport::available_ports()?
+        .iter()
+        .map(PortCandidate::from_port_info)
+        .collect();
+    sort_candidates(&mut candidates);
+    Ok(candidates)
+}
+
+fn sort_candidates(candidates: &mut [PortCandidate]) {
+    candidates.sort_by(|a, b| {
+        b.likelihood
+            .cmp(&a.likelihood)
+            .then_with(|| a.serial_number.cmp(&b.serial_number))
+            .then_with(|| a.port_name.cmp(&b.port_name))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serialport::UsbPortInfo;
+
+    fn usb(name: &str, vid: u16, pid: u16, serial: &str, product: Option<&str>) -> SerialPortInfo {
+        SerialPortInfo {
+            port_name: name.to_string(),
+            port_type: SerialPortType::UsbPort(UsbPortInfo {
+                vid,
+                pid,
+                serial_number: Some(serial.to_string()),
+                manufacturer: None,
+                product: product.map(|p| p.to_string()),
+            }),
+        }
+    }
+
+    #[test]
+    fn ranks_xbee_adapters_first() {
+        let ports = [
+            SerialPortInfo {
+                port_name: "/dev/ttyS0".to_string(),
+                port_type: SerialPortType::Unknown,
+            },
+            usb("/dev/ttyUSB2", 0x10c4, 0xea60, "C", None),
+            usb(
+                "/dev/ttyUSB1",
+                0x2341,
+                0x0043,
+                "A",
+                Some("XBee Grove Dev Board"),
+            ),
+            usb("/dev/ttyUSB0", 0x0403, 0x6015, "B", None),
+            usb("/dev/ttyACM0", 0x2341, 0x0043, "D", Some("Arduino Uno")),
+        ];
+        let mut candidates: Vec<PortCandidate> =
+            ports.iter().map(PortCandidate::from_port_info).collect();
+        sort_candidates(&mut candidates);
+
+        let order: Vec<(&str, Likelihood)> = candidates
+            .iter()
+            .map(|c| (c.port_name.as_str(), c.likelihood))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("/dev/ttyUSB1", Likelihood::Likely),
+                ("/dev/ttyUSB0", Likelihood::Likely),
+                ("/dev/ttyUSB2", Likelihood::Possible),
+                ("/dev/ttyS0", Likelihood::Unlikely),
+                ("/dev/ttyACM0", Likelihood::Unlikely),
+            ]
+        );
+        assert_eq!(candidates[1].vid, Some(0x0403));
+        assert_eq!(candidates[1].serial_number.as_deref(), Some("B"));
+    }
+}
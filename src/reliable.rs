@@ -0,0 +1,420 @@
+//!
+//! Reliable messaging
+//!
+//! Opt-in layer over Transmit Requests for applications that need to know a
+//! message reached the application on the other end. MAC and network ACKs
+//! only tell that a radio took the frame; here every message carries a
+//! sequence number and is acknowledged by the `ReliableChannel` of the remote
+//! host. Messages that are not acknowledged in time are sent again, and
+//! repeats of a message that was already delivered are dropped.
+//!
+//! Every channel picks a random session id that goes along with its sequence
+//! numbers, so a sender that restarts from sequence number 0 is not mistaken
+//! for repeats of its previous run.
+//!
+//! Both ends have to use a `ReliableChannel`, messages are unicast only, and
+//! AO must be 0 so data arrives as Receive Packets.
+//!
+
+use crate::api::{self, Addr64};
+use crate::device::{DigiMeshDevice, Error, Result};
+use rand::Rng;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// First byte of every payload sent by the reliable layer
+const MAGIC: u8 = 0xb5;
+const KIND_DATA: u8 = 0x01;
+const KIND_ACK: u8 = 0x02;
+/// Magic, kind, then the session id and sequence number, big endian
+const HEADER_LEN: usize = 8;
+/// Shortest wait for incoming packets, a zero serial timeout does not block
+const MIN_WAIT: Duration = Duration::from_millis(1);
+
+/// Acknowledgement and duplicate handling of a `ReliableChannel`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReliableConfig {
+    /// Wait for the acknowledgement before the message is sent again
+    pub ack_timeout: Duration,
+    /// Retransmissions after the first attempt
+    pub retries: u32,
+    /// Sequence numbers remembered per sender to recognise repeats
+    pub dedup_window: usize,
+}
+
+impl Default for ReliableConfig {
+    fn default() -> Self {
+        Self {
+            ack_timeout: Duration::from_secs(2),
+            retries: 3,
+            dedup_window: 32,
+        }
+    }
+}
+
+/// Message recieved through a `ReliableChannel`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    pub source: Addr64,
+    pub seq: u16,
+    pub data: bytes::Bytes,
+}
+
+/// Outcome of a message sent through a `ReliableChannel`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeliveryReport {
+    pub dest: Addr64,
+    pub seq: u16,
+    /// Whether the remote host acknowledged the message, as opposed to the
+    /// retries running out
+    pub acknowledged: bool,
+    /// Times the message was transmitted
+    pub attempts: u32,
+}
+
+type DeliveryHandler = Box<dyn FnMut(&DeliveryReport) + Send>;
+
+/// Sequence numbers delivered recently from one sender, oldest first
+#[derive(Default)]
+struct Seen {
+    session: u32,
+    seqs: VecDeque<u16>,
+}
+
+/// Message waiting for its acknowledgement
+struct Pending {
+    dest: Addr64,
+    seq: u16,
+    payload: Vec<u8>,
+    attempts: u32,
+    resend_at: Instant,
+}
+
+/// Sequenced, acknowledged messaging on top of a `DigiMeshDevice`
+pub struct ReliableChannel {
+    device: DigiMeshDevice,
+    config: ReliableConfig,
+    /// Sent along with every message and acknowledgement of this channel
+    session: u32,
+    next_seq: HashMap<Addr64, u16>,
+    pending: Vec<Pending>,
+    seen: HashMap<Addr64, Seen>,
+    /// Messages recieved and not yet returned by `recv`
+    inbox: VecDeque<Message>,
+    /// Receive Packets that did not come from a `ReliableChannel`
+    plain: VecDeque<api::ReceivePacket>,
+    on_delivery: Option<DeliveryHandler>,
+}
+
+impl ReliableChannel {
+    pub fn new(device: DigiMeshDevice) -> Self {
+        Self::with_config(device, ReliableConfig::default())
+    }
+
+    pub fn with_config(device: DigiMeshDevice, config: ReliableConfig) -> Self {
+        Self {
+            device,
+            config,
+            session: rand::thread_rng().gen(),
+            next_seq: HashMap::new(),
+            pending: Vec::new(),
+            seen: HashMap::new(),
+            inbox: VecDeque::new(),
+            plain: VecDeque::new(),
+            on_delivery: None,
+        }
+    }
+
+    pub fn config(&self) -> &ReliableConfig {
+        &self.config
+    }
+
+    /// The underlying device, for anything besides reliable messaging
+    pub fn device(&mut self) -> &mut DigiMeshDevice {
+        &mut self.device
+    }
+
+    pub fn into_inner(self) -> DigiMeshDevice {
+        self.device
+    }
+
+    /// Calls `handler` whenever a message is acknowledged or given up on,
+    /// replacing any previous handler
+    pub fn on_delivery<F>(&mut self, handler: F)
+    where
+        F: FnMut(&DeliveryReport) + Send + 'static,
+    {
+        self.on_delivery = Some(Box::new(handler));
+    }
+
+    /// Messages sent and neither acknowledged nor given up on yet
+    pub fn in_flight(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Sends `data` to `dest` without waiting for the acknowledgement and
+    /// returns its sequence number. The outcome is reported to the
+    /// `on_delivery` handler by later calls to `poll`, `recv` or `send`.
+    pub fn post(&mut self, dest: impl Into<Addr64>, data: &[u8]) -> Result<u16> {
+        let dest = dest.into();
+        let max = self.device.max_payload()?.saturating_sub(HEADER_LEN);
+        if data.len() > max {
+            return Err(Error::PayloadTooLarge {
+                max,
+                got: data.len(),
+            });
+        }
+        let seq = self.next_seq.entry(dest).or_insert(0);
+        let this = *seq;
+        *seq = seq.wrapping_add(1);
+
+        let mut payload = header(KIND_DATA, self.session, this).to_vec();
+        payload.extend_from_slice(data);
+        transmit(&mut self.device, dest, &payload)?;
+        self.pending.push(Pending {
+            dest,
+            seq: this,
+            payload,
+            attempts: 1,
+            resend_at: Instant::now() + self.config.ack_timeout,
+        });
+        Ok(this)
+    }
+
+    /// Sends `data` to `dest` and waits until the remote host acknowledges it.
+    /// Fails with `Error::RemoteUnreachable` once the retries run out.
+    /// Messages recieved in the meantime are kept for `recv`.
+    pub fn send(&mut self, dest: impl Into<Addr64>, data: &[u8]) -> Result<()> {
+        let dest = dest.into();
+        let seq = self.post(dest, data)?;
+        loop {
+            let deadline = Instant::now() + self.config.ack_timeout;
+            let resolved = self.step(deadline)?;
+            if let Some(report) = resolved
+                .iter()
+                .find(|report| report.dest == dest && report.seq == seq)
+            {
+                return if report.acknowledged {
+                    Ok(())
+                } else {
+                    Err(Error::RemoteUnreachable { addr: dest })
+                };
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for the next message, acknowledging it and
+    /// handling retransmissions of sent messages while waiting
+    pub fn recv(&mut self, timeout: Duration) -> Result<Message> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(message) = self.inbox.pop_front() {
+                return Ok(message);
+            }
+            if Instant::now() >= deadline {
+                return Err(Error::ApiError(api::Error::Timeout {
+                    operation: "Reliable message",
+                }));
+            }
+            self.step(deadline)?;
+        }
+    }
+
+    /// Handles acknowledgements and retransmissions for up to `timeout`
+    /// without waiting for a message. Messages recieved are kept for `recv`.
+    pub fn poll(&mut self, timeout: Duration) -> Result<()> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            self.step(deadline)?;
+        }
+        Ok(())
+    }
+
+    /// Receive Packets without the reliable layer's header, oldest first
+    pub fn take_plain(&mut self) -> Vec<api::ReceivePacket> {
+        self.plain.drain(..).collect()
+    }
+
+    /// Sends what is due again, then waits until `deadline` or the next
+    /// retransmission for one packet. Returns the deliveries that were
+    /// resolved, after passing them to the `on_delivery` handler.
+    fn step(&mut self, deadline: Instant) -> Result<Vec<DeliveryReport>> {
+        let mut resolved = self.retransmit_due()?;
+        let wake = self
+            .pending
+            .iter()
+            .map(|pending| pending.resend_at)
+            .fold(deadline, Instant::min);
+        let wait = wake.saturating_duration_since(Instant::now()).max(MIN_WAIT);
+        match self.device.recv_packet(wait) {
+            Ok(packet) => self.handle(packet, &mut resolved)?,
+            Err(Error::ApiError(api::Error::Timeout { .. })) => {}
+            Err(err) => return Err(err),
+        }
+        if let Some(handler) = self.on_delivery.as_mut() {
+            for report in &resolved {
+                handler(report);
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn retransmit_due(&mut self) -> Result<Vec<DeliveryReport>> {
+        let now = Instant::now();
+        let mut resolved = Vec::new();
+        let mut i = 0;
+        while i < self.pending.len() {
+            let pending = &mut self.pending[i];
+            if pending.resend_at > now {
+                i += 1;
+            } else if pending.attempts > self.config.retries {
+                let pending = self.pending.swap_remove(i);
+                resolved.push(DeliveryReport {
+                    dest: pending.dest,
+                    seq: pending.seq,
+                    acknowledged: false,
+                    attempts: pending.attempts,
+                });
+            } else {
+                transmit(&mut self.device, pending.dest, &pending.payload)?;
+                pending.attempts += 1;
+                pending.resend_at = now + self.config.ack_timeout;
+                i += 1;
+            }
+        }
+        Ok(resolved)
+    }
+
+    fn handle(
+        &mut self,
+        packet: api::ReceivePacket,
+        resolved: &mut Vec<DeliveryReport>,
+    ) -> Result<()> {
+        let (kind, session, seq) = match parse_header(&packet.data) {
+            Some(header) => header,
+            None => {
+                self.plain.push_back(packet);
+                return Ok(());
+            }
+        };
+        let source = packet.source_addr;
+        if kind == KIND_ACK {
+            // acknowledgements of a previous run of this channel are ignored
+            if session != self.session {
+                return Ok(());
+            }
+            if let Some(i) = self
+                .pending
+                .iter()
+                .position(|pending| pending.dest == source && pending.seq == seq)
+            {
+                let pending = self.pending.swap_remove(i);
+                resolved.push(DeliveryReport {
+                    dest: pending.dest,
+                    seq: pending.seq,
+                    acknowledged: true,
+                    attempts: pending.attempts,
+                });
+            }
+        } else {
+            // repeats are acknowledged again, the first acknowledgement may be lost
+            transmit(&mut self.device, source, &header(KIND_ACK, session, seq))?;
+            let seen = self.seen.entry(source).or_default();
+            if seen.session != session {
+                // the sender restarted, its sequence numbers start over
+                seen.session = session;
+                seen.seqs.clear();
+            }
+            if remember(&mut seen.seqs, seq, self.config.dedup_window) {
+                self.inbox.push_back(Message {
+                    source,
+                    seq,
+                    data: packet.data.slice(HEADER_LEN..),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Header of a message (`KIND_DATA`) or acknowledgement (`KIND_ACK`) of the
+/// sender's `session`
+fn header(kind: u8, session: u32, seq: u16) -> [u8; HEADER_LEN] {
+    let session = session.to_be_bytes();
+    let seq = seq.to_be_bytes();
+    [
+        MAGIC, kind, session[0], session[1], session[2], session[3], seq[0], seq[1],
+    ]
+}
+
+/// Kind, session id and sequence number of a reliable layer payload. Anything
+/// else, including payloads that merely start with the magic byte, is plain data.
+fn parse_header(data: &[u8]) -> Option<(u8, u32, u16)> {
+    match data {
+        [magic, kind, s0, s1, s2, s3, hi, lo, ..]
+            if *magic == MAGIC && (*kind == KIND_DATA || *kind == KIND_ACK) =>
+        {
+            Some((
+                *kind,
+                u32::from_be_bytes([*s0, *s1, *s2, *s3]),
+                u16::from_be_bytes([*hi, *lo]),
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Adds `seq` to the sequence numbers `seen` from a sender, keeping at most
+/// `window` of them. Returns false if it was seen already.
+fn remember(seen: &mut VecDeque<u16>, seq: u16, window: usize) -> bool {
+    if seen.contains(&seq) {
+        return false;
+    }
+    seen.push_back(seq);
+    while seen.len() > window.max(1) {
+        seen.pop_front();
+    }
+    true
+}
+
+/// Unicasts `payload` without a Transmit Status; delivery is confirmed by the
+/// remote host instead
+fn transmit(device: &mut DigiMeshDevice, dest: Addr64, payload: &[u8]) -> Result<()> {
+    device.send_frame(api::TransmitRequestFrame {
+        dest_addr: dest,
+        dest_addr_16: None,
+        broadcast_radius: 0,
+        options: None,
+        payload,
+        fire_and_forget: true,
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_round_trip() {
+        let payload = header(KIND_DATA, 0xdeadbeef, 0x1234);
+        assert_eq!(
+            parse_header(&payload),
+            Some((KIND_DATA, 0xdeadbeef, 0x1234))
+        );
+        assert_eq!(parse_header(&payload[..HEADER_LEN - 1]), None);
+        assert_eq!(parse_header(b"plain data"), None);
+        assert_eq!(parse_header(&[MAGIC, 0x7f, 0, 0, 0, 0, 0x00, 0x01]), None);
+    }
+
+    #[test]
+    fn repeats_within_the_window_are_dropped() {
+        let mut seen = VecDeque::new();
+        assert!(remember(&mut seen, 1, 2));
+        assert!(!remember(&mut seen, 1, 2));
+        assert!(remember(&mut seen, 2, 2));
+        assert!(remember(&mut seen, 3, 2));
+        // 1 fell out of the window
+        assert!(remember(&mut seen, 1, 2));
+    }
+}
@@ -0,0 +1,950 @@
+//!
+//! Virtual XBee network
+//!
+//! Simulates DigiMesh radios in API mode (AP=1, or AP=2 once set) connected by links with
+//! configurable loss and latency. Every radio exposes a serial port that a
+//! `DigiMeshDevice` can be built on with `DigiMeshDeviceBuilder::build_with_port`,
+//! so applications and tests can run without hardware.
+//!
+//! The radios answer local and remote AT commands, node discovery, transmit
+//! requests and explicit addressing frames, including the loopback cluster.
+//! Frames are routed over the shortest path of at most NH hops.
+//!
+//! Three command characters (CC, `+` by default) written on their own enter
+//! command mode, where `AT` commands take
+//! hexadecimal parameters and `ATCN` leaves again; guard times are not
+//! checked. With AP=0 a radio is in transparent mode: every write is sent to
+//! DH/DL as one packet and recieved data is passed to the host as is. Sleep
+//! and encryption are not simulated.
+//!
+
+use crate::api;
+use crate::constants;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serialport::*;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time a radio takes to answer a local AT command
+static LOCAL_LATENCY: Duration = Duration::from_millis(1);
+/// How often a blocked read checks for new data
+static POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Quality of the link between two radios
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConfig {
+    /// Probability between 0 and 1 that a frame is lost on this hop
+    pub loss: f64,
+    /// One way delay of this hop
+    pub latency: Duration,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            loss: 0.0,
+            latency: Duration::from_millis(5),
+        }
+    }
+}
+
+/// A set of virtual radios and the links between them. Clones are handles to
+/// the same network.
+#[derive(Clone)]
+pub struct SimNetwork {
+    inner: Arc<Mutex<Inner>>,
+}
+
+impl Default for SimNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimNetwork {
+    pub fn new() -> Self {
+        Self::with_seed(0)
+    }
+
+    /// Network whose packet loss is drawn from a generator seeded with `seed`,
+    /// so lossy runs can be reproduced
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                radios: BTreeMap::new(),
+                links: HashMap::new(),
+                rng: StdRng::seed_from_u64(seed),
+            })),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds a radio with factory defaults, replacing any radio with the same address
+    pub fn add_radio(&self, addr: u64, node_id: &str) {
+        self.lock().radios.insert(addr, Radio::new(addr, node_id));
+    }
+
+    /// Connects two radios with a default link
+    pub fn link(&self, a: u64, b: u64) {
+        self.link_with(a, b, LinkConfig::default());
+    }
+
+    pub fn link_with(&self, a: u64, b: u64, config: LinkConfig) {
+        self.lock().links.insert(link_key(a, b), config);
+    }
+
+    pub fn unlink(&self, a: u64, b: u64) {
+        self.lock().links.remove(&link_key(a, b));
+    }
+
+    /// Connects every radio with every other one using default links
+    pub fn link_all(&self) {
+        let mut inner = self.lock();
+        let addrs: Vec<u64> = inner.radios.keys().cloned().collect();
+        for (i, a) in addrs.iter().enumerate() {
+            for b in &addrs[i + 1..] {
+                inner.links.insert(link_key(*a, *b), LinkConfig::default());
+            }
+        }
+    }
+
+    /// Sets an AT parameter of a radio directly, e.g. to configure NH or AO
+    pub fn set_param(&self, addr: u64, cmd: &str, value: &[u8]) {
+        if let Some(radio) = self.lock().radios.get_mut(&addr) {
+            radio.params.insert(at_key(cmd.as_bytes()), value.to_vec());
+        }
+    }
+
+    pub fn param(&self, addr: u64, cmd: &str) -> Option<Vec<u8>> {
+        self.lock()
+            .radios
+            .get(&addr)
+            .and_then(|radio| radio.params.get(&at_key(cmd.as_bytes())).cloned())
+    }
+
+    /// Makes the radio report a modem status to its host, as it would after a
+    /// reset (0x00) or when the network wakes up (0x0b)
+    pub fn modem_status(&self, addr: u64, status: u8) {
+        self.lock()
+            .queue_for_host(addr, Instant::now(), 0x8a, &[status]);
+    }
+
+    /// Makes `from` identify itself to the host of `to`, as a press of its
+    /// commissioning button would
+    pub fn identify(&self, from: u64, to: u64) {
+        let mut inner = self.lock();
+        let node_id = match inner.radios.get(&from) {
+            Some(radio) => radio.params.get(b"NI").cloned().unwrap_or_default(),
+            None => return,
+        };
+        let mut body = from.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe, 0x02, 0xff, 0xfe]);
+        body.extend_from_slice(&from.to_be_bytes());
+        body.extend_from_slice(&node_id);
+        body.push(0x00);
+        // parent, router, pushbutton, Digi profile and manufacturer
+        body.extend_from_slice(&[0xff, 0xfe, 0x01, 0x01, 0xc1, 0x05, 0x10, 0x1e]);
+        inner.queue_for_host(to, Instant::now(), 0x95, &body);
+    }
+
+    /// Makes `bytes` readable by the host of `addr` ahead of any later frame,
+    /// like the boot banner or line noise a real radio can produce
+    pub fn noise(&self, addr: u64, bytes: &[u8]) {
+        if let Some(radio) = self.lock().radios.get_mut(&addr) {
+            radio.ready.extend(bytes.iter());
+        }
+    }
+
+    /// Serial port of the radio with address `addr`
+    pub fn port(&self, addr: u64) -> Option<Box<dyn SerialPort>> {
+        if !self.lock().radios.contains_key(&addr) {
+            return None;
+        }
+        Some(Box::new(SimPort {
+            network: self.clone(),
+            addr,
+            settings: SerialPortSettings::default(),
+        }))
+    }
+}
+
+fn link_key(a: u64, b: u64) -> (u64, u64) {
+    (a.min(b), a.max(b))
+}
+
+fn at_key(cmd: &[u8]) -> [u8; 2] {
+    [cmd[0], cmd[1]]
+}
+
+struct Radio {
+    params: HashMap<[u8; 2], Vec<u8>>,
+    /// Values set with AT Command Queue frames, applied by AC or WR
+    queued: Vec<([u8; 2], Vec<u8>)>,
+    /// Bytes written by the host that do not form a complete frame yet
+    input: Vec<u8>,
+    /// The last byte written was an API mode 2 escape
+    escape_next: bool,
+    /// In command mode, where `input` collects the current command line
+    command_mode: bool,
+    /// Frames for the host, ordered by the time they become readable
+    outbox: Vec<(Instant, Vec<u8>)>,
+    /// Bytes the host can read right now
+    ready: VecDeque<u8>,
+}
+
+impl Radio {
+    fn new(addr: u64, node_id: &str) -> Self {
+        let mut params = HashMap::new();
+        params.insert(*b"SH", ((addr >> 32) as u32).to_be_bytes().to_vec());
+        params.insert(*b"SL", (addr as u32).to_be_bytes().to_vec());
+        params.insert(*b"NI", node_id.as_bytes().to_vec());
+        // XBee3 running DigiMesh
+        params.insert(*b"VR", vec![0x30, 0x0b]);
+        params.insert(*b"HV", vec![0x42, 0x47]);
+        params.insert(*b"NP", vec![0x49]);
+        params.insert(*b"AP", vec![0x01]);
+        params.insert(*b"AO", vec![0x00]);
+        params.insert(*b"NO", vec![0x00]);
+        params.insert(*b"GT", vec![0x03, 0xe8]);
+        params.insert(*b"CC", vec![0x2b]);
+        params.insert(*b"DH", vec![0x00]);
+        params.insert(*b"DL", vec![0x00]);
+        params.insert(*b"NT", vec![0x0a]);
+        params.insert(*b"NH", vec![0x07]);
+        params.insert(*b"MT", vec![0x03]);
+        params.insert(*b"SM", vec![0x00]);
+        params.insert(*b"ID", vec![0x7f, 0xff]);
+        params.insert(*b"CH", vec![0x0c]);
+        Self {
+            params,
+            queued: Vec::new(),
+            input: Vec::new(),
+            escape_next: false,
+            command_mode: false,
+            outbox: Vec::new(),
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Makes `bytes` readable by the host at `at`
+    fn queue(&mut self, at: Instant, bytes: Vec<u8>) {
+        let pos = self.outbox.iter().take_while(|(t, _)| *t <= at).count();
+        self.outbox.insert(pos, (at, bytes));
+    }
+
+    /// Handles command mode input, answering every complete line
+    fn command_input(&mut self, now: Instant, bytes: &[u8]) {
+        self.input.extend_from_slice(bytes);
+        while let Some(end) = self.input.iter().position(|b| *b == b'\r') {
+            let line: Vec<u8> = self.input.drain(..=end).collect();
+            let reply = self.command(&line[..end]);
+            self.queue(now + LOCAL_LATENCY, reply);
+        }
+    }
+
+    /// Answers a command mode line without its carriage return, e.g. `ATAP1`
+    fn command(&mut self, line: &[u8]) -> Vec<u8> {
+        if line.len() < 2 || !line[..2].eq_ignore_ascii_case(b"AT") {
+            return b"ERROR\r".to_vec();
+        }
+        if line.len() < 4 {
+            return b"OK\r".to_vec();
+        }
+        let cmd = at_key(&line[2..4].to_ascii_uppercase());
+        let param = &line[4..];
+        if &cmd == b"CN" {
+            self.command_mode = false;
+            self.input.clear();
+            return b"OK\r".to_vec();
+        }
+        let value = match (&cmd, param.is_empty()) {
+            (_, true) => Vec::new(),
+            (b"NI", false) => param.to_vec(),
+            (_, false) => match std::str::from_utf8(param)
+                .ok()
+                .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            {
+                Some(value) => {
+                    let bytes = value.to_be_bytes();
+                    let skip = bytes.iter().take_while(|b| **b == 0).count().min(7);
+                    bytes[skip..].to_vec()
+                }
+                None => return b"ERROR\r".to_vec(),
+            },
+        };
+        let (status, data) = self.at(cmd, &value, false);
+        let mut reply = match (status, param.is_empty()) {
+            (0, false) => b"OK".to_vec(),
+            (0, true) if &cmd == b"NI" => data,
+            (0, true) if data.is_empty() => b"OK".to_vec(),
+            (0, true) => format!("{:X}", be_u64(&data)).into_bytes(),
+            _ => b"ERROR".to_vec(),
+        };
+        reply.push(b'\r');
+        reply
+    }
+
+    /// Moves frames whose time has come into the readable bytes
+    fn promote(&mut self, now: Instant) {
+        let due = self.outbox.iter().take_while(|(at, _)| *at <= now).count();
+        let escaped = self.param_u64(b"AP") == 2;
+        for (_, frame) in self.outbox.drain(..due) {
+            if escaped {
+                self.ready.extend(api::escape_frame(&frame).iter());
+            } else {
+                self.ready.extend(frame);
+            }
+        }
+    }
+
+    /// Answers an AT command, returning the status and command data. Queued
+    /// values are only stored once AC or WR is sent.
+    fn at(&mut self, cmd: [u8; 2], param: &[u8], queue: bool) -> (u8, Vec<u8>) {
+        if param.is_empty() {
+            return match &cmd {
+                b"AC" | b"WR" => {
+                    for (cmd, value) in self.queued.drain(..) {
+                        self.params.insert(cmd, value);
+                    }
+                    (0, Vec::new())
+                }
+                b"FR" | b"SI" | b"CN" => (0, Vec::new()),
+                _ => match self.params.get(&cmd) {
+                    Some(value) => (0, value.clone()),
+                    None => (2, Vec::new()),
+                },
+            };
+        }
+        match &cmd {
+            b"SH" | b"SL" | b"VR" | b"HV" | b"NP" => (3, Vec::new()),
+            _ if queue => {
+                self.queued.push((cmd, param.to_vec()));
+                (0, Vec::new())
+            }
+            _ => {
+                self.params.insert(cmd, param.to_vec());
+                (0, Vec::new())
+            }
+        }
+    }
+
+    fn param_u64(&self, cmd: &[u8; 2]) -> u64 {
+        self.params
+            .get(cmd)
+            .map(|v| v.iter().fold(0, |acc, b| (acc << 8) | *b as u64))
+            .unwrap_or(0)
+    }
+
+    /// ND response describing this radio
+    fn discovery_record(&self) -> Vec<u8> {
+        let mut data = vec![0xff, 0xfe];
+        data.extend_from_slice(&self.params[b"SH"]);
+        data.extend_from_slice(&self.params[b"SL"]);
+        data.extend_from_slice(&self.params[b"NI"]);
+        data.push(0);
+        // parent, router, status, profile, manufacturer
+        data.extend_from_slice(&[0xff, 0xfe, 0x01, 0x00, 0xc1, 0x05, 0x10, 0x1e]);
+        data
+    }
+}
+
+/// Path a frame took through the network
+struct Route {
+    latency: Duration,
+    delivered: bool,
+}
+
+struct Inner {
+    radios: BTreeMap<u64, Radio>,
+    links: HashMap<(u64, u64), LinkConfig>,
+    rng: StdRng,
+}
+
+impl Inner {
+    fn neighbours(&self, addr: u64) -> impl Iterator<Item = (u64, &LinkConfig)> + '_ {
+        self.links.iter().filter_map(move |((a, b), config)| {
+            if *a == addr {
+                Some((*b, config))
+            } else if *b == addr {
+                Some((*a, config))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Shortest paths from `from` to every radio within its NH hops, as the
+    /// links of each path
+    fn paths(&self, from: u64) -> BTreeMap<u64, Vec<LinkConfig>> {
+        let max_hops = match self.radios.get(&from) {
+            Some(radio) => radio.param_u64(b"NH") as usize,
+            None => return BTreeMap::new(),
+        };
+        let mut paths: BTreeMap<u64, Vec<LinkConfig>> = BTreeMap::new();
+        let mut queue = VecDeque::new();
+        paths.insert(from, Vec::new());
+        queue.push_back(from);
+        while let Some(addr) = queue.pop_front() {
+            let path = paths[&addr].clone();
+            if path.len() >= max_hops {
+                continue;
+            }
+            for (next, config) in self.neighbours(addr) {
+                if !paths.contains_key(&next) && self.radios.contains_key(&next) {
+                    let mut longer = path.clone();
+                    longer.push(*config);
+                    paths.insert(next, longer);
+                    queue.push_back(next);
+                }
+            }
+        }
+        paths.remove(&from);
+        paths
+    }
+
+    /// Sends a frame along `path`, deciding whether it survives every hop
+    fn travel(&mut self, path: &[LinkConfig]) -> Route {
+        let mut route = Route {
+            latency: Duration::from_millis(0),
+            delivered: true,
+        };
+        for hop in path {
+            route.latency += hop.latency;
+            if hop.loss > 0.0 && self.rng.gen::<f64>() < hop.loss {
+                route.delivered = false;
+            }
+        }
+        route
+    }
+
+    fn queue_for_host(&mut self, addr: u64, at: Instant, frame_type: u8, body: &[u8]) {
+        let frame = match api::encode_frame(frame_type, body) {
+            Ok(frame) => frame.to_vec(),
+            Err(_) => return,
+        };
+        if let Some(radio) = self.radios.get_mut(&addr) {
+            radio.queue(at, frame);
+        }
+    }
+
+    /// Hands RF data to the host of `to` in the format its AO setting asks for
+    #[allow(clippy::too_many_arguments)]
+    fn deliver(
+        &mut self,
+        to: u64,
+        from: u64,
+        at: Instant,
+        endpoints: (u8, u8),
+        cluster: u16,
+        profile: u16,
+        options: u8,
+        data: &[u8],
+    ) {
+        let explicit = match self.radios.get_mut(&to) {
+            Some(radio) if radio.param_u64(b"AP") == 0 => {
+                radio.queue(at, data.to_vec());
+                return;
+            }
+            Some(radio) => radio.param_u64(b"AO") != 0,
+            None => return,
+        };
+        let mut body = from.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0xff, 0xfe]);
+        if explicit {
+            body.extend_from_slice(&[endpoints.0, endpoints.1]);
+            body.extend_from_slice(&cluster.to_be_bytes());
+            body.extend_from_slice(&profile.to_be_bytes());
+        }
+        body.push(options);
+        body.extend_from_slice(data);
+        // Explicit Rx Indicator or Receive Packet
+        let frame_type = if explicit { 0x91 } else { 0x90 };
+        self.queue_for_host(to, at, frame_type, &body);
+    }
+
+    /// Accepts bytes written by the host of `addr` and handles complete frames,
+    /// command mode lines or transparent data
+    fn host_write(&mut self, addr: u64, bytes: &[u8]) {
+        let now = Instant::now();
+        let frames = match self.radios.get_mut(&addr) {
+            Some(radio) if radio.command_mode => {
+                radio.command_input(now, bytes);
+                return;
+            }
+            Some(radio)
+                if bytes.len() == 3
+                    && bytes.iter().all(|b| *b as u64 == radio.param_u64(b"CC")) =>
+            {
+                radio.command_mode = true;
+                radio.input.clear();
+                radio.queue(now + LOCAL_LATENCY, b"OK\r".to_vec());
+                return;
+            }
+            Some(radio) if radio.param_u64(b"AP") == 0 => {
+                let dest = (radio.param_u64(b"DH") << 32) | radio.param_u64(b"DL");
+                let endpoints = (api::DIGI_DATA_ENDPOINT, api::DIGI_DATA_ENDPOINT);
+                self.transmit(addr, now, 0, dest, endpoints, 0x0011, bytes);
+                return;
+            }
+            Some(radio) => {
+                if radio.param_u64(b"AP") == 2 {
+                    for byte in bytes {
+                        match (radio.escape_next, *byte) {
+                            (false, 0x7d) => radio.escape_next = true,
+                            (true, byte) => {
+                                radio.input.push(byte ^ 0x20);
+                                radio.escape_next = false;
+                            }
+                            (false, byte) => radio.input.push(byte),
+                        }
+                    }
+                } else {
+                    radio.input.extend_from_slice(bytes);
+                }
+                take_frames(&mut radio.input)
+            }
+            None => return,
+        };
+        for frame in frames {
+            self.handle_frame(addr, &frame);
+        }
+    }
+
+    /// `frame` is the frame type followed by the frame data
+    fn handle_frame(&mut self, addr: u64, frame: &[u8]) {
+        let now = Instant::now();
+        match frame[0] {
+            0x08 | 0x09 if frame.len() >= 4 => self.local_at(addr, now, frame),
+            0x17 if frame.len() >= 15 => self.remote_at(addr, now, frame),
+            0x10 if frame.len() >= 14 => {
+                let dest = be_u64(&frame[2..10]);
+                let endpoints = (api::DIGI_DATA_ENDPOINT, api::DIGI_DATA_ENDPOINT);
+                self.transmit(addr, now, frame[1], dest, endpoints, 0x0011, &frame[14..]);
+            }
+            0x11 if frame.len() >= 20 => {
+                let dest = be_u64(&frame[2..10]);
+                let endpoints = (frame[12], frame[13]);
+                let cluster = u16::from_be_bytes([frame[14], frame[15]]);
+                self.transmit(addr, now, frame[1], dest, endpoints, cluster, &frame[20..]);
+            }
+            _ => {}
+        }
+    }
+
+    fn local_at(&mut self, addr: u64, now: Instant, frame: &[u8]) {
+        let frame_id = frame[1];
+        let cmd = at_key(&frame[2..4]);
+        if &cmd == b"ND" {
+            for (node, path) in self.paths(addr) {
+                let there = self.travel(&path);
+                let back = self.travel(&path);
+                if !there.delivered || !back.delivered {
+                    continue;
+                }
+                let mut body = vec![frame_id, b'N', b'D', 0];
+                body.extend(self.radios[&node].discovery_record());
+                self.queue_for_host(addr, now + there.latency + back.latency, 0x88, &body);
+            }
+            return;
+        }
+
+        let (status, data) = match self.radios.get_mut(&addr) {
+            Some(radio) => radio.at(cmd, &frame[4..], frame[0] == 0x09),
+            None => return,
+        };
+        if frame_id != 0 {
+            let mut body = vec![frame_id, cmd[0], cmd[1], status];
+            body.extend(data);
+            self.queue_for_host(addr, now + LOCAL_LATENCY, 0x88, &body);
+        }
+    }
+
+    fn remote_at(&mut self, addr: u64, now: Instant, frame: &[u8]) {
+        let frame_id = frame[1];
+        let dest = be_u64(&frame[2..10]);
+        let cmd = at_key(&frame[13..15]);
+        let param = &frame[15..];
+        let paths = self.paths(addr);
+        let targets: Vec<u64> = if dest == constants::BROADCAST {
+            paths.keys().cloned().collect()
+        } else {
+            vec![dest]
+        };
+
+        for target in targets {
+            let path = match paths.get(&target) {
+                Some(path) => path.clone(),
+                None => {
+                    if frame_id != 0 {
+                        self.remote_at_response(addr, now, frame_id, target, cmd, 0x04, &[]);
+                    }
+                    continue;
+                }
+            };
+            let there = self.travel(&path);
+            if !there.delivered {
+                if frame_id != 0 && dest != constants::BROADCAST {
+                    self.remote_at_response(addr, now, frame_id, target, cmd, 0x04, &[]);
+                }
+                continue;
+            }
+            let (status, data) = match self.radios.get_mut(&target) {
+                Some(radio) => radio.at(cmd, param, false),
+                None => continue,
+            };
+            let back = self.travel(&path);
+            if frame_id != 0 && back.delivered {
+                let at = now + there.latency + back.latency;
+                self.remote_at_response(addr, at, frame_id, target, cmd, status, &data);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn remote_at_response(
+        &mut self,
+        addr: u64,
+        at: Instant,
+        frame_id: u8,
+        source: u64,
+        cmd: [u8; 2],
+        status: u8,
+        data: &[u8],
+    ) {
+        let mut body = vec![frame_id];
+        body.extend_from_slice(&source.to_be_bytes());
+        body.extend_from_slice(&[0xff, 0xfe, cmd[0], cmd[1], status]);
+        body.extend_from_slice(data);
+        self.queue_for_host(addr, at, 0x97, &body);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn transmit(
+        &mut self,
+        addr: u64,
+        now: Instant,
+        frame_id: u8,
+        dest: u64,
+        endpoints: (u8, u8),
+        cluster: u16,
+        data: &[u8],
+    ) {
+        let paths = self.paths(addr);
+        if dest == constants::BROADCAST {
+            for (target, path) in paths {
+                let route = self.travel(&path);
+                if route.delivered {
+                    let at = now + route.latency;
+                    self.deliver(target, addr, at, endpoints, cluster, 0xc105, 0x02, data);
+                }
+            }
+            if frame_id != 0 {
+                self.queue_for_host(
+                    addr,
+                    now + LOCAL_LATENCY,
+                    0x8b,
+                    &[frame_id, 0xff, 0xfe, 0, 0, 0],
+                );
+            }
+            return;
+        }
+
+        let (status, at) = match paths.get(&dest).cloned() {
+            // route not found
+            None => (0x25, now + LOCAL_LATENCY),
+            Some(path) => {
+                let there = self.travel(&path);
+                let back = self.travel(&path);
+                let round_trip = now + there.latency + back.latency;
+                if !there.delivered {
+                    // network ack failure
+                    (0x21, round_trip)
+                } else {
+                    let arrival = now + there.latency;
+                    let loopback = cluster == api::LOOPBACK_CLUSTER_ID
+                        && endpoints.1 == api::DIGI_DATA_ENDPOINT;
+                    if loopback {
+                        if back.delivered {
+                            // the echo is sent once the request was acknowledged
+                            let echo_at = round_trip + back.latency;
+                            let echo = (endpoints.1, endpoints.0);
+                            self.deliver(addr, dest, echo_at, echo, cluster, 0xc105, 0x01, data);
+                        }
+                    } else {
+                        self.deliver(dest, addr, arrival, endpoints, cluster, 0xc105, 0x01, data);
+                    }
+                    (0x00, round_trip)
+                }
+            }
+        };
+        if frame_id != 0 {
+            self.queue_for_host(addr, at, 0x8b, &[frame_id, 0xff, 0xfe, 0, status, 0]);
+        }
+    }
+}
+
+fn be_u64(data: &[u8]) -> u64 {
+    data.iter().fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+/// Removes every complete frame from `input`, returning the frame type and data
+/// of those with a valid checksum. Bytes outside of a frame are dropped.
+fn take_frames(input: &mut Vec<u8>) -> Vec<Vec<u8>> {
+    let mut frames = Vec::new();
+    loop {
+        match input.iter().position(|b| *b == 0x7e) {
+            Some(start) => {
+                input.drain(..start);
+            }
+            None => {
+                input.clear();
+                break;
+            }
+        }
+        if input.len() < 3 {
+            break;
+        }
+        let len = ((input[1] as usize) << 8) | input[2] as usize;
+        if input.len() < len + 4 {
+            break;
+        }
+        let frame: Vec<u8> = input.drain(..len + 4).collect();
+        let data = &frame[3..3 + len];
+        let sum = data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b));
+        if len > 0 && sum.wrapping_add(frame[3 + len]) == 0xff {
+            frames.push(data.to_vec());
+        }
+    }
+    frames
+}
+
+/// Serial port of a simulated radio
+#[derive(Clone)]
+pub struct SimPort {
+    network: SimNetwork,
+    addr: u64,
+    settings: SerialPortSettings,
+}
+
+impl io::Read for SimPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = Instant::now() + self.settings.timeout;
+        loop {
+            {
+                let mut inner = self.network.lock();
+                let radio = inner
+                    .radios
+                    .get_mut(&self.addr)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "radio removed"))?;
+                radio.promote(Instant::now());
+                if !radio.ready.is_empty() {
+                    let n = buf.len().min(radio.ready.len());
+                    for (dst, src) in buf.iter_mut().zip(radio.ready.drain(..n)) {
+                        *dst = src;
+                    }
+                    return Ok(n);
+                }
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Operation timed out",
+                ));
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+impl io::Write for SimPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.network.lock().host_write(self.addr, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for SimPort {
+    fn name(&self) -> Option<String> {
+        Some(format!("sim:{:016X}", self.addr))
+    }
+
+    fn settings(&self) -> SerialPortSettings {
+        self.settings
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.settings.baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(self.settings.data_bits)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(self.settings.flow_control)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(self.settings.parity)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(self.settings.stop_bits)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.settings.timeout
+    }
+
+    fn set_all(&mut self, settings: &SerialPortSettings) -> serialport::Result<()> {
+        self.settings = *settings;
+        Ok(())
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        self.settings.baud_rate = baud_rate;
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> serialport::Result<()> {
+        self.settings.data_bits = data_bits;
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> serialport::Result<()> {
+        self.settings.flow_control = flow_control;
+        Ok(())
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> serialport::Result<()> {
+        self.settings.parity = parity;
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> serialport::Result<()> {
+        self.settings.stop_bits = stop_bits;
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.settings.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        let mut inner = self.network.lock();
+        Ok(match inner.radios.get_mut(&self.addr) {
+            Some(radio) => {
+                radio.promote(Instant::now());
+                radio.ready.len() as u32
+            }
+            None => 0,
+        })
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        if buffer_to_clear != ClearBuffer::Output {
+            if let Some(radio) = self.network.lock().radios.get_mut(&self.addr) {
+                radio.promote(Instant::now());
+                radio.ready.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Ok(Box::new(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_are_split_and_checked() {
+        let mut input = vec![0x00, 0x01];
+        input.extend(api::encode_frame(0x08, &[0x01, b'N', b'I']).unwrap());
+        let mut corrupt = api::encode_frame(0x08, &[0x02, b'V', b'R'])
+            .unwrap()
+            .to_vec();
+        *corrupt.last_mut().unwrap() ^= 0xff;
+        input.extend(corrupt);
+        let partial = api::encode_frame(0x08, &[0x03, b'H', b'V']).unwrap();
+        input.extend_from_slice(&partial[..4]);
+
+        let frames = take_frames(&mut input);
+        assert_eq!(frames, vec![vec![0x08, 0x01, b'N', b'I']]);
+        assert_eq!(&input[..], &partial[..4]);
+    }
+
+    #[test]
+    fn command_mode_lines() {
+        let mut radio = Radio::new(1, "N");
+        assert_eq!(radio.command(b"AT"), b"OK\r");
+        assert_eq!(radio.command(b"ATNH"), b"7\r");
+        assert_eq!(radio.command(b"atnh0a"), b"OK\r");
+        assert_eq!(radio.params[b"NH"], [0x0a]);
+        assert_eq!(radio.command(b"ATNI"), b"N\r");
+        assert_eq!(radio.command(b"ATNHzz"), b"ERROR\r");
+        assert_eq!(radio.command(b"XYZ"), b"ERROR\r");
+    }
+
+    #[test]
+    fn paths_respect_hop_limit() {
+        let net = SimNetwork::new();
+        for addr in 1..=4 {
+            net.add_radio(addr, "N");
+        }
+        net.link(1, 2);
+        net.link(2, 3);
+        net.link(3, 4);
+        net.set_param(1, "NH", &[2]);
+
+        let paths = net.lock().paths(1);
+        assert_eq!(paths.keys().cloned().collect::<Vec<_>>(), vec![2, 3]);
+        assert_eq!(paths[&3].len(), 2);
+    }
+}
@@ -0,0 +1,152 @@
+//! `AsyncDigiMeshDevice` against a scripted radio on an in-memory stream
+
+#![cfg(feature = "tokio")]
+
+use rustbee::api::{self, FrameParser};
+use rustbee::async_device::AsyncDigiMeshDevice;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio_util::compat::Compat;
+
+static LOCAL: u64 = 0x0013a200_00000001;
+static REMOTE: u64 = 0x0013a200_00000002;
+
+fn at_response(frame_id: u8, cmd: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut body = vec![frame_id];
+    body.extend_from_slice(cmd);
+    body.push(0x00);
+    body.extend_from_slice(data);
+    api::encode_frame(0x88, &body).unwrap().to_vec()
+}
+
+/// Answers AT queries, ND and Transmit Requests like a radio with a single
+/// neighbour, which sends data back before every Transmit Status
+async fn radio(mut port: DuplexStream) {
+    let mut parser = FrameParser::new();
+    let mut chunk = [0u8; 256];
+    loop {
+        let read = match port.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => read,
+        };
+        for frame in parser.feed(&chunk[..read]) {
+            let frame_id = frame[4];
+            let mut replies = Vec::new();
+            match frame[3] {
+                0x08 => match &frame[5..7] {
+                    b"SH" => replies.push(at_response(frame_id, b"SH", &[0x00, 0x13, 0xa2, 0x00])),
+                    b"SL" => replies.push(at_response(frame_id, b"SL", &[0, 0, 0, 1])),
+                    b"NO" => replies.push(at_response(frame_id, b"NO", &[0x00])),
+                    b"NP" => replies.push(at_response(frame_id, b"NP", &[0x00, 0x49])),
+                    b"ND" => {
+                        let mut node = vec![0xff, 0xfe];
+                        node.extend_from_slice(&REMOTE.to_be_bytes());
+                        node.extend_from_slice(b"REMOTE\0");
+                        node.extend_from_slice(&[0xff, 0xfe, 0x01, 0x00, 0xc1, 0x05, 0x10, 0x1e]);
+                        replies.push(at_response(frame_id, b"ND", &node));
+                    }
+                    cmd => replies.push(at_response(frame_id, cmd, &[])),
+                },
+                0x10 => {
+                    let mut rx = REMOTE.to_be_bytes().to_vec();
+                    rx.extend_from_slice(&[0xff, 0xfe, 0x01]);
+                    rx.extend_from_slice(b"pong");
+                    replies.push(api::encode_frame(0x90, &rx).unwrap().to_vec());
+                    let status = [frame_id, 0xff, 0xfe, 0x00, 0x00, 0x00];
+                    replies.push(api::encode_frame(0x8b, &status).unwrap().to_vec());
+                }
+                _ => {}
+            }
+            for reply in replies {
+                port.write_all(&reply).await.unwrap();
+            }
+        }
+    }
+}
+
+fn connect() -> AsyncDigiMeshDevice<Compat<DuplexStream>> {
+    let (host, radio_end) = tokio::io::duplex(1024);
+    tokio::spawn(radio(radio_end));
+    AsyncDigiMeshDevice::from_tokio(host)
+}
+
+#[tokio::test]
+async fn send_frame_returns_the_response() {
+    let mut device = connect();
+    let response = device
+        .send_frame(api::AtCommandFrame("SL", None))
+        .await
+        .unwrap();
+    match response {
+        Some(api::XBeeFrame::AtCommandResponse(response)) => {
+            assert_eq!(response.command_data.as_deref(), Some(&[0, 0, 0, 1][..]))
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let silent = api::TransmitRequestFrame::builder()
+        .dest(REMOTE)
+        .payload(b"ping")
+        .fire_and_forget()
+        .build()
+        .unwrap();
+    assert!(device.send_frame(silent).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn data_arriving_before_a_status_is_kept_for_recv() {
+    let mut device = connect();
+    let request = api::TransmitRequestFrame::builder()
+        .dest(REMOTE)
+        .payload(b"ping")
+        .build()
+        .unwrap();
+    match device.send_frame(request).await.unwrap() {
+        Some(api::XBeeFrame::TransmitStatus(status)) => {
+            assert_eq!(status.delivery(), api::DeliveryStatus::Success)
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let packet = device.recv().await.unwrap();
+    assert_eq!(packet.source_addr, api::Addr64(REMOTE));
+    assert_eq!(&packet.data[..], b"pong");
+    assert!(device
+        .recv_timeout(Duration::from_millis(20))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn payloads_are_limited_to_np() {
+    let mut device = connect();
+    assert_eq!(device.max_payload().await.unwrap(), 0x49);
+
+    let data = [0u8; 0x4a];
+    let request = api::TransmitRequestFrame::builder()
+        .dest(REMOTE)
+        .payload(&data)
+        .build()
+        .unwrap();
+    match device.send_frame(request).await {
+        Err(rustbee::device::Error::PayloadTooLarge { max, got }) => {
+            assert_eq!((max, got), (0x49, 0x4a))
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn discovers_nodes() {
+    let mut device = connect();
+    let found = device
+        .discover_nodes(Some(Duration::from_millis(100)))
+        .await
+        .unwrap();
+    assert_eq!(found, 1);
+    assert_eq!(
+        device.nodes().get_by_addr(REMOTE).unwrap().node_id,
+        b"REMOTE"
+    );
+    assert!(device.nodes().get_by_addr(LOCAL).is_none());
+}
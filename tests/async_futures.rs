@@ -0,0 +1,81 @@
+//! `AsyncDigiMeshDevice` on a plain `futures::io` stream, without any runtime
+
+#![cfg(feature = "futures")]
+
+use futures::executor::block_on;
+use futures::io::{AsyncRead, AsyncWrite, Cursor};
+use rustbee::api;
+use rustbee::async_device::AsyncDigiMeshDevice;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+/// Plays back `input` and records everything written
+struct ScriptedPort {
+    input: Cursor<Vec<u8>>,
+    written: Vec<u8>,
+}
+
+impl AsyncRead for ScriptedPort {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.input).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for ScriptedPort {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.written.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[test]
+fn runs_on_any_executor() {
+    let mut rx = 0x0013a200_00000002_u64.to_be_bytes().to_vec();
+    rx.extend_from_slice(&[0xff, 0xfe, 0x01]);
+    rx.extend_from_slice(&[0x7e, 0x11]);
+    let packet = api::encode_frame(0x90, &rx).unwrap();
+    let port = ScriptedPort {
+        input: Cursor::new(api::escape_frame(&packet).to_vec()),
+        written: Vec::new(),
+    };
+    let mut device = AsyncDigiMeshDevice::with_port(port).api_mode(api::ApiMode::Escaped);
+
+    block_on(async {
+        let packet = device
+            .recv_timeout(Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert_eq!(&packet.data[..], &[0x7e, 0x11]);
+
+        let silent = api::TransmitRequestFrame::builder()
+            .broadcast()
+            .payload(&[0x7d])
+            .fire_and_forget()
+            .build()
+            .unwrap();
+        assert!(device.send_frame(silent).await.unwrap().is_none());
+    });
+
+    let written = device.into_inner().written;
+    assert_eq!(
+        &written[written.len() - 3..written.len() - 1],
+        &[0x7d, 0x5d]
+    );
+}
@@ -0,0 +1,885 @@
+//! End to end tests against the virtual network in `rustbee::sim`
+
+use rustbee::api;
+use rustbee::device::{DigiMeshDevice, DigiMeshDeviceBuilder, ReadUntil, RetryPolicy};
+use rustbee::reliable::{ReliableChannel, ReliableConfig};
+use rustbee::sim::{LinkConfig, SimNetwork};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+static LOCAL: u64 = 0x0013a200_00000001;
+static RELAY: u64 = 0x0013a200_00000002;
+static FAR: u64 = 0x0013a200_00000003;
+static ISOLATED: u64 = 0x0013a200_00000004;
+
+/// LOCAL - RELAY - FAR in a line, ISOLATED without links
+fn line_network() -> SimNetwork {
+    let net = SimNetwork::new();
+    net.add_radio(LOCAL, "LOCAL");
+    net.add_radio(RELAY, "RELAY");
+    net.add_radio(FAR, "FAR");
+    net.add_radio(ISOLATED, "ISOLATED");
+    net.link(LOCAL, RELAY);
+    net.link(RELAY, FAR);
+    net
+}
+
+fn connect(net: &SimNetwork, addr: u64) -> DigiMeshDevice {
+    DigiMeshDeviceBuilder::new("sim", 9600)
+        .build_with_port(net.port(addr).unwrap())
+        .unwrap()
+}
+
+#[test]
+fn reads_local_device_info() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let info = device.info().unwrap();
+    assert_eq!(info.addr_64, api::Addr64(LOCAL));
+    assert_eq!(info.node_id, b"LOCAL");
+    assert_eq!(info.max_payload, Some(0x49));
+}
+
+#[test]
+fn discovers_nodes_over_multiple_hops() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let found = device
+        .discover_nodes(Some(Duration::from_millis(300)))
+        .unwrap();
+    assert_eq!(found, 2);
+    assert_eq!(device.nodes().get_by_addr(FAR).unwrap().node_id, b"FAR");
+    assert!(!device.nodes().contains(ISOLATED));
+}
+
+#[test]
+fn transmit_status_reflects_delivery() {
+    let net = line_network();
+    net.link_with(
+        LOCAL,
+        ISOLATED,
+        LinkConfig {
+            loss: 1.0,
+            ..LinkConfig::default()
+        },
+    );
+    let mut device = connect(&net, LOCAL);
+    let mut far = connect(&net, FAR);
+
+    let status = |device: &mut DigiMeshDevice, dest| {
+        let response = device
+            .send_frame(api::TransmitRequestFrame {
+                dest_addr: dest,
+                dest_addr_16: None,
+                broadcast_radius: 0,
+                options: None,
+                payload: b"ping",
+                fire_and_forget: false,
+            })
+            .unwrap();
+        response
+            .downcast_ref::<api::TransmitStatus>()
+            .unwrap()
+            .deliver_status
+    };
+    assert_eq!(status(&mut device, api::Addr64(FAR)), 0x00);
+    let packet = far.recv_packet(Duration::from_millis(500)).unwrap();
+    assert_eq!(packet.source_addr, api::Addr64(LOCAL));
+    assert_eq!(&packet.data[..], b"ping");
+
+    // every frame over the link to ISOLATED is lost
+    assert_eq!(status(&mut device, api::Addr64(ISOLATED)), 0x21);
+    net.unlink(LOCAL, ISOLATED);
+    assert_eq!(status(&mut device, api::Addr64(ISOLATED)), 0x25);
+}
+
+#[test]
+fn per_call_timeouts_override_the_policy() {
+    let net = line_network();
+    net.link_with(
+        LOCAL,
+        ISOLATED,
+        LinkConfig {
+            latency: Duration::from_millis(200),
+            ..LinkConfig::default()
+        },
+    );
+    let mut device = connect(&net, LOCAL);
+    let frame = api::TransmitRequestFrame {
+        dest_addr: api::Addr64(ISOLATED),
+        dest_addr_16: None,
+        broadcast_radius: 0,
+        options: None,
+        payload: b"slow",
+        fire_and_forget: false,
+    };
+
+    match device.send_frame_with_timeout(frame.clone(), Duration::from_millis(50)) {
+        Err(rustbee::device::Error::ApiError(api::Error::Timeout { .. })) => {}
+        other => panic!("expected a timeout, got {:?}", other.map(|_| ())),
+    }
+    // the late status of the first request must not answer the second
+    let response = device
+        .send_frame_with_timeout(frame, Duration::from_secs(2))
+        .unwrap();
+    let status = response.downcast_ref::<api::TransmitStatus>().unwrap();
+    assert_eq!(status.deliver_status, 0x00);
+}
+
+#[test]
+fn failed_deliveries_are_retried() {
+    let net = line_network();
+    net.link_with(
+        LOCAL,
+        ISOLATED,
+        LinkConfig {
+            loss: 1.0,
+            ..LinkConfig::default()
+        },
+    );
+    let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+        .retry_policy(RetryPolicy {
+            retries: 2,
+            backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(20),
+        })
+        .build_with_port(net.port(LOCAL).unwrap())
+        .unwrap();
+    let frame = |dest| api::TransmitRequestFrame {
+        dest_addr: dest,
+        dest_addr_16: None,
+        broadcast_radius: 0,
+        options: None,
+        payload: b"retry",
+        fire_and_forget: false,
+    };
+
+    match device.send_frame(frame(api::Addr64(ISOLATED))) {
+        Err(rustbee::device::Error::DeliveryFailed { status, attempts }) => {
+            assert_eq!(status, api::DeliveryStatus::NetworkAckFailure);
+            assert_eq!(attempts, 3);
+        }
+        other => panic!("expected a delivery failure, got {:?}", other.map(|_| ())),
+    }
+    let frame_id = device.transmit(frame(api::Addr64(ISOLATED))).unwrap();
+    match device.wait_for_transmit_status(frame_id, Duration::from_secs(2)) {
+        Err(rustbee::device::Error::DeliveryFailed { attempts, .. }) => assert_eq!(attempts, 3),
+        other => panic!("expected a delivery failure, got {:?}", other.map(|_| ())),
+    }
+
+    // successful deliveries are not repeated
+    let mut far = connect(&net, FAR);
+    let frame_id = device.transmit(frame(api::Addr64(FAR))).unwrap();
+    let status = device
+        .wait_for_transmit_status(frame_id, Duration::from_secs(2))
+        .unwrap();
+    assert!(status.is_success());
+    far.recv_packet(Duration::from_millis(500)).unwrap();
+    assert!(far.recv_packet(Duration::from_millis(100)).is_err());
+}
+
+#[test]
+fn lost_transmit_statuses_free_their_frame_id() {
+    let net = line_network();
+    net.link_with(
+        LOCAL,
+        ISOLATED,
+        LinkConfig {
+            latency: Duration::from_secs(1),
+            ..LinkConfig::default()
+        },
+    );
+    let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+        .retry_policy(RetryPolicy::default())
+        .build_with_port(net.port(LOCAL).unwrap())
+        .unwrap();
+    let frame_id = device
+        .transmit(api::TransmitRequestFrame {
+            dest_addr: api::Addr64(ISOLATED),
+            dest_addr_16: None,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"slow",
+            fire_and_forget: false,
+        })
+        .unwrap();
+    assert!(api::FRAME_ID_ALLOCATOR
+        .lock()
+        .unwrap()
+        .is_in_flight(frame_id));
+    match device.wait_for_transmit_status(frame_id, Duration::from_millis(50)) {
+        Err(rustbee::device::Error::ApiError(api::Error::Timeout { .. })) => {}
+        other => panic!("expected a timeout, got {:?}", other.map(|_| ())),
+    }
+    assert!(!api::FRAME_ID_ALLOCATOR
+        .lock()
+        .unwrap()
+        .is_in_flight(frame_id));
+}
+
+#[test]
+fn fire_and_forget_frames_have_no_status_to_wait_for() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let frame_id = device
+        .transmit(api::TransmitRequestFrame {
+            dest_addr: api::Addr64(RELAY),
+            dest_addr_16: None,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"quiet",
+            fire_and_forget: true,
+        })
+        .unwrap();
+    assert_eq!(frame_id, 0);
+
+    let started = std::time::Instant::now();
+    match device.wait_for_transmit_status(frame_id, Duration::from_secs(2)) {
+        Err(rustbee::device::Error::ApiError(api::Error::FrameError(_))) => {}
+        other => panic!("expected a frame error, got {:?}", other.map(|_| ())),
+    }
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn remote_at_reaches_known_nodes() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    device
+        .discover_nodes(Some(Duration::from_millis(300)))
+        .unwrap();
+
+    let outcomes = device.network().remote_at_all("ID", None).unwrap();
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(
+        outcomes[&api::Addr64(RELAY)].data(),
+        Some(&[0x7f, 0xff][..])
+    );
+    assert!(outcomes[&api::Addr64(FAR)].is_ok());
+
+    let report = device
+        .network()
+        .broadcast_and_verify("NI", b"RENAMED", false)
+        .unwrap();
+    assert!(report.is_complete());
+    assert_eq!(net.param(FAR, "NI").unwrap(), b"RENAMED");
+}
+
+#[test]
+fn loopback_echoes_through_relay() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    device.set_api_options(api::ApiOptions::Explicit).unwrap();
+    let rtt = device.loopback(FAR, b"echo").unwrap();
+    assert!(rtt >= Duration::from_millis(20));
+
+    let mut far = connect(&net, FAR);
+    far.set_api_options(api::ApiOptions::Explicit).unwrap();
+    device
+        .send_frame(api::ExplicitAddressingFrame {
+            dest_addr: api::Addr64(FAR),
+            source_endpoint: 0xe8,
+            dest_endpoint: 0xe9,
+            cluster_id: 0x0011,
+            profile_id: api::DIGI_PROFILE_ID,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"data",
+            fire_and_forget: false,
+        })
+        .unwrap();
+    let rx = far.recv_explicit(Duration::from_millis(500)).unwrap();
+    assert_eq!(rx.source_addr, api::Addr64(LOCAL));
+    assert_eq!((rx.dest_endpoint, rx.cluster_id), (0xe9, 0x0011));
+    assert_eq!(&rx.data[..], b"data");
+}
+
+#[test]
+fn modem_statuses_do_not_disturb_responses() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    net.modem_status(LOCAL, 0x00);
+    device.reinitialize().unwrap();
+    assert_eq!(device.info().unwrap().addr_64, api::Addr64(LOCAL));
+    net.modem_status(LOCAL, 0x0b);
+
+    let first = device.take_modem_statuses();
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].state, api::ModemState::HardwareReset);
+    let woke = device
+        .wait_for_modem_status(Duration::from_millis(200))
+        .unwrap();
+    assert_eq!(woke.state, api::ModemState::NetworkWokeUp);
+}
+
+#[test]
+fn queued_settings_apply_together() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    device
+        .apply_settings(&[("NI", b"QUEUED"), ("CH", &[0x0f])])
+        .unwrap();
+    assert_eq!(net.param(LOCAL, "NI").unwrap(), b"QUEUED");
+    assert_eq!(net.param(LOCAL, "CH").unwrap(), vec![0x0f]);
+    assert_eq!(device.info().unwrap().node_id, b"QUEUED");
+
+    // a rejected setting leaves the earlier ones queued, not applied
+    assert!(device
+        .apply_settings(&[("NI", b"NEVER"), ("SH", &[0x00])])
+        .is_err());
+    assert_eq!(net.param(LOCAL, "NI").unwrap(), b"QUEUED");
+}
+
+#[test]
+fn at_response_read_stops_at_frame_end() {
+    use rustbee::api::{RecieveApiFrame, TransmitApiFrame};
+
+    let net = line_network();
+    let mut port = net.port(LOCAL).unwrap();
+    port.set_timeout(Duration::from_secs(2)).unwrap();
+    let request = api::AtCommandFrame("NI", None).gen().unwrap();
+    port.write_all(&request).unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    net.modem_status(LOCAL, 0x0b);
+
+    let started = std::time::Instant::now();
+    let response = api::AtCommandResponse::recieve(port.try_clone().unwrap()).unwrap();
+    assert_eq!(response.command_data.as_deref(), Some(&b"LOCAL"[..]));
+    // the frame following the response is left for the next read
+    let status = api::ModemStatus::recieve(port).unwrap();
+    assert_eq!(status.state, api::ModemState::NetworkWokeUp);
+    assert!(started.elapsed() < Duration::from_secs(1));
+}
+
+#[test]
+fn connecting_detects_the_api_mode() {
+    let net = line_network();
+    net.set_param(LOCAL, "AP", &[0x02]);
+    let mut device = connect(&net, LOCAL);
+    assert_eq!(device.api_mode(), api::ApiMode::Escaped);
+    assert_eq!(device.info().unwrap().addr_64, api::Addr64(LOCAL));
+
+    net.set_param(RELAY, "AP", &[0x00]);
+    match DigiMeshDeviceBuilder::new("sim", 9600).build_with_port(net.port(RELAY).unwrap()) {
+        Err(rustbee::device::Error::InvalidMode(_)) => {}
+        other => panic!("expected an invalid mode, got {:?}", other.map(|_| ())),
+    }
+    let mut relay = DigiMeshDeviceBuilder::new("sim", 9600)
+        .provision_api_mode(true)
+        .build_with_port(net.port(RELAY).unwrap())
+        .unwrap();
+    assert_eq!(net.param(RELAY, "AP").unwrap(), [0x01]);
+    assert!(!relay.is_transparent());
+    assert_eq!(relay.info().unwrap().addr_64, api::Addr64(RELAY));
+}
+
+#[test]
+fn opening_defers_talking_to_the_radio() {
+    let net = line_network();
+    net.set_param(LOCAL, "AP", &[0x02]);
+    let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+        .open_with_port(net.port(LOCAL).unwrap())
+        .unwrap();
+    assert_eq!(device.to_string(), "<unknown> (<unknown address>)");
+    assert_eq!(device.api_mode(), api::ApiMode::Unescaped);
+
+    device.init().unwrap();
+    assert_eq!(device.api_mode(), api::ApiMode::Escaped);
+    assert_eq!(device.info().unwrap().addr_64, api::Addr64(LOCAL));
+
+    net.set_param(LOCAL, "NI", b"RENAMED");
+    assert_ne!(device.info().unwrap().node_id, b"RENAMED");
+    assert_eq!(device.refresh_info().unwrap().node_id, b"RENAMED");
+}
+
+#[test]
+fn escaped_api_mode_round_trips_reserved_bytes() {
+    let net = line_network();
+    net.set_param(LOCAL, "AP", &[0x02]);
+    net.set_param(RELAY, "AP", &[0x02]);
+    // SH of every radio is 0x0013a200, which contains a byte that must be escaped
+    let mut device = DigiMeshDeviceBuilder::new("sim", 9600)
+        .api_mode(api::ApiMode::Escaped)
+        .build_with_port(net.port(LOCAL).unwrap())
+        .unwrap();
+    assert_eq!(device.info().unwrap().addr_64, api::Addr64(LOCAL));
+
+    let mut relay = DigiMeshDeviceBuilder::new("sim", 9600)
+        .api_mode(api::ApiMode::Escaped)
+        .build_with_port(net.port(RELAY).unwrap())
+        .unwrap();
+    let payload = [0x7e, 0x7d, 0x11, 0x13];
+    device
+        .send_frame(api::TransmitRequestFrame {
+            dest_addr: api::Addr64(RELAY),
+            dest_addr_16: None,
+            broadcast_radius: 0,
+            options: None,
+            payload: &payload,
+            fire_and_forget: false,
+        })
+        .unwrap();
+    let packet = relay.recv_packet(Duration::from_millis(500)).unwrap();
+    assert_eq!(&packet.data[..], &payload[..]);
+}
+
+#[test]
+fn junk_before_a_frame_is_skipped() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    net.noise(LOCAL, b"XBee boot\r\n");
+    net.modem_status(LOCAL, 0x00);
+
+    let status = device
+        .wait_for_modem_status(Duration::from_millis(200))
+        .unwrap();
+    assert_eq!(status.state, api::ModemState::HardwareReset);
+    assert_eq!(device.skipped_bytes(), 11);
+}
+
+#[test]
+fn stray_delimiters_in_junk_do_not_swallow_frames() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    // a '~' whose "length" reaches into the modem status behind it
+    net.noise(LOCAL, b"~\x00\x08ok\r\n");
+    net.modem_status(LOCAL, 0x00);
+    let status = device
+        .wait_for_modem_status(Duration::from_millis(200))
+        .unwrap();
+    assert_eq!(status.state, api::ModemState::HardwareReset);
+    assert_eq!(device.skipped_bytes(), 7);
+
+    // and one followed by a length no frame can have
+    net.noise(LOCAL, b"boot ~ done\r\n");
+    net.modem_status(LOCAL, 0x00);
+    device
+        .wait_for_modem_status(Duration::from_millis(200))
+        .unwrap();
+    assert_eq!(device.skipped_bytes(), 7 + 13);
+}
+
+#[test]
+fn responses_are_matched_by_frame_id() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    api::FRAME_ID_ALLOCATOR.lock().unwrap().reserve(0x42);
+
+    // answered, but nobody waits for it
+    device.send_raw(0x08, b"\x42NI").unwrap();
+    net.set_param(LOCAL, "NI", b"RENAMED");
+    device.reinitialize().unwrap();
+    let info = device.info().unwrap();
+    assert_eq!(info.addr_64, api::Addr64(LOCAL));
+    assert_eq!(info.node_id, b"RENAMED");
+
+    api::FRAME_ID_ALLOCATOR.lock().unwrap().release(0x42);
+}
+
+#[test]
+fn caller_chosen_frame_ids() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let request = || api::TransmitRequestFrame {
+        dest_addr: api::Addr64(RELAY),
+        dest_addr_16: None,
+        broadcast_radius: 0,
+        options: None,
+        payload: b"ping",
+        fire_and_forget: false,
+    };
+
+    let silent = device.send_frame_with_id(request(), 0).unwrap();
+    assert_eq!(silent.id(), api::FrameId::Null);
+
+    let response = device.send_frame_with_id(request(), 0xa5).unwrap();
+    let status = response.downcast_ref::<api::TransmitStatus>().unwrap();
+    assert_eq!((status.frame_id, status.deliver_status), (0xa5, 0x00));
+}
+
+#[test]
+fn payloads_are_limited_to_np() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let payload = [0u8; 0x4a];
+
+    let built = device
+        .transmit_request()
+        .unwrap()
+        .dest(RELAY)
+        .payload(&payload)
+        .build();
+    match built {
+        Err(api::Error::PayloadTooLarge { max, got }) => assert_eq!((max, got), (0x49, 0x4a)),
+        other => panic!("unexpected {:?}", other.is_ok()),
+    }
+
+    let sent = device.send_frame(
+        api::TransmitRequestFrame::builder()
+            .dest(RELAY)
+            .payload(&payload)
+            .build()
+            .unwrap(),
+    );
+    match sent {
+        Err(rustbee::device::Error::PayloadTooLarge { max, got }) => {
+            assert_eq!((max, got), (0x49, 0x4a))
+        }
+        other => panic!("unexpected {:?}", other.is_ok()),
+    }
+}
+
+#[test]
+fn handlers_receive_unsolicited_frames() {
+    use std::sync::{Arc, Mutex};
+
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let mut relay = connect(&net, RELAY);
+    let events = Arc::new(Mutex::new(Vec::new()));
+
+    let log = events.clone();
+    device.on_data(move |packet| {
+        log.lock()
+            .unwrap()
+            .push(format!("data {}", String::from_utf8_lossy(&packet.data)))
+    });
+    let log = events.clone();
+    device.on_modem_status(move |status| {
+        log.lock()
+            .unwrap()
+            .push(format!("status {:?}", status.state))
+    });
+    let log = events.clone();
+    device.on_node_identification(move |node| {
+        log.lock()
+            .unwrap()
+            .push(format!("node {}", String::from_utf8_lossy(&node.node_id)))
+    });
+
+    relay
+        .send_frame(
+            api::TransmitRequestFrame::builder()
+                .dest(LOCAL)
+                .payload(b"reading")
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    net.modem_status(LOCAL, 0x0b);
+    net.identify(FAR, LOCAL);
+    device.dispatch_events(Duration::from_millis(300)).unwrap();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["data reading", "status NetworkWokeUp", "node FAR"]
+    );
+    assert!(device.take_modem_statuses().is_empty());
+    assert_eq!(device.nodes().get_by_addr(FAR).unwrap().node_id, b"FAR");
+
+    // a caller waiting for data gets it instead of the handler
+    relay
+        .send_frame(
+            api::TransmitRequestFrame::builder()
+                .dest(LOCAL)
+                .payload(b"direct")
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let packet = device.recv_packet(Duration::from_millis(500)).unwrap();
+    assert_eq!(&packet.data[..], b"direct");
+    assert_eq!(events.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn recv_returns_data_sent_to_the_radio() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let mut far = connect(&net, FAR);
+
+    match device.recv_timeout(Duration::from_millis(50)) {
+        Err(rustbee::device::Error::ApiError(api::Error::Timeout { .. })) => {}
+        other => panic!("unexpected {:?}", other.is_ok()),
+    }
+
+    far.send_frame(
+        api::TransmitRequestFrame::builder()
+            .dest(LOCAL)
+            .payload(b"hello")
+            .build()
+            .unwrap(),
+    )
+    .unwrap();
+    let packet = device.recv().unwrap();
+    assert_eq!(packet.source_addr, api::Addr64(FAR));
+    assert_eq!(&packet.data[..], b"hello");
+}
+
+#[test]
+fn incoming_yields_frames_in_order() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let mut relay = connect(&net, RELAY);
+    net.modem_status(LOCAL, 0x0b);
+    device.reinitialize().unwrap();
+    relay
+        .send_frame(
+            api::TransmitRequestFrame::builder()
+                .dest(LOCAL)
+                .payload(b"first")
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    net.modem_status(LOCAL, 0x00);
+
+    let frames: Vec<api::XBeeFrame> = device.incoming().take(3).map(Result::unwrap).collect();
+    match &frames[..] {
+        [api::XBeeFrame::ModemStatus(woke), api::XBeeFrame::ReceivePacket(packet), api::XBeeFrame::ModemStatus(reset)] =>
+        {
+            assert_eq!(woke.state, api::ModemState::NetworkWokeUp);
+            assert_eq!(&packet.data[..], b"first");
+            assert_eq!(reset.state, api::ModemState::HardwareReset);
+        }
+        _ => panic!("unexpected frames"),
+    }
+}
+
+#[test]
+fn interleaved_frames_reach_their_waiters() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let mut relay = connect(&net, RELAY);
+    relay
+        .send_frame(
+            api::TransmitRequestFrame::builder()
+                .dest(LOCAL)
+                .payload(b"early")
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    let pending = device.transmit(api::AtCommandFrame("NI", None)).unwrap();
+
+    // reads past both the data and the NI response while answering its own queries
+    device.reinitialize().unwrap();
+    assert_eq!(device.info().unwrap().addr_64, api::Addr64(LOCAL));
+
+    match device
+        .wait_for_response(pending, Duration::from_millis(50))
+        .unwrap()
+    {
+        api::XBeeFrame::AtCommandResponse(response) => {
+            assert_eq!(response.command_data.as_deref(), Some(&b"LOCAL"[..]))
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    let packet = device.recv_packet(Duration::from_millis(50)).unwrap();
+    assert_eq!(&packet.data[..], b"early");
+}
+
+#[test]
+fn data_received_during_calls_is_buffered() {
+    use std::sync::{Arc, Mutex};
+
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    let mut relay = connect(&net, RELAY);
+    for payload in &[&b"one"[..], b"two", b"three"] {
+        relay
+            .send_frame(
+                api::TransmitRequestFrame::builder()
+                    .dest(LOCAL)
+                    .payload(payload)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+    }
+
+    // answering this reads past the packets
+    device.reinitialize().unwrap();
+    assert_eq!(device.get_node_id().unwrap(), "LOCAL");
+
+    assert_eq!(&device.recv().unwrap().data[..], b"one");
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    device.on_data(move |packet| log.lock().unwrap().push(packet.data.to_vec()));
+    device.dispatch_events(Duration::from_millis(10)).unwrap();
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![b"two".to_vec(), b"three".to_vec()]
+    );
+    assert!(device.take_unsolicited().is_empty());
+}
+
+#[test]
+fn reliable_messages_survive_a_lossy_link() {
+    let net = SimNetwork::with_seed(7);
+    net.add_radio(LOCAL, "LOCAL");
+    net.add_radio(FAR, "FAR");
+    net.link_with(
+        LOCAL,
+        FAR,
+        LinkConfig {
+            loss: 0.2,
+            ..LinkConfig::default()
+        },
+    );
+    let config = ReliableConfig {
+        ack_timeout: Duration::from_millis(100),
+        retries: 10,
+        ..ReliableConfig::default()
+    };
+    let mut sender = ReliableChannel::with_config(connect(&net, LOCAL), config);
+    let mut receiver = ReliableChannel::with_config(connect(&net, FAR), config);
+
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+    sender.on_delivery(move |report| sink.lock().unwrap().push(*report));
+
+    // the receiver keeps acknowledging until the sender is done, since the
+    // last acknowledgement may be lost as well
+    let done = Arc::new(AtomicBool::new(false));
+    let finished = done.clone();
+    let receiving = std::thread::spawn(move || {
+        let mut messages = Vec::new();
+        while !finished.load(Ordering::SeqCst) {
+            if let Ok(message) = receiver.recv(Duration::from_millis(20)) {
+                assert_eq!(message.source, api::Addr64(LOCAL));
+                messages.push(message.data.to_vec());
+            }
+        }
+        messages
+    });
+
+    let sent: Vec<Vec<u8>> = (0..10u8).map(|i| vec![b'm', i]).collect();
+    for message in &sent {
+        sender.send(FAR, message).unwrap();
+    }
+    done.store(true, Ordering::SeqCst);
+    assert_eq!(receiving.join().unwrap(), sent);
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), sent.len());
+    assert!(reports.iter().all(|report| report.acknowledged));
+    assert_eq!(sender.in_flight(), 0);
+}
+
+#[test]
+fn transparent_mode_round_trip() {
+    let net = line_network();
+    net.set_param(LOCAL, "DH", &[0x00, 0x13, 0xa2, 0x00]);
+    net.set_param(LOCAL, "DL", &[0x00, 0x00, 0x00, 0x02]);
+    let mut device = connect(&net, LOCAL);
+    let mut relay = connect(&net, RELAY);
+    assert!(device.transparent_send(b"too early").is_err());
+
+    device.enter_transparent_mode().unwrap();
+    device.transparent_send(b"hello").unwrap();
+    let packet = relay.recv_packet(Duration::from_millis(500)).unwrap();
+    assert_eq!(packet.source_addr, api::Addr64(LOCAL));
+    assert_eq!(&packet.data[..], b"hello");
+
+    relay
+        .send_frame(api::TransmitRequestFrame {
+            dest_addr: api::Addr64(LOCAL),
+            dest_addr_16: None,
+            broadcast_radius: 0,
+            options: None,
+            payload: b"first line\nsecond",
+            fire_and_forget: false,
+        })
+        .unwrap();
+    let timeout = Duration::from_millis(500);
+    let line = device
+        .transparent_recv(ReadUntil::Delimiter(b'\n'), timeout)
+        .unwrap();
+    assert_eq!(line, b"first line\n");
+    assert!(device
+        .transparent_recv(ReadUntil::Delimiter(b'\n'), Duration::from_millis(50))
+        .is_err());
+    // the partial line was kept
+    let rest = device.transparent_recv(ReadUntil::Len(6), timeout).unwrap();
+    assert_eq!(rest, b"second");
+
+    device.enter_api_mode().unwrap();
+    assert!(!device.is_transparent());
+    assert_eq!(net.param(LOCAL, "AP").unwrap(), [0x01]);
+    assert_eq!(device.get_node_id().unwrap(), "LOCAL");
+}
+
+#[test]
+fn settings_through_command_mode() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    assert_eq!(device.cmd_mode_get("NH").unwrap(), api::ParamValue::U8(7));
+    assert_eq!(device.cmd_mode_get("ID").unwrap().as_u64(), Some(0x7fff));
+    assert_eq!(device.cmd_mode_get("NI").unwrap().as_str(), Some("LOCAL"));
+
+    device.cmd_mode_set("NH", 0x0au8).unwrap();
+    device.cmd_mode_set("NI", "RENAMED").unwrap();
+    assert_eq!(net.param(LOCAL, "NH").unwrap(), [0x0a]);
+    assert_eq!(net.param(LOCAL, "NI").unwrap(), b"RENAMED");
+    match device.cmd_mode_get("ZZ") {
+        Err(rustbee::device::Error::AtCommandFailed { cmd, .. }) => assert_eq!(cmd, "ZZ"),
+        other => panic!("expected a failed command, got {:?}", other),
+    }
+    assert!(device.cmd_mode_set("SH", 1u8).is_err());
+
+    device.command_mode(false).unwrap();
+    assert_eq!(device.command_reply(), b"OK");
+}
+
+#[test]
+fn escape_sequence_follows_gt_and_cc() {
+    let net = line_network();
+    net.set_param(LOCAL, "GT", &[0x64]);
+    net.set_param(LOCAL, "CC", b"-");
+    let mut device = connect(&net, LOCAL);
+    device.load_escape_sequence().unwrap();
+    assert_eq!(device.escape_sequence().command_char, b'-');
+    assert_eq!(
+        device.escape_sequence().guard_time,
+        Duration::from_millis(100)
+    );
+
+    let started = std::time::Instant::now();
+    assert_eq!(device.cmd_mode_get("CH").unwrap().as_u64(), Some(0x0c));
+    assert!(started.elapsed() < Duration::from_millis(800));
+    device.command_mode(false).unwrap();
+}
+
+#[test]
+fn dropping_the_device_leaves_command_mode() {
+    let net = line_network();
+    let mut device = connect(&net, LOCAL);
+    device.command_mode(true).unwrap();
+    drop(device);
+
+    // a radio left in command mode would not answer API frames
+    let mut device = connect(&net, LOCAL);
+    assert_eq!(device.info().unwrap().addr_64, api::Addr64(LOCAL));
+}
+
+#[test]
+fn restarted_senders_are_not_taken_for_repeats() {
+    let net = line_network();
+    let mut sender = ReliableChannel::new(connect(&net, LOCAL));
+    let mut receiver = ReliableChannel::new(connect(&net, RELAY));
+
+    let exchange = |sender: &mut ReliableChannel, receiver: &mut ReliableChannel, data: &[u8]| {
+        assert_eq!(sender.post(RELAY, data).unwrap(), 0);
+        let message = receiver.recv(Duration::from_millis(500)).unwrap();
+        assert_eq!(&message.data[..], data);
+        sender.poll(Duration::from_millis(100)).unwrap();
+        assert_eq!(sender.in_flight(), 0);
+    };
+    exchange(&mut sender, &mut receiver, b"first run");
+
+    // a new channel starts its sequence numbers over
+    let mut sender = ReliableChannel::new(sender.into_inner());
+    exchange(&mut sender, &mut receiver, b"second run");
+}